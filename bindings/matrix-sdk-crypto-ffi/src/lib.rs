@@ -470,6 +470,13 @@ fn collect_sessions(
             backed_up: session.backed_up,
             history_visibility: None,
             algorithm: RustEventEncryptionAlgorithm::MegolmV1AesSha2,
+            forwarding_curve25519_key_chain: session
+                .forwarding_chains
+                .iter()
+                .map(|k| Curve25519PublicKey::from_base64(k))
+                .collect::<Result<_, _>>()?,
+            creation_time: None,
+            forwarded_by_verified_device: false,
         };
 
         let session = matrix_sdk_crypto::olm::InboundGroupSession::from_pickle(pickle)?;
@@ -741,6 +748,23 @@ pub struct RoomKeyCounts {
     pub backed_up: i64,
 }
 
+/// Aggregate statistics about the room keys we have.
+#[derive(uniffi::Record)]
+pub struct SessionStats {
+    /// The total number of room keys.
+    pub total: i64,
+    /// The number of backed up room keys.
+    pub backed_up: i64,
+    /// The number of room keys we received directly from their creator.
+    pub direct: i64,
+    /// The number of room keys that were forwarded to us by another device.
+    pub forwarded: i64,
+    /// The number of room keys that were imported from a file or a backup.
+    pub imported: i64,
+    /// The number of room keys per room ID.
+    pub rooms: HashMap<String, RoomKeyCounts>,
+}
+
 /// Backup keys and information we load from the store.
 #[derive(uniffi::Object)]
 pub struct BackupKeys {
@@ -784,6 +808,19 @@ impl From<matrix_sdk_crypto::store::RoomKeyCounts> for RoomKeyCounts {
     }
 }
 
+impl From<matrix_sdk_crypto::store::SessionStats> for SessionStats {
+    fn from(stats: matrix_sdk_crypto::store::SessionStats) -> Self {
+        Self {
+            total: stats.total as i64,
+            backed_up: stats.backed_up as i64,
+            direct: stats.direct as i64,
+            forwarded: stats.forwarded as i64,
+            imported: stats.imported as i64,
+            rooms: stats.rooms.into_iter().map(|(k, v)| (k.to_string(), v.into())).collect(),
+        }
+    }
+}
+
 impl From<matrix_sdk_crypto::CrossSigningKeyExport> for CrossSigningKeyExport {
     fn from(e: matrix_sdk_crypto::CrossSigningKeyExport) -> Self {
         Self {
@@ -56,7 +56,8 @@ use crate::{
     CrossSigningStatus, DecodeError, DecryptedEvent, Device, DeviceLists, EncryptionSettings,
     EventEncryptionAlgorithm, KeyImportError, KeysImportResult, MegolmV1BackupKey,
     ProgressListener, Request, RequestType, RequestVerificationResult, RoomKeyCounts, RoomSettings,
-    Sas, SignatureUploadRequest, StartSasResult, UserIdentity, Verification, VerificationRequest,
+    Sas, SessionStats, SignatureUploadRequest, StartSasResult, UserIdentity, Verification,
+    VerificationRequest,
 };
 
 /// The return value for the [`OlmMachine::receive_sync_changes()`] method.
@@ -1346,6 +1347,12 @@ impl OlmMachine {
         Ok(self.runtime.block_on(self.inner.backup_machine().room_key_counts())?.into())
     }
 
+    /// Get aggregate statistics about all the room keys we have, e.g. for a
+    /// diagnostics screen.
+    pub fn session_stats(&self) -> Result<SessionStats, CryptoStoreError> {
+        Ok(self.runtime.block_on(self.inner.store().session_stats())?.into())
+    }
+
     /// Store the recovery key in the crypto store.
     ///
     /// This is useful if the client wants to support gossiping of the backup
@@ -27,7 +27,9 @@ use matrix_sdk::{
         },
     },
 };
-use matrix_sdk_ui::timeline::{EventItemOrigin, PollResult, Profile, TimelineDetails};
+use matrix_sdk_ui::timeline::{
+    CallEventKind, EventItemOrigin, PollResult, Profile, TimelineDetails,
+};
 use ruma::{assign, UInt};
 use tracing::{info, warn};
 
@@ -242,6 +244,10 @@ impl TimelineItem {
         match self.0.as_virtual()? {
             VItem::DayDivider(ts) => Some(VirtualTimelineItem::DayDivider { ts: ts.0.into() }),
             VItem::ReadMarker => Some(VirtualTimelineItem::ReadMarker),
+            VItem::UnreadBoundary => Some(VirtualTimelineItem::UnreadBoundary),
+            VItem::TimelineStart { predecessor } => Some(VirtualTimelineItem::TimelineStart {
+                predecessor: predecessor.as_ref().map(ToString::to_string),
+            }),
         }
     }
 
@@ -311,6 +317,10 @@ impl EventTimelineItem {
         self.0.sender_profile().into()
     }
 
+    pub fn sender_power_level(&self) -> Option<i64> {
+        self.0.sender_power_level()
+    }
+
     pub fn is_own(&self) -> bool {
         self.0.is_own()
     }
@@ -319,6 +329,14 @@ impl EventTimelineItem {
         self.0.is_editable()
     }
 
+    pub fn is_grouped_with_previous(&self) -> bool {
+        self.0.is_grouped_with_previous()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.0.is_encrypted()
+    }
+
     pub fn content(&self) -> Arc<TimelineItemContent> {
         Arc::new(TimelineItemContent(self.0.content().clone()))
     }
@@ -427,6 +445,13 @@ impl TimelineItemContent {
                 }
             }
             Content::Poll(poll_state) => TimelineItemContentKind::from(poll_state.results()),
+            Content::Call(call) => {
+                let call_id = call.call_id().to_string();
+                match call.kind() {
+                    CallEventKind::Invite => TimelineItemContentKind::CallInvite { call_id },
+                    CallEventKind::Hangup => TimelineItemContentKind::CallHangup { call_id },
+                }
+            }
             Content::UnableToDecrypt(msg) => {
                 TimelineItemContentKind::UnableToDecrypt { msg: EncryptedMessage::new(msg) }
             }
@@ -501,6 +526,12 @@ pub enum TimelineItemContentKind {
     UnableToDecrypt {
         msg: EncryptedMessage,
     },
+    CallInvite {
+        call_id: String,
+    },
+    CallHangup {
+        call_id: String,
+    },
     RoomMembership {
         user_id: String,
         change: Option<MembershipChange>,
@@ -546,6 +577,10 @@ impl Message {
     pub fn is_edited(&self) -> bool {
         self.0.is_edited()
     }
+
+    pub fn replaces(&self) -> Option<String> {
+        self.0.replaces().map(ToString::to_string)
+    }
 }
 
 #[derive(Clone, uniffi::Enum)]
@@ -1128,6 +1163,16 @@ pub enum VirtualTimelineItem {
 
     /// The user's own read marker.
     ReadMarker,
+
+    /// The boundary before the first message that was unread when the
+    /// timeline was created.
+    UnreadBoundary,
+
+    /// The start of the timeline, i.e. the creation of the room.
+    TimelineStart {
+        /// The ID of the room this room was upgraded from, if any.
+        predecessor: Option<String>,
+    },
 }
 
 #[extension_trait]
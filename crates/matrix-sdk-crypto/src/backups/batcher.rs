@@ -0,0 +1,210 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, mem};
+
+use ruma::{
+    api::client::backup::{KeyBackupData, RoomKeyBackup},
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
+
+/// The size of the `{"rooms":{}}` envelope of a backup request.
+const REQUEST_OVERHEAD: usize = r#"{"rooms":{}}"#.len();
+
+/// The size of the `"":{"sessions":{}},` wrapper of a room, without the room
+/// ID.
+const ROOM_OVERHEAD: usize = r#""":{"sessions":{}},"#.len();
+
+/// The size of the `"":,` wrapper of a room key, without the session ID and
+/// the room key.
+const KEY_OVERHEAD: usize = r#""":,"#.len();
+
+/// Estimate how many bytes a backed up room key adds to the body of a
+/// `/room_keys/keys` request.
+///
+/// The estimate doesn't include the size of the room entry the key is part of,
+/// it's the serialized size of the session ID and the room key.
+pub fn estimate_backup_size(session_id: &str, key: &Raw<KeyBackupData>) -> usize {
+    estimate_backup_size_from_len(session_id, key.json().get().len())
+}
+
+/// Estimate how many bytes a backed up room key adds to the body of a
+/// `/room_keys/keys` request, given the length of its serialized
+/// [`KeyBackupData`].
+///
+/// This is [`estimate_backup_size()`] for room keys that weren't encrypted
+/// yet, e.g. using [`KeyBackupDataPreview::json_size()`].
+///
+/// [`KeyBackupDataPreview::json_size()`]: crate::backups::KeyBackupDataPreview::json_size
+pub fn estimate_backup_size_from_len(session_id: &str, key_len: usize) -> usize {
+    session_id.len() + key_len + KEY_OVERHEAD
+}
+
+/// Groups backed up room keys into batches that can be uploaded with a single
+/// `/room_keys/keys` request.
+///
+/// Homeservers limit both the number of room keys and the size of the body of
+/// such requests. The batcher starts a new batch as soon as adding a room key
+/// to the current one would exceed either of the configured limits. A room key
+/// is never split across batches, a room key that exceeds the byte limit on
+/// its own ends up in a batch of its own.
+///
+/// Each room key should only be pushed once, the batcher doesn't deduplicate
+/// them.
+#[derive(Debug)]
+pub struct BackupBatcher {
+    max_keys: usize,
+    max_bytes: usize,
+    batch: BTreeMap<OwnedRoomId, RoomKeyBackup>,
+    key_count: usize,
+    byte_count: usize,
+}
+
+impl BackupBatcher {
+    /// Create a new batcher producing batches of at most `max_keys` room keys
+    /// and, as far as possible, `max_bytes` bytes.
+    pub fn new(max_keys: usize, max_bytes: usize) -> Self {
+        Self {
+            max_keys,
+            max_bytes,
+            batch: BTreeMap::new(),
+            key_count: 0,
+            byte_count: REQUEST_OVERHEAD,
+        }
+    }
+
+    /// Add a backed up room key to the current batch.
+    ///
+    /// Returns the current batch if it's full, in which case the room key is
+    /// added to a new batch.
+    pub fn push(
+        &mut self,
+        room_id: &RoomId,
+        session_id: String,
+        key: Raw<KeyBackupData>,
+    ) -> Option<BTreeMap<OwnedRoomId, RoomKeyBackup>> {
+        let full = if self.key_count > 0
+            && (self.key_count >= self.max_keys
+                || self.byte_count + self.added_size(room_id, &session_id, &key) > self.max_bytes)
+        {
+            self.take()
+        } else {
+            None
+        };
+
+        self.byte_count += self.added_size(room_id, &session_id, &key);
+        self.key_count += 1;
+        self.batch
+            .entry(room_id.to_owned())
+            .or_insert_with(|| RoomKeyBackup::new(BTreeMap::new()))
+            .sessions
+            .insert(session_id, key);
+
+        full
+    }
+
+    /// Get the last, partially filled, batch.
+    ///
+    /// Returns `None` if no room keys were pushed since the last batch was
+    /// returned.
+    pub fn finish(mut self) -> Option<BTreeMap<OwnedRoomId, RoomKeyBackup>> {
+        self.take()
+    }
+
+    /// The number of bytes adding the given room key to the current batch
+    /// would add.
+    fn added_size(&self, room_id: &RoomId, session_id: &str, key: &Raw<KeyBackupData>) -> usize {
+        let room_size = if self.batch.contains_key(room_id) {
+            0
+        } else {
+            room_id.as_str().len() + ROOM_OVERHEAD
+        };
+
+        room_size + estimate_backup_size(session_id, key)
+    }
+
+    fn take(&mut self) -> Option<BTreeMap<OwnedRoomId, RoomKeyBackup>> {
+        if self.key_count == 0 {
+            return None;
+        }
+
+        self.key_count = 0;
+        self.byte_count = REQUEST_OVERHEAD;
+
+        Some(mem::take(&mut self.batch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{api::client::backup::KeyBackupData, room_id, serde::Raw};
+
+    use super::{estimate_backup_size, estimate_backup_size_from_len, BackupBatcher};
+
+    fn key(size: usize) -> Raw<KeyBackupData> {
+        Raw::from_json_string(format!(r#""{}""#, "a".repeat(size - 2))).unwrap()
+    }
+
+    #[test]
+    fn estimate() {
+        assert_eq!(estimate_backup_size("session", &key(10)), 7 + 10 + 4);
+        assert_eq!(estimate_backup_size_from_len("session", 10), 7 + 10 + 4);
+    }
+
+    #[test]
+    fn batches_by_key_count() {
+        let room_id = room_id!("!test:localhost");
+        let mut batcher = BackupBatcher::new(2, usize::MAX);
+
+        assert!(batcher.push(room_id, "a".to_owned(), key(10)).is_none());
+        assert!(batcher.push(room_id, "b".to_owned(), key(10)).is_none());
+
+        let batch = batcher.push(room_id, "c".to_owned(), key(10)).unwrap();
+        assert_eq!(batch[room_id].sessions.len(), 2);
+
+        let batch = batcher.finish().unwrap();
+        assert!(batch[room_id].sessions.contains_key("c"));
+    }
+
+    #[test]
+    fn batches_by_size() {
+        let room_id = room_id!("!test:localhost");
+        let other_room_id = room_id!("!other:localhost");
+        let mut batcher = BackupBatcher::new(100, 200);
+
+        assert!(batcher.push(room_id, "a".to_owned(), key(50)).is_none());
+        assert!(batcher.push(room_id, "b".to_owned(), key(50)).is_none());
+
+        // The new room entry doesn't fit anymore.
+        let batch = batcher.push(other_room_id, "c".to_owned(), key(50)).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[room_id].sessions.len(), 2);
+
+        // A room key that is larger than the limit gets a batch of its own.
+        let batch = batcher.push(room_id, "d".to_owned(), key(500)).unwrap();
+        assert!(batch[other_room_id].sessions.contains_key("c"));
+
+        let batch = batcher.push(room_id, "e".to_owned(), key(10)).unwrap();
+        assert!(batch[room_id].sessions.contains_key("d"));
+
+        let batch = batcher.finish().unwrap();
+        assert!(batch[room_id].sessions.contains_key("e"));
+    }
+
+    #[test]
+    fn empty_batcher() {
+        assert!(BackupBatcher::new(10, 100).finish().is_none());
+    }
+}
@@ -18,15 +18,124 @@ use std::{
 };
 
 use ruma::{
-    api::client::backup::{KeyBackupData, KeyBackupDataInit, SessionDataInit},
+    api::client::backup::{BackupAlgorithm, KeyBackupData, KeyBackupDataInit, SessionDataInit},
     serde::Base64,
-    OwnedDeviceKeyId, OwnedUserId,
+    CanonicalJsonValue, DeviceKeyAlgorithm, DeviceKeyId, OwnedDeviceKeyId, OwnedUserId, UInt,
+    UserId,
 };
-use vodozemac::Curve25519PublicKey;
+use thiserror::Error;
+use vodozemac::{Curve25519PublicKey, Ed25519PublicKey, Ed25519Signature};
 use zeroize::Zeroizing;
 
-use super::{compat::PkEncryption, decryption::DecodeError};
-use crate::olm::InboundGroupSession;
+use super::{
+    compat::{Message, PkEncryption, MAC_LENGTH},
+    decryption::DecodeError,
+};
+use crate::{
+    error::SignatureError,
+    olm::{InboundGroupSession, KeySourceKind},
+    types::{EventEncryptionAlgorithm, SigningKey, SigningKeys},
+};
+
+/// Get the `forwarded_count` of a room key in the backup, i.e. how many times
+/// the room key was forwarded before we received it.
+///
+/// * A room key we received directly from its creator has a count of 0.
+/// * A forwarded room key has a count of the length of its forwarding chain,
+///   and at least 1.
+/// * A room key that was imported, e.g. from a file, without a forwarding chain
+///   has a count of 1, we don't know how it was received before it was
+///   exported.
+fn forwarded_count(session: &InboundGroupSession) -> UInt {
+    let count = match session.key_source_kind() {
+        KeySourceKind::Direct => 0,
+        KeySourceKind::Forwarded => session.forwarding_chain().len().max(1),
+        KeySourceKind::Imported => 1,
+    };
+
+    UInt::try_from(count).unwrap_or(UInt::MAX)
+}
+
+/// The size of the serialized [`KeyBackupData`] of a room key, without the
+/// values of its fields.
+const KEY_BACKUP_DATA_OVERHEAD: usize = concat!(
+    r#"{"first_message_index":,"forwarded_count":,"is_verified":false,"#,
+    r#""session_data":{"ephemeral":"","ciphertext":"","mac":""}}"#,
+)
+.len();
+
+/// The block size of the AES cipher used to encrypt room keys for the backup.
+const AES_BLOCK_SIZE: usize = 16;
+
+/// The length of an unpadded base64 string encoding `len` bytes.
+fn base64_len(len: usize) -> usize {
+    (len * 4 + 2) / 3
+}
+
+/// A preview of the [`KeyBackupData`] of a room key, see
+/// [`MegolmV1BackupKey::encrypt_preview()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyBackupDataPreview {
+    /// The first message index of the room key.
+    pub first_message_index: UInt,
+    /// The number of times the room key was forwarded.
+    pub forwarded_count: UInt,
+    /// The length, in bytes, of the encrypted room key, before it's base64
+    /// encoded.
+    pub ciphertext_length: usize,
+}
+
+impl KeyBackupDataPreview {
+    /// The length of the serialized [`KeyBackupData`] of the room key.
+    ///
+    /// This can be passed, together with the session ID, to
+    /// [`estimate_backup_size_from_len()`] to get the size the room key adds
+    /// to a `/room_keys/keys` request.
+    ///
+    /// [`estimate_backup_size_from_len()`]: crate::backups::estimate_backup_size_from_len
+    pub fn json_size(&self) -> usize {
+        KEY_BACKUP_DATA_OVERHEAD
+            + self.first_message_index.to_string().len()
+            + self.forwarded_count.to_string().len()
+            + base64_len(Curve25519PublicKey::LENGTH)
+            + base64_len(self.ciphertext_length)
+            + base64_len(MAC_LENGTH)
+    }
+}
+
+/// Error type for the encryption of a room key for a backup.
+#[derive(Debug, Error)]
+pub enum BackupEncryptionError {
+    /// The room key uses an algorithm that can't be backed up with the
+    /// algorithm of the backup key.
+    #[error(
+        "A room key using the {0} algorithm can't be backed up using the {1} backup algorithm"
+    )]
+    IncompatibleAlgorithm(EventEncryptionAlgorithm, &'static str),
+
+    /// The backup key doesn't have a backup version set, see
+    /// [`MegolmV1BackupKey::set_version()`].
+    #[error("The backup key doesn't have a backup version set")]
+    NoVersionSet,
+
+    /// The room key couldn't be serialized before being encrypted.
+    #[error("The room key couldn't be serialized: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// The trust state of a backup, as returned by
+/// [`MegolmV1BackupKey::verify_auth_data()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackupTrustState {
+    /// The auth data of the backup carries a valid signature from one of the
+    /// trusted keys.
+    Trusted,
+    /// The auth data of the backup is signed, but none of the signatures is a
+    /// valid signature from one of the trusted keys.
+    Untrusted,
+    /// The auth data of the backup isn't signed by the user at all.
+    NoSignature,
+}
 
 #[derive(Debug)]
 struct InnerBackupKey {
@@ -36,6 +145,17 @@ struct InnerBackupKey {
 }
 
 /// The public part of a backup key.
+///
+/// The `m.megolm_backup.v1.curve25519-aes-sha2` algorithm derives its MAC key
+/// from the ephemeral key exchange of every encrypted room key, so there is no
+/// separate MAC key to configure: every [`KeyBackupData`] this key produces
+/// carries a `mac`.
+///
+/// **Note**: For compatibility with libolm the `mac` doesn't cover the
+/// ciphertext, or any other part of the [`KeyBackupData`], it's the first 8
+/// bytes of an HMAC-SHA-256 over an empty input. Verifiers that want to
+/// reproduce it need the MAC key derived from the ephemeral key exchange, the
+/// JSON of the backup doesn't play any part in it.
 #[derive(Clone)]
 pub struct MegolmV1BackupKey {
     inner: Arc<InnerBackupKey>,
@@ -52,6 +172,8 @@ impl std::fmt::Debug for MegolmV1BackupKey {
 }
 
 impl MegolmV1BackupKey {
+    const BACKUP_ALGORITHM: &'static str = "m.megolm_backup.v1.curve25519-aes-sha2";
+
     pub(super) fn new(key: Curve25519PublicKey, version: Option<String>) -> Self {
         Self {
             inner: InnerBackupKey {
@@ -63,9 +185,27 @@ impl MegolmV1BackupKey {
         }
     }
 
+    /// Create a new `MegolmV1BackupKey` that already carries the given
+    /// signatures.
+    ///
+    /// This lets tests exercise [`MegolmV1BackupKey::verify_signature()`]
+    /// without going through the auth data of a backup version.
+    ///
+    /// **Note**: The `m.megolm_backup.v1.curve25519-aes-sha2` algorithm has no
+    /// separate MAC key, so unlike the other parts of the auth data there's
+    /// nothing to pass for it.
+    #[cfg(test)]
+    pub(crate) fn new_with_signatures(
+        key: Curve25519PublicKey,
+        version: Option<String>,
+        signatures: BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceKeyId, String>>,
+    ) -> Self {
+        Self { inner: InnerBackupKey { key, signatures, version: Mutex::new(version) }.into() }
+    }
+
     /// Get the full name of the backup algorithm this backup key supports.
     pub fn backup_algorithm(&self) -> &str {
-        "m.megolm_backup.v1.curve25519-aes-sha2"
+        Self::BACKUP_ALGORITHM
     }
 
     /// Get all the signatures of this `MegolmV1BackupKey`.
@@ -73,6 +213,97 @@ impl MegolmV1BackupKey {
         self.inner.signatures.to_owned()
     }
 
+    /// Verify the signature of the given Ed25519 key over the auth data of
+    /// this backup.
+    ///
+    /// This is used to check that the backup was created by a trusted device,
+    /// for example by checking the signature of a user's master cross-signing
+    /// key before restoring room keys from the backup.
+    ///
+    /// **Note**: The auth data is reconstructed from the public key of this
+    /// `MegolmV1BackupKey`, any additional fields of the auth data as
+    /// uploaded to the server aren't covered.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user that claims to have signed the backup.
+    ///
+    /// * `key` - The Ed25519 key that should have signed the backup.
+    ///
+    /// Returns [`SignatureError::NoSignatureFound`] if there's no signature
+    /// from the given user and key.
+    pub fn verify_signature(
+        &self,
+        user_id: &UserId,
+        key: &Ed25519PublicKey,
+    ) -> Result<(), SignatureError> {
+        let key_id =
+            DeviceKeyId::from_parts(DeviceKeyAlgorithm::Ed25519, key.to_base64().as_str().into());
+
+        self.verify_signature_helper(user_id, &key_id, key)
+    }
+
+    /// Check if the auth data of this backup has been signed by any of the
+    /// given trusted keys of the given user.
+    ///
+    /// Clients must not restore room keys from a backup, or set its version,
+    /// unless this returns [`BackupTrustState::Trusted`]. The trusted keys are
+    /// usually the master cross-signing key of the user and the keys of the
+    /// user's verified devices.
+    ///
+    /// Like [`MegolmV1BackupKey::verify_signature()`], this only covers the
+    /// public key of the auth data.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user whose signatures should be checked.
+    ///
+    /// * `trusted_keys` - The Ed25519 keys that are trusted to sign the backup,
+    /// keyed by their key ID.
+    pub fn verify_auth_data(
+        &self,
+        user_id: &UserId,
+        trusted_keys: &SigningKeys<OwnedDeviceKeyId>,
+    ) -> BackupTrustState {
+        let Some(signatures) = self.inner.signatures.get(user_id).filter(|s| !s.is_empty()) else {
+            return BackupTrustState::NoSignature;
+        };
+
+        let trusted = signatures.keys().any(|key_id| match trusted_keys.get(key_id) {
+            Some(SigningKey::Ed25519(key)) => {
+                self.verify_signature_helper(user_id, key_id, key).is_ok()
+            }
+            _ => false,
+        });
+
+        if trusted {
+            BackupTrustState::Trusted
+        } else {
+            BackupTrustState::Untrusted
+        }
+    }
+
+    fn verify_signature_helper(
+        &self,
+        user_id: &UserId,
+        key_id: &DeviceKeyId,
+        key: &Ed25519PublicKey,
+    ) -> Result<(), SignatureError> {
+        let signature = self
+            .inner
+            .signatures
+            .get(user_id)
+            .and_then(|s| s.get(key_id))
+            .ok_or(SignatureError::NoSignatureFound)?;
+        let signature = Ed25519Signature::from_base64(signature)
+            .map_err(|_| SignatureError::InvalidSignature)?;
+
+        let auth_data: CanonicalJsonValue =
+            serde_json::json!({ "public_key": self.to_base64() }).try_into()?;
+
+        Ok(key.verify(auth_data.to_string().as_bytes(), &signature)?)
+    }
+
     /// Try to create a new `MegolmV1BackupKey` from a base 64 encoded string.
     pub fn from_base64(public_key: &str) -> Result<Self, DecodeError> {
         let key = Curve25519PublicKey::from_base64(public_key)?;
@@ -83,11 +314,57 @@ impl MegolmV1BackupKey {
         Ok(MegolmV1BackupKey { inner: inner.into() })
     }
 
+    /// Create a new `MegolmV1BackupKey` from the raw bytes of a Curve25519
+    /// public key.
+    pub fn from_bytes(public_key: [u8; 32]) -> Self {
+        Self::new(Curve25519PublicKey::from_bytes(public_key), None)
+    }
+
+    /// Create a new `MegolmV1BackupKey` from the backup info the server
+    /// returns from the [`/room_keys/version`] endpoint.
+    ///
+    /// This will extract the public key and the signatures from the auth data
+    /// and set the backup version of the key to the given `version`.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_data` - The backup algorithm, including the auth data, of the
+    /// backup version.
+    ///
+    /// * `version` - The version of the backup, as returned by the server.
+    ///
+    /// [`/room_keys/version`]: https://spec.matrix.org/unstable/client-server-api/#get_matrixclientv3room_keysversion
+    pub fn from_auth_data(
+        auth_data: &BackupAlgorithm,
+        version: String,
+    ) -> Result<Self, DecodeError> {
+        let BackupAlgorithm::MegolmBackupV1Curve25519AesSha2 { public_key, signatures } = auth_data
+        else {
+            return Err(DecodeError::UnsupportedAlgorithm);
+        };
+
+        let key = Curve25519PublicKey::from_slice(public_key.as_bytes())?;
+
+        let inner = InnerBackupKey {
+            key,
+            signatures: signatures.to_owned(),
+            version: Mutex::new(Some(version)),
+        };
+
+        Ok(MegolmV1BackupKey { inner: inner.into() })
+    }
+
     /// Convert the [`MegolmV1BackupKey`] to a base 64 encoded string.
     pub fn to_base64(&self) -> String {
         self.inner.key.to_base64()
     }
 
+    /// Get the raw bytes of the Curve25519 public key of this
+    /// [`MegolmV1BackupKey`].
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.inner.key.to_bytes()
+    }
+
     /// Get the backup version that this key is used with, if any.
     pub fn backup_version(&self) -> Option<String> {
         self.inner.version.lock().unwrap().clone()
@@ -96,17 +373,95 @@ impl MegolmV1BackupKey {
     /// Set the backup version that this `MegolmV1BackupKey` will be used with.
     ///
     /// The key won't be able to encrypt room keys unless a version has been
-    /// set.
+    /// set, encrypting a room key fails with
+    /// [`BackupEncryptionError::NoVersionSet`] otherwise.
     pub fn set_version(&self, version: String) {
         *self.inner.version.lock().unwrap() = Some(version);
     }
 
-    pub(crate) async fn encrypt(&self, session: InboundGroupSession) -> KeyBackupData {
+    /// Check if room keys using the given algorithm can be backed up using
+    /// this backup key.
+    ///
+    /// The `m.megolm_backup.v1.curve25519-aes-sha2` backup algorithm only
+    /// supports `m.megolm.v1.aes-sha2` room keys.
+    pub fn is_compatible_with(&self, algorithm: &EventEncryptionAlgorithm) -> bool {
+        matches!(algorithm, EventEncryptionAlgorithm::MegolmV1AesSha2)
+    }
+
+    pub(crate) async fn encrypt(
+        &self,
+        session: InboundGroupSession,
+    ) -> Result<KeyBackupData, BackupEncryptionError> {
+        self.encrypt_helper(session, |pk, key| pk.encrypt(key)).await
+    }
+
+    /// Encrypt the given room key for the backup, using an ephemeral key
+    /// generated by the given RNG.
+    ///
+    /// This makes it possible to produce reproducible [`KeyBackupData`] in
+    /// tests by using a seeded RNG. It's only available in tests since reusing
+    /// an ephemeral key breaks the confidentiality of the backup.
+    #[cfg(test)]
+    pub(crate) async fn encrypt_with_rng(
+        &self,
+        session: InboundGroupSession,
+        rng: &mut (impl rand::RngCore + rand::CryptoRng),
+    ) -> Result<KeyBackupData, BackupEncryptionError> {
+        self.encrypt_helper(session, |pk, key| pk.encrypt_with_rng(key, rng)).await
+    }
+
+    /// Get a preview of the [`KeyBackupData`] the given room key would be
+    /// encrypted to, without encrypting it.
+    ///
+    /// This skips the expensive Curve25519 key agreement and the encryption
+    /// itself, the size of the ciphertext is derived from the size of the
+    /// exported room key. This makes it cheap to compute the size of large
+    /// backups, e.g. to split them into batches.
+    pub async fn encrypt_preview(
+        &self,
+        session: &InboundGroupSession,
+    ) -> Result<KeyBackupDataPreview, BackupEncryptionError> {
+        self.check_session(session)?;
+
+        let key = session.to_backup().await;
+        let key = Zeroizing::new(serde_json::to_vec(&key)?);
+
+        // The room key is padded using PKCS#7, which always adds at least one
+        // byte of padding.
+        let ciphertext_length = (key.len() / AES_BLOCK_SIZE + 1) * AES_BLOCK_SIZE;
+
+        Ok(KeyBackupDataPreview {
+            first_message_index: session.first_known_index().into(),
+            forwarded_count: forwarded_count(session),
+            ciphertext_length,
+        })
+    }
+
+    fn check_session(&self, session: &InboundGroupSession) -> Result<(), BackupEncryptionError> {
+        if self.backup_version().is_none() {
+            return Err(BackupEncryptionError::NoVersionSet);
+        }
+
+        if !self.is_compatible_with(session.algorithm()) {
+            return Err(BackupEncryptionError::IncompatibleAlgorithm(
+                session.algorithm().to_owned(),
+                Self::BACKUP_ALGORITHM,
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn encrypt_helper(
+        &self,
+        session: InboundGroupSession,
+        encrypt: impl FnOnce(&PkEncryption, &[u8]) -> Message,
+    ) -> Result<KeyBackupData, BackupEncryptionError> {
+        self.check_session(&session)?;
+
         let pk = PkEncryption::from_key(self.inner.key);
 
-        // The forwarding chains don't mean much, we only care whether we received the
-        // session directly from the creator of the session or not.
-        let forwarded_count = (session.has_been_imported() as u8).into();
+        let forwarded_count = forwarded_count(&session);
         let first_message_index = session.first_known_index().into();
 
         // Convert our key to the backup representation.
@@ -114,10 +469,9 @@ impl MegolmV1BackupKey {
 
         // The key gets zeroized in `BackedUpRoomKey` but we're creating a copy
         // here that won't, so let's wrap it up in a `Zeroizing` struct.
-        let key =
-            Zeroizing::new(serde_json::to_vec(&key).expect("Can't serialize exported room key"));
+        let key = Zeroizing::new(serde_json::to_vec(&key)?);
 
-        let message = pk.encrypt(&key);
+        let message = encrypt(&pk, &key);
 
         let session_data = SessionDataInit {
             ephemeral: Base64::new(message.ephemeral_key.to_vec()),
@@ -126,7 +480,7 @@ impl MegolmV1BackupKey {
         }
         .into();
 
-        KeyBackupDataInit {
+        Ok(KeyBackupDataInit {
             first_message_index,
             forwarded_count,
             // TODO: is this actually used anywhere? seems to be completely
@@ -136,6 +490,501 @@ impl MegolmV1BackupKey {
             is_verified: false,
             session_data,
         }
-        .into()
+        .into())
+    }
+}
+
+/// The public key of a backup, for one of the supported backup algorithms.
+///
+/// This allows code that handles backups to be written once for all the
+/// backup algorithms, [`MegolmV1BackupKey`] can still be used directly for
+/// code that only supports the `m.megolm_backup.v1.curve25519-aes-sha2`
+/// algorithm.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum BackupKey {
+    /// A key for the `m.megolm_backup.v1.curve25519-aes-sha2` algorithm.
+    MegolmV1(MegolmV1BackupKey),
+    /// Placeholder for the upcoming `m.megolm_backup.v2` algorithm.
+    ///
+    /// This algorithm isn't supported yet, so no key of this variant can be
+    /// constructed.
+    #[cfg(feature = "experimental-algorithms")]
+    MegolmV2(std::convert::Infallible),
+}
+
+impl BackupKey {
+    /// Get the name of the backup algorithm of this key.
+    pub fn backup_algorithm(&self) -> &str {
+        match self {
+            BackupKey::MegolmV1(key) => key.backup_algorithm(),
+            #[cfg(feature = "experimental-algorithms")]
+            BackupKey::MegolmV2(never) => match *never {},
+        }
+    }
+
+    /// Convert the backup key to a base64 encoded string.
+    pub fn to_base64(&self) -> String {
+        match self {
+            BackupKey::MegolmV1(key) => key.to_base64(),
+            #[cfg(feature = "experimental-algorithms")]
+            BackupKey::MegolmV2(never) => match *never {},
+        }
+    }
+
+    /// Get the backup version that this key is used with, if any.
+    pub fn backup_version(&self) -> Option<String> {
+        match self {
+            BackupKey::MegolmV1(key) => key.backup_version(),
+            #[cfg(feature = "experimental-algorithms")]
+            BackupKey::MegolmV2(never) => match *never {},
+        }
+    }
+
+    /// Encrypt the given room key for the backup this key is used with.
+    pub(crate) async fn encrypt(
+        &self,
+        session: InboundGroupSession,
+    ) -> Result<KeyBackupData, BackupEncryptionError> {
+        match self {
+            BackupKey::MegolmV1(key) => key.encrypt(session).await,
+            #[cfg(feature = "experimental-algorithms")]
+            BackupKey::MegolmV2(never) => match *never {},
+        }
+    }
+
+    /// Encrypt the given room key for the backup, unless its first known
+    /// index is larger than `max_first_index`.
+    ///
+    /// Some servers refuse room keys that don't start at, or close to, the
+    /// beginning of the session. Returns `Ok(None)` if the room key was
+    /// skipped because of this policy; the room key is left as is, so it will
+    /// keep being returned by
+    /// [`CryptoStore::inbound_group_sessions_for_backup()`] until it's marked
+    /// as backed up or the policy changes.
+    ///
+    /// [`CryptoStore::inbound_group_sessions_for_backup()`]: crate::store::CryptoStore::inbound_group_sessions_for_backup
+    pub(crate) async fn encrypt_with_policy(
+        &self,
+        session: InboundGroupSession,
+        max_first_index: Option<u32>,
+    ) -> Result<Option<KeyBackupData>, BackupEncryptionError> {
+        if max_first_index.is_some_and(|max| session.first_known_index() > max) {
+            return Ok(None);
+        }
+
+        self.encrypt(session).await.map(Some)
+    }
+}
+
+impl From<MegolmV1BackupKey> for BackupKey {
+    fn from(key: MegolmV1BackupKey) -> Self {
+        Self::MegolmV1(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assert_matches::assert_matches;
+    use matrix_sdk_test::async_test;
+    use rand::{rngs::StdRng, SeedableRng};
+    use ruma::{
+        api::client::backup::BackupAlgorithm, device_id, room_id, serde::Raw, user_id,
+        DeviceKeyAlgorithm, DeviceKeyId,
+    };
+    use serde_json::json;
+    use vodozemac::{Curve25519PublicKey, Ed25519SecretKey};
+
+    use super::{
+        forwarded_count, BackupEncryptionError, BackupKey, BackupTrustState, MegolmV1BackupKey,
+    };
+    use crate::{
+        backups::{DecodeError, RestoreOutcome},
+        error::SignatureError,
+        olm::{BackedUpRoomKey, InboundGroupSession},
+        store::BackupDecryptionKey,
+        types::SigningKeys,
+        ReadOnlyAccount,
+    };
+
+    #[test]
+    fn from_auth_data() {
+        let auth_data: BackupAlgorithm = serde_json::from_value(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": "XjhWTCjW7l59pbfx9tlCBQolfnIQWARoKOzjTOPSlWM",
+                "signatures": {
+                    "@alice:example.org": {
+                        "ed25519:deviceid": "signature"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let key = MegolmV1BackupKey::from_auth_data(&auth_data, "1".to_owned())
+            .expect("We should be able to create a backup key from valid auth data");
+
+        assert_eq!(key.to_base64(), "XjhWTCjW7l59pbfx9tlCBQolfnIQWARoKOzjTOPSlWM");
+        assert_eq!(key.backup_version().as_deref(), Some("1"));
+        assert!(key.signatures().contains_key(user_id!("@alice:example.org")));
+
+        let auth_data: BackupAlgorithm = serde_json::from_value(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": "XjhWTCjW7l59pbfx9tlCBQ",
+                "signatures": {}
+            }
+        }))
+        .unwrap();
+
+        let result = MegolmV1BackupKey::from_auth_data(&auth_data, "1".to_owned());
+        assert!(
+            matches!(result, Err(DecodeError::PublicKey(_))),
+            "A malformed public key should result in a decode error"
+        );
+    }
+
+    #[test]
+    fn verify_signature() {
+        let public_key = "XjhWTCjW7l59pbfx9tlCBQolfnIQWARoKOzjTOPSlWM";
+        let signing_key = Ed25519SecretKey::new();
+        let other_key = Ed25519SecretKey::new();
+        let user_id = user_id!("@alice:example.org");
+
+        let signature = signing_key.sign(format!(r#"{{"public_key":"{public_key}"}}"#).as_bytes());
+        let signing_key_id = format!("ed25519:{}", signing_key.public_key().to_base64());
+        let other_key_id = format!("ed25519:{}", other_key.public_key().to_base64());
+
+        let auth_data: BackupAlgorithm = serde_json::from_value(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": public_key,
+                "signatures": {
+                    user_id.to_string(): {
+                        signing_key_id: signature.to_base64(),
+                        other_key_id: "invalid",
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let key = MegolmV1BackupKey::from_auth_data(&auth_data, "1".to_owned()).unwrap();
+
+        key.verify_signature(user_id, &signing_key.public_key())
+            .expect("The signature of the signing key should be valid");
+
+        assert_matches!(
+            key.verify_signature(user_id, &other_key.public_key()),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_matches!(
+            key.verify_signature(user_id, &Ed25519SecretKey::new().public_key()),
+            Err(SignatureError::NoSignatureFound)
+        );
+        assert_matches!(
+            key.verify_signature(user_id!("@bob:example.org"), &signing_key.public_key()),
+            Err(SignatureError::NoSignatureFound)
+        );
+    }
+
+    #[test]
+    fn verify_auth_data() {
+        let public_key = "XjhWTCjW7l59pbfx9tlCBQolfnIQWARoKOzjTOPSlWM";
+        let signing_key = Ed25519SecretKey::new();
+        let user_id = user_id!("@alice:example.org");
+
+        let signature = signing_key.sign(format!(r#"{{"public_key":"{public_key}"}}"#).as_bytes());
+        let key_id = DeviceKeyId::from_parts(DeviceKeyAlgorithm::Ed25519, device_id!("DEVICEID"));
+
+        let auth_data: BackupAlgorithm = serde_json::from_value(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": public_key,
+                "signatures": {
+                    user_id.to_string(): {
+                        key_id.to_string(): signature.to_base64(),
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let key = MegolmV1BackupKey::from_auth_data(&auth_data, "1".to_owned()).unwrap();
+
+        let trusted_keys = SigningKeys::from([(key_id.clone(), signing_key.public_key().into())]);
+        assert_eq!(key.verify_auth_data(user_id, &trusted_keys), BackupTrustState::Trusted);
+
+        // A valid signature from a key we don't trust isn't enough.
+        let other_keys = SigningKeys::from([(
+            DeviceKeyId::from_parts(DeviceKeyAlgorithm::Ed25519, device_id!("OTHERDEVICE")),
+            signing_key.public_key().into(),
+        )]);
+        assert_eq!(key.verify_auth_data(user_id, &other_keys), BackupTrustState::Untrusted);
+
+        // Neither is a trusted key ID paired with the wrong key.
+        let wrong_keys = SigningKeys::from([(key_id, Ed25519SecretKey::new().public_key().into())]);
+        assert_eq!(key.verify_auth_data(user_id, &wrong_keys), BackupTrustState::Untrusted);
+
+        assert_eq!(
+            key.verify_auth_data(user_id!("@bob:example.org"), &trusted_keys),
+            BackupTrustState::NoSignature
+        );
+    }
+
+    #[test]
+    fn verify_injected_signature() {
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        let signing_key = Ed25519SecretKey::new();
+        let user_id = user_id!("@alice:example.org");
+
+        let auth_data = format!(r#"{{"public_key":"{}"}}"#, backup_key.to_base64());
+        let signature = signing_key.sign(auth_data.as_bytes());
+        let key_id = DeviceKeyId::from_parts(
+            DeviceKeyAlgorithm::Ed25519,
+            signing_key.public_key().to_base64().as_str().into(),
+        );
+        let signatures = BTreeMap::from([(
+            user_id.to_owned(),
+            BTreeMap::from([(key_id, signature.to_base64())]),
+        )]);
+
+        let key = MegolmV1BackupKey::new_with_signatures(
+            Curve25519PublicKey::from_bytes(backup_key.to_bytes()),
+            Some("1".to_owned()),
+            signatures.clone(),
+        );
+
+        assert_eq!(key.signatures(), signatures);
+        assert_eq!(key.backup_version().as_deref(), Some("1"));
+        key.verify_signature(user_id, &signing_key.public_key())
+            .expect("The injected signature should be valid");
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        let restored = MegolmV1BackupKey::from_bytes(backup_key.to_bytes());
+
+        assert_eq!(restored.to_bytes(), backup_key.to_bytes());
+        assert_eq!(restored.to_base64(), backup_key.to_base64());
+        assert_eq!(restored.backup_version(), None);
+    }
+
+    #[async_test]
+    async fn encrypt() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+        assert!(backup_key.is_compatible_with(session.algorithm()));
+        backup_key.encrypt(session).await.expect("A megolm.v1 room key should be backed up");
+    }
+
+    #[async_test]
+    async fn backup_key_dispatches_to_megolm_v1() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let decryption_key = BackupDecryptionKey::new().unwrap();
+        let megolm_v1_key = decryption_key.megolm_v1_public_key();
+        megolm_v1_key.set_version("1".to_owned());
+
+        let backup_key = BackupKey::from(megolm_v1_key.clone());
+        assert_eq!(backup_key.backup_algorithm(), megolm_v1_key.backup_algorithm());
+        assert_eq!(backup_key.to_base64(), megolm_v1_key.to_base64());
+        assert_eq!(backup_key.backup_version().as_deref(), Some("1"));
+
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+        let data = backup_key.encrypt(session.clone()).await.unwrap();
+        assert_eq!(data.first_message_index, session.first_known_index().into());
+
+        // The room key was encrypted for the megolm.v1 key.
+        let decrypted = decryption_key
+            .decrypt_v1(
+                &data.session_data.ephemeral.encode(),
+                &data.session_data.mac.encode(),
+                &data.session_data.ciphertext.encode(),
+            )
+            .unwrap();
+        let room_key: BackedUpRoomKey = serde_json::from_str(&decrypted).unwrap();
+        assert_eq!(
+            room_key.session_key.to_base64(),
+            session.export().await.session_key.to_base64()
+        );
+    }
+
+    #[async_test]
+    async fn encrypt_preview() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+        assert_matches!(
+            backup_key.encrypt_preview(&session).await,
+            Err(BackupEncryptionError::NoVersionSet)
+        );
+
+        backup_key.set_version("1".to_owned());
+
+        let imported = InboundGroupSession::from_export(&session.export().await).unwrap();
+
+        for session in [session, imported] {
+            let preview = backup_key.encrypt_preview(&session).await.unwrap();
+            let data = backup_key.encrypt(session).await.unwrap();
+
+            assert_eq!(preview.first_message_index, data.first_message_index);
+            assert_eq!(preview.forwarded_count, data.forwarded_count);
+            assert_eq!(preview.json_size(), Raw::new(&data).unwrap().json().get().len());
+        }
+    }
+
+    #[async_test]
+    async fn encrypt_without_version() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let public_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key().to_base64();
+        let backup_key = MegolmV1BackupKey::from_base64(&public_key).unwrap();
+        assert_eq!(backup_key.backup_version(), None);
+
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+
+        assert_matches!(
+            backup_key.encrypt(session.clone()).await,
+            Err(BackupEncryptionError::NoVersionSet)
+        );
+        assert_matches!(
+            BackupKey::from(backup_key.clone()).encrypt_with_policy(session.clone(), None).await,
+            Err(BackupEncryptionError::NoVersionSet)
+        );
+
+        backup_key.set_version("1".to_owned());
+        backup_key.encrypt(session).await.expect("A key with a version should encrypt room keys");
+    }
+
+    #[async_test]
+    async fn forwarded_count_per_key_source() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+        let forwarder = account.identity_keys().curve25519;
+
+        let (_, direct) = account.create_group_session_pair_with_defaults(room_id).await;
+        assert_eq!(forwarded_count(&direct), 0u32.into());
+
+        let imported = InboundGroupSession::from_export(&direct.export().await).unwrap();
+        assert_eq!(forwarded_count(&imported), 1u32.into());
+
+        let mut export = direct.export().await;
+        export.forwarding_curve25519_key_chain = vec![forwarder];
+        let forwarded_once = InboundGroupSession::from_export(&export).unwrap();
+        assert_eq!(forwarded_count(&forwarded_once), 1u32.into());
+
+        export.forwarding_curve25519_key_chain = vec![forwarder, forwarder, forwarder];
+        let forwarded_thrice = InboundGroupSession::from_export(&export).unwrap();
+        assert_eq!(forwarded_count(&forwarded_thrice), 3u32.into());
+
+        let backed_up = backup_key.encrypt(forwarded_thrice).await.unwrap();
+        assert_eq!(backed_up.forwarded_count, 3u32.into());
+        let backed_up = backup_key.encrypt(direct).await.unwrap();
+        assert_eq!(backed_up.forwarded_count, 0u32.into());
+    }
+
+    #[async_test]
+    async fn encrypt_with_seeded_rng() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let decryption_key = BackupDecryptionKey::new().unwrap();
+        let backup_key = decryption_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+
+        let first = backup_key
+            .encrypt_with_rng(session.clone(), &mut StdRng::seed_from_u64(42))
+            .await
+            .unwrap();
+        let second = backup_key
+            .encrypt_with_rng(session.clone(), &mut StdRng::seed_from_u64(42))
+            .await
+            .unwrap();
+        let other = backup_key
+            .encrypt_with_rng(session.clone(), &mut StdRng::seed_from_u64(43))
+            .await
+            .unwrap();
+
+        assert_eq!(serde_json::to_value(&first).unwrap(), serde_json::to_value(&second).unwrap());
+        assert_ne!(first.session_data.ephemeral, other.session_data.ephemeral);
+
+        let outcome = decryption_key
+            .decrypt_and_import(room_id, session.session_id(), &first.session_data, None)
+            .await
+            .expect("A backup encrypted with a seeded RNG should be decryptable");
+        assert_matches!(outcome, RestoreOutcome::New(_));
+    }
+
+    #[async_test]
+    async fn encrypt_with_max_first_index() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let megolm_v1_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        megolm_v1_key.set_version("1".to_owned());
+        let backup_key = BackupKey::from(megolm_v1_key);
+
+        let (outbound, _) = account.create_group_session_pair_with_defaults(room_id).await;
+        outbound.encrypt_helper("Hello".to_owned()).await;
+        let session = outbound.create_matching_inbound().await.unwrap();
+        assert_eq!(session.first_known_index(), 1);
+
+        let result = backup_key.encrypt_with_policy(session.clone(), Some(0)).await.unwrap();
+        assert!(result.is_none(), "A room key starting after the maximum index should be skipped");
+
+        let result = backup_key.encrypt_with_policy(session.clone(), Some(1)).await.unwrap();
+        assert_eq!(result.unwrap().first_message_index, 1u32.into());
+
+        let result = backup_key.encrypt_with_policy(session, None).await.unwrap();
+        assert!(result.is_some(), "Without a policy every room key should be backed up");
+    }
+
+    #[async_test]
+    #[cfg(feature = "experimental-algorithms")]
+    async fn encrypt_incompatible_algorithm() {
+        use crate::{olm::EncryptionSettings, types::EventEncryptionAlgorithm};
+
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let backup_key = BackupDecryptionKey::new().unwrap().megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+
+        let settings = EncryptionSettings {
+            algorithm: EventEncryptionAlgorithm::MegolmV2AesSha2,
+            ..Default::default()
+        };
+        let (_, session) = account.create_group_session_pair(room_id, settings).await.unwrap();
+        assert!(!backup_key.is_compatible_with(session.algorithm()));
+
+        let result = backup_key.encrypt(session).await;
+        assert!(
+            matches!(
+                result,
+                Err(BackupEncryptionError::IncompatibleAlgorithm(
+                    EventEncryptionAlgorithm::MegolmV2AesSha2,
+                    _
+                ))
+            ),
+            "A megolm.v2 room key shouldn't be backed up with a megolm.v1 backup key"
+        );
     }
 }
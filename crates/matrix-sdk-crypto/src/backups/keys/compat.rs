@@ -37,7 +37,7 @@ type Aes256Key = GenericArray<u8, <Aes256 as KeySizeUser>::KeySize>;
 type Aes256Iv = GenericArray<u8, <Aes256CbcEnc as IvSizeUser>::IvSize>;
 type HmacSha256Key<'a> = &'a [u8; 32];
 
-const MAC_LENGTH: usize = 8;
+pub(super) const MAC_LENGTH: usize = 8;
 
 pub struct PkDecryption {
     key: Curve25519SecretKey,
@@ -153,7 +153,31 @@ impl PkEncryption {
     }
 
     pub fn encrypt(&self, message: &[u8]) -> Message {
-        let ephemeral_key = Curve25519SecretKey::new();
+        self.encrypt_with_ephemeral_key(message, Curve25519SecretKey::new())
+    }
+
+    /// Encrypt the message using an ephemeral key generated by the given RNG.
+    ///
+    /// Seeding the RNG makes the output reproducible, which is only
+    /// acceptable in tests: reusing an ephemeral key for different messages
+    /// breaks the confidentiality of the scheme.
+    #[cfg(test)]
+    pub fn encrypt_with_rng(
+        &self,
+        message: &[u8],
+        rng: &mut (impl rand::RngCore + rand::CryptoRng),
+    ) -> Message {
+        let mut bytes = zeroize::Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(bytes.as_mut_slice());
+
+        self.encrypt_with_ephemeral_key(message, Curve25519SecretKey::from_slice(&bytes))
+    }
+
+    fn encrypt_with_ephemeral_key(
+        &self,
+        message: &[u8],
+        ephemeral_key: Curve25519SecretKey,
+    ) -> Message {
         let shared_secret = ephemeral_key.diffie_hellman(&self.public_key);
         let keys = Keys::new(shared_secret);
 
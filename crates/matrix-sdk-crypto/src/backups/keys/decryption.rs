@@ -18,14 +18,19 @@ use std::{
 };
 
 use bs58;
+use ruma::{api::client::backup::EncryptedSessionData, RoomId};
 use thiserror::Error;
+use vodozemac::megolm::SessionOrdering;
 use zeroize::Zeroizing;
 
 use super::{
     compat::{Error as DecryptionError, Message, PkDecryption},
     MegolmV1BackupKey,
 };
-use crate::store::BackupDecryptionKey;
+use crate::{
+    olm::{BackedUpRoomKey, InboundGroupSession, SessionCreationError},
+    store::BackupDecryptionKey,
+};
 
 /// Error type for the decoding of a [`BackupDecryptionKey`].
 #[derive(Debug, Error)]
@@ -39,6 +44,9 @@ pub enum DecodeError {
     /// The recovery key has an invalid length.
     #[error("The decoded recovery key has a invalid length: expected {0}, got {1}")]
     Length(usize, usize),
+    /// The secret storage secret has an invalid length.
+    #[error("The secret storage secret has an invalid length: expected {0} characters, got {1}")]
+    SecretLength(usize, usize),
     /// The recovry key isn't valid base58.
     #[error(transparent)]
     Base58(#[from] bs58::decode::Error),
@@ -51,6 +59,59 @@ pub enum DecodeError {
     /// The recovery key, a Curve25519 public key, couldn't be decoded.
     #[error(transparent)]
     PublicKey(#[from] vodozemac::KeyError),
+    /// The backup uses an algorithm we don't support.
+    #[error("The backup uses an unsupported algorithm")]
+    UnsupportedAlgorithm,
+}
+
+/// Error type for the restoration of a single room key from a backup.
+#[derive(Debug, Error)]
+pub enum RestoreError {
+    /// The MAC of the backed up room key didn't pass validation.
+    #[error("The MAC of the backed up room key didn't pass validation")]
+    Mac,
+    /// The backed up room key couldn't be decrypted.
+    #[error(transparent)]
+    Decryption(DecryptionError),
+    /// The decrypted room key isn't valid JSON.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The decrypted room key couldn't be turned into a group session.
+    #[error(transparent)]
+    SessionCreation(#[from] SessionCreationError),
+    /// The decrypted room key belongs to a different session than the one it
+    /// was stored under.
+    #[error("The session ID of the backed up room key doesn't match: expected {0}, got {1}")]
+    MismatchedSessionId(String, String),
+    /// The decrypted room key has the ID of a session we already have, but
+    /// isn't connected to it, e.g. because it claims to be from a different
+    /// sender. The backed up room key might have been forged and shouldn't
+    /// replace the session we have.
+    #[error("The backed up room key isn't connected to the session {0} we already have")]
+    UnconnectedSession(String),
+}
+
+impl From<DecryptionError> for RestoreError {
+    fn from(e: DecryptionError) -> Self {
+        match e {
+            DecryptionError::Mac(_) => Self::Mac,
+            e => Self::Decryption(e),
+        }
+    }
+}
+
+/// The outcome of restoring a single room key from a backup.
+#[derive(Debug)]
+pub enum RestoreOutcome {
+    /// We didn't have a session with this ID before, the restored session
+    /// should be stored.
+    New(InboundGroupSession),
+    /// The restored session is better than the one we already had, i.e. it can
+    /// decrypt older messages, and should replace it in the store.
+    Better(InboundGroupSession),
+    /// The session we already had is at least as good as the restored one,
+    /// nothing needs to be stored.
+    Redundant,
 }
 
 #[derive(Debug, Error)]
@@ -133,6 +194,27 @@ impl BackupDecryptionKey {
         }
     }
 
+    /// Try to create a [`BackupDecryptionKey`] from the `m.megolm_backup.v1`
+    /// secret stored in secret storage.
+    ///
+    /// The secret is the unpadded base64 encoding of the decryption key, any
+    /// surrounding whitespace and trailing padding is ignored. After
+    /// decoding, [`BackupDecryptionKey::megolm_v1_public_key()`] can be used to
+    /// check that the key matches the public key of the backup on the server
+    /// before restoring room keys from it.
+    pub fn from_secret_storage_base64(secret: &str) -> Result<Self, DecodeError> {
+        // The length of the unpadded base64 encoding of the key.
+        const SECRET_LENGTH: usize = (BackupDecryptionKey::KEY_SIZE * 4 + 2) / 3;
+
+        let secret = secret.trim().trim_end_matches('=');
+
+        if secret.len() != SECRET_LENGTH {
+            Err(DecodeError::SecretLength(SECRET_LENGTH, secret.len()))
+        } else {
+            Self::from_base64(secret)
+        }
+    }
+
     /// Try to create a [`BackupDecryptionKey`] from a base58 export.
     pub fn from_base58(value: &str) -> Result<Self, DecodeError> {
         // Remove any whitespace we might have
@@ -207,15 +289,82 @@ impl BackupDecryptionKey {
 
         Ok(String::from_utf8_lossy(&decrypted).to_string())
     }
+
+    /// Decrypt a room key from a backup and check it against the session we
+    /// may already have.
+    ///
+    /// This verifies the MAC of the backed up data, decrypts it, and makes
+    /// sure that the decrypted room key belongs to the session it was stored
+    /// under.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room the room key was backed up for.
+    ///
+    /// * `session_id` - The ID of the session the room key was backed up
+    /// under.
+    ///
+    /// * `data` - The encrypted session data of the backed up room key.
+    ///
+    /// * `existing` - The session with the same ID we already have in our
+    /// store, if any.
+    ///
+    /// Returns a [`RestoreOutcome`] telling the caller whether the restored
+    /// session needs to be saved. If the restored session isn't connected to
+    /// the existing one, a [`RestoreError::UnconnectedSession`] error is
+    /// returned, since the backed up room key might have been forged.
+    pub async fn decrypt_and_import(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+        data: &EncryptedSessionData,
+        existing: Option<&InboundGroupSession>,
+    ) -> Result<RestoreOutcome, RestoreError> {
+        let decrypted = Zeroizing::new(self.decrypt_v1(
+            &data.ephemeral.encode(),
+            &data.mac.encode(),
+            &data.ciphertext.encode(),
+        )?);
+
+        let room_key: BackedUpRoomKey = serde_json::from_str(&decrypted)?;
+        let session = InboundGroupSession::from_backup(room_id, room_key)?;
+
+        if session.session_id() != session_id {
+            return Err(RestoreError::MismatchedSessionId(
+                session_id.to_owned(),
+                session.session_id().to_owned(),
+            ));
+        }
+
+        // The session came from the backup, no need to back it up again.
+        session.mark_as_backed_up();
+
+        Ok(match existing {
+            None => RestoreOutcome::New(session),
+            Some(existing) => match session.compare(existing).await {
+                SessionOrdering::Better => RestoreOutcome::Better(session),
+                SessionOrdering::Equal | SessionOrdering::Worse => RestoreOutcome::Redundant,
+                SessionOrdering::Unconnected => {
+                    return Err(RestoreError::UnconnectedSession(session_id.to_owned()));
+                }
+            },
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use ruma::api::client::backup::KeyBackupData;
+    use assert_matches::assert_matches;
+    use matrix_sdk_test::async_test;
+    use ruma::{api::client::backup::KeyBackupData, device_id, room_id, serde::Base64, user_id};
     use serde_json::json;
+    use vodozemac::{Curve25519PublicKey, Curve25519SecretKey};
 
-    use super::{BackupDecryptionKey, DecodeError};
-    use crate::olm::BackedUpRoomKey;
+    use super::{BackupDecryptionKey, DecodeError, RestoreError, RestoreOutcome};
+    use crate::{
+        olm::{BackedUpRoomKey, InboundGroupSession},
+        ReadOnlyAccount,
+    };
 
     const TEST_KEY: [u8; 32] = [
         0x77, 0x07, 0x6D, 0x0A, 0x73, 0x18, 0xA5, 0x7D, 0x3C, 0x16, 0xC1, 0x72, 0x51, 0xB2, 0x66,
@@ -236,6 +385,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn secret_storage_decoding() -> Result<(), DecodeError> {
+        let key = BackupDecryptionKey::new().expect("Can't create a new recovery key");
+
+        let secret = key.to_base64();
+        let decoded_key = BackupDecryptionKey::from_secret_storage_base64(&secret)?;
+        assert_eq!(key.inner, decoded_key.inner, "The decode key doesn't match the original");
+        assert_eq!(
+            key.megolm_v1_public_key().to_base64(),
+            decoded_key.megolm_v1_public_key().to_base64()
+        );
+
+        let padded = format!(" {secret}=\n");
+        let decoded_key = BackupDecryptionKey::from_secret_storage_base64(&padded)?;
+        assert_eq!(key.inner, decoded_key.inner, "The padded secret should be accepted");
+
+        assert_matches!(
+            BackupDecryptionKey::from_secret_storage_base64(&secret[1..]),
+            Err(DecodeError::SecretLength(43, 42))
+        );
+        assert_matches!(
+            BackupDecryptionKey::from_secret_storage_base64(&format!("{}!", &secret[1..])),
+            Err(DecodeError::Base64(_))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn base58_decoding() -> Result<(), DecodeError> {
         let key = BackupDecryptionKey::new().expect("Can't create a new recovery key");
@@ -307,4 +484,62 @@ mod tests {
         let _: BackedUpRoomKey = serde_json::from_str(&decrypted)
             .expect("The decrypted payload should contain valid JSON");
     }
+
+    #[async_test]
+    async fn decrypt_and_import() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let room_id = room_id!("!test:localhost");
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+        let session_id = session.session_id().to_owned();
+
+        let decryption_key = BackupDecryptionKey::new().unwrap();
+        let backup_key = decryption_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+        let data = backup_key.encrypt(session.clone()).await.unwrap().session_data;
+
+        let outcome =
+            decryption_key.decrypt_and_import(room_id, &session_id, &data, None).await.unwrap();
+        let restored = assert_matches!(outcome, RestoreOutcome::New(s) => s);
+        assert_eq!(restored.session_id(), session_id);
+        assert!(restored.backed_up());
+
+        let outcome = decryption_key
+            .decrypt_and_import(room_id, &session_id, &data, Some(&session))
+            .await
+            .unwrap();
+        assert_matches!(outcome, RestoreOutcome::Redundant);
+
+        let worse = InboundGroupSession::from_export(&session.export_at_index(10).await).unwrap();
+        let outcome = decryption_key
+            .decrypt_and_import(room_id, &session_id, &data, Some(&worse))
+            .await
+            .unwrap();
+        assert_matches!(outcome, RestoreOutcome::Better(_));
+
+        // A session with the same ID that claims to be from another sender
+        // can't be compared with the backed up one.
+        let mut export = session.export().await;
+        export.sender_key = Curve25519PublicKey::from(&Curve25519SecretKey::new());
+        let unconnected = InboundGroupSession::from_export(&export).unwrap();
+        let error = decryption_key
+            .decrypt_and_import(room_id, &session_id, &data, Some(&unconnected))
+            .await
+            .unwrap_err();
+        assert_matches!(error, RestoreError::UnconnectedSession(id) if id == session_id);
+
+        let error = decryption_key
+            .decrypt_and_import(room_id, "wrong_session_id", &data, None)
+            .await
+            .unwrap_err();
+        assert_matches!(error, RestoreError::MismatchedSessionId(..));
+
+        let mut tampered = data.clone();
+        tampered.mac = Base64::new(vec![0u8; 8]);
+        let error = decryption_key
+            .decrypt_and_import(room_id, &session_id, &tampered, None)
+            .await
+            .unwrap_err();
+        assert_matches!(error, RestoreError::Mac);
+    }
 }
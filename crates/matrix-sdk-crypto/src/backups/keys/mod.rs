@@ -30,6 +30,9 @@
 //!    `BackupDecryptionKey`. This is used to encrypt room keys that get backed
 //!    up.
 //!
+//! The public key of any of the supported backup algorithms can be wrapped in
+//! a [`BackupKey`], to handle them independently of the algorithm.
+//!
 //! In theory, the `BackupDecryptionKey` can be derived from a passphrase.
 //! However, in practice deriving a decryption key from a passphrase isn't done,
 //! and is **not** supported by the spec.
@@ -51,6 +54,8 @@ mod backup;
 mod compat;
 mod decryption;
 
-pub use backup::MegolmV1BackupKey;
+pub use backup::{
+    BackupEncryptionError, BackupKey, BackupTrustState, KeyBackupDataPreview, MegolmV1BackupKey,
+};
 pub use compat::{Error as DecryptionError, MessageDecodeError};
-pub use decryption::DecodeError;
+pub use decryption::{DecodeError, RestoreError, RestoreOutcome};
@@ -28,9 +28,12 @@ use std::{
     sync::Arc,
 };
 
+use futures_core::Stream;
+use futures_util::stream;
 use ruma::{
-    api::client::backup::RoomKeyBackup, serde::Raw, DeviceId, DeviceKeyAlgorithm, OwnedDeviceId,
-    OwnedRoomId, OwnedTransactionId, TransactionId,
+    api::client::backup::{KeyBackupData, RoomKeyBackup},
+    serde::Raw,
+    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedRoomId, OwnedTransactionId, TransactionId,
 };
 use tokio::sync::RwLock;
 use tracing::{debug, info, instrument, trace, warn};
@@ -42,9 +45,14 @@ use crate::{
     CryptoStoreError, Device, KeysBackupRequest, OutgoingRequest,
 };
 
+mod batcher;
 mod keys;
 
-pub use keys::{DecodeError, DecryptionError, MegolmV1BackupKey};
+pub use batcher::{estimate_backup_size, estimate_backup_size_from_len, BackupBatcher};
+pub use keys::{
+    BackupEncryptionError, BackupKey, BackupTrustState, DecodeError, DecryptionError,
+    KeyBackupDataPreview, MegolmV1BackupKey, RestoreError, RestoreOutcome,
+};
 
 /// A state machine that handles backing up room keys.
 ///
@@ -56,8 +64,30 @@ pub use keys::{DecodeError, DecryptionError, MegolmV1BackupKey};
 pub struct BackupMachine {
     account: Account,
     store: Store,
-    backup_key: Arc<RwLock<Option<MegolmV1BackupKey>>>,
+    backup_key: Arc<RwLock<Option<BackupKey>>>,
     pending_backup: Arc<RwLock<Option<PendingBackup>>>,
+    max_first_index: Arc<RwLock<Option<u32>>>,
+    /// The room and session IDs of the room keys that the current backup key
+    /// can't back up, so they can be left out of the following batches.
+    ///
+    /// This is cleared before a backup once it holds
+    /// [`BackupMachine::MAX_SKIPPED_SESSIONS`] entries.
+    skipped_sessions: Arc<RwLock<BTreeSet<(OwnedRoomId, String)>>>,
+}
+
+/// The progress of restoring room keys from a backup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RestoreProgress {
+    /// The number of room keys that were decrypted so far.
+    pub decrypted: usize,
+    /// The total number of room keys in the backup.
+    pub total: usize,
+    /// The number of decrypted room keys that were stored so far, because we
+    /// didn't have them or because they're better than the ones we had.
+    pub imported: usize,
+    /// The number of room keys that couldn't be decrypted so far, or that were
+    /// rejected because they aren't connected to the session we already have.
+    pub failed: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -144,6 +174,10 @@ impl SignatureState {
 
 impl BackupMachine {
     const BACKUP_BATCH_SIZE: usize = 100;
+    const RESTORE_BATCH_SIZE: usize = 100;
+    /// The maximum number of room keys we remember as not backed up, see
+    /// `skipped_sessions`.
+    const MAX_SKIPPED_SESSIONS: usize = 10 * Self::BACKUP_BATCH_SIZE;
 
     pub(crate) fn new(
         account: Account,
@@ -153,8 +187,10 @@ impl BackupMachine {
         Self {
             account,
             store,
-            backup_key: RwLock::new(backup_key).into(),
+            backup_key: RwLock::new(backup_key.map(BackupKey::from)).into(),
             pending_backup: RwLock::new(None).into(),
+            max_first_index: RwLock::new(None).into(),
+            skipped_sessions: RwLock::new(BTreeSet::new()).into(),
         }
     }
 
@@ -367,7 +403,8 @@ impl BackupMachine {
     /// https://spec.matrix.org/unstable/client-server-api/#backup-algorithm-mmegolm_backupv1curve25519-aes-sha2
     pub async fn enable_backup_v1(&self, key: MegolmV1BackupKey) -> Result<(), CryptoStoreError> {
         if key.backup_version().is_some() {
-            *self.backup_key.write().await = Some(key.clone());
+            *self.backup_key.write().await = Some(key.clone().into());
+            self.skipped_sessions.write().await.clear();
             info!(backup_key = ?key, "Activated a backup");
         } else {
             warn!(backup_key = ?key, "Tried to activate a backup without having the backup key uploaded");
@@ -391,6 +428,7 @@ impl BackupMachine {
 
         self.backup_key.write().await.take();
         self.pending_backup.write().await.take();
+        self.skipped_sessions.write().await.clear();
 
         self.store.reset_backup_state().await?;
 
@@ -399,6 +437,53 @@ impl BackupMachine {
         Ok(())
     }
 
+    /// Reset the backup state of all our room keys if the backup version
+    /// changed.
+    ///
+    /// The given `new_version` is compared to the backup version we have
+    /// stored in the crypto store, if they differ, the backed up flag of all
+    /// our room keys is reset, since they haven't been uploaded to the new
+    /// backup, and the new version is stored. Nothing is reset if the version
+    /// didn't change, so the room keys don't need to be uploaded again.
+    ///
+    /// Any pending backup request that was created for a backup key with a
+    /// different version is discarded as well.
+    ///
+    /// Returns the number of room keys that were marked as backed up and whose
+    /// backup state got reset.
+    #[instrument(skip(self))]
+    pub async fn reset_all_backup_state_for_version_change(
+        &self,
+        new_version: &str,
+    ) -> Result<usize, CryptoStoreError> {
+        // Hold the lock on the pending request, so no backup request can be
+        // created or marked as sent while we reset the backup state.
+        let mut pending_backup = self.pending_backup.write().await;
+
+        let stored_version = self.store.load_backup_keys().await?.backup_version;
+
+        if stored_version.as_deref() == Some(new_version) {
+            trace!("The backup version didn't change, not resetting the backup state");
+            return Ok(0);
+        }
+
+        debug!(?stored_version, "The backup version changed, resetting the backup state");
+
+        let active_version =
+            self.backup_key.read().await.as_ref().and_then(|key| key.backup_version());
+
+        if active_version.as_deref() != Some(new_version) {
+            pending_backup.take();
+        }
+
+        let reset_count =
+            self.store.reset_backup_state_for_new_version(new_version.to_owned()).await?;
+
+        debug!(reset_count, "Done resetting the backup state");
+
+        Ok(reset_count)
+    }
+
     /// Store the backup decryption key in the crypto store.
     ///
     /// This is useful if the client wants to support gossiping of the backup
@@ -418,6 +503,127 @@ impl BackupMachine {
         self.store.load_backup_keys().await
     }
 
+    /// Set the largest first known message index a room key may have to be
+    /// included in a backup.
+    ///
+    /// Some servers refuse room keys that don't start at, or close to, the
+    /// beginning of the session. Room keys with a larger first known index
+    /// are skipped by [`BackupMachine::backup()`], `None` removes the limit.
+    ///
+    /// **Note**: Skipped room keys aren't marked as backed up, they will keep
+    /// being counted in [`BackupMachine::room_key_counts()`]. They are left
+    /// out of the following batches until the limit or the backup key
+    /// changes, or until too many of them accumulated, in which case they are
+    /// checked again.
+    pub async fn set_max_first_index(&self, max_first_index: Option<u32>) {
+        *self.max_first_index.write().await = max_first_index;
+        self.skipped_sessions.write().await.clear();
+    }
+
+    /// Restore the room keys of the given backup, and report the progress as a
+    /// stream.
+    ///
+    /// The room keys are decrypted and stored in batches, the stream yields
+    /// the progress after each batch was saved, so its last item contains the
+    /// totals of the whole restore. A room key is only stored if we don't
+    /// have a better copy of it, see [`Store::upsert_if_better()`]. Room keys
+    /// that can't be decrypted are counted as failed and skipped, the stream
+    /// ends early with an error if the store fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `decryption_key` - The key that should be used to decrypt the backup.
+    ///
+    /// * `backup` - The backed up room keys, as returned by the server for
+    /// each room.
+    pub fn restore_backup(
+        &self,
+        decryption_key: BackupDecryptionKey,
+        backup: BTreeMap<OwnedRoomId, RoomKeyBackup>,
+    ) -> impl Stream<Item = Result<RestoreProgress, CryptoStoreError>> {
+        let total = backup.values().map(|room| room.sessions.len()).sum();
+        let progress = RestoreProgress { total, ..Default::default() };
+        let room_keys = backup.into_iter().flat_map(|(room_id, room_backup)| {
+            room_backup
+                .sessions
+                .into_iter()
+                .map(move |(session_id, data)| (room_id.clone(), session_id, data))
+        });
+        let state = (self.store.clone(), decryption_key, room_keys, progress);
+
+        stream::unfold(Some(state), |state| async move {
+            let (store, decryption_key, mut room_keys, mut progress) = state?;
+            let batch: Vec<_> = room_keys.by_ref().take(Self::RESTORE_BATCH_SIZE).collect();
+
+            if batch.is_empty() {
+                return None;
+            }
+
+            match Self::restore_batch(&store, &decryption_key, batch, &mut progress).await {
+                Ok(()) => Some((Ok(progress), Some((store, decryption_key, room_keys, progress)))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    async fn restore_batch(
+        store: &Store,
+        decryption_key: &BackupDecryptionKey,
+        batch: Vec<(OwnedRoomId, String, Raw<KeyBackupData>)>,
+        progress: &mut RestoreProgress,
+    ) -> Result<(), CryptoStoreError> {
+        let mut sessions = Vec::new();
+
+        for (room_id, session_id, data) in batch {
+            let data = match data.deserialize() {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!(
+                        ?room_id,
+                        session_id,
+                        error = ?e,
+                        "Couldn't deserialize a backed up room key"
+                    );
+                    progress.failed += 1;
+                    continue;
+                }
+            };
+
+            let existing = store.get_inbound_group_session(&room_id, &session_id).await?;
+
+            match decryption_key
+                .decrypt_and_import(&room_id, &session_id, &data.session_data, existing.as_ref())
+                .await
+            {
+                Ok(RestoreOutcome::New(session) | RestoreOutcome::Better(session)) => {
+                    progress.decrypted += 1;
+                    sessions.push(session);
+                }
+                Ok(RestoreOutcome::Redundant) => progress.decrypted += 1,
+                Err(e) => {
+                    warn!(
+                        ?room_id,
+                        session_id,
+                        error = ?e,
+                        "Couldn't restore a backed up room key"
+                    );
+                    progress.failed += 1;
+                }
+            }
+        }
+
+        if !sessions.is_empty() {
+            // The comparison above was done without holding the upsert lock, a
+            // better copy of a room key might have been stored in the meantime.
+            let changes = Changes { inbound_group_sessions: sessions, ..Default::default() };
+            let outcomes = store.save_changes_if_better(changes).await?;
+
+            progress.imported += outcomes.iter().filter(|o| o.was_written()).count();
+        }
+
+        Ok(())
+    }
+
     /// Encrypt a batch of room keys and return a request that needs to be sent
     /// out to backup the room keys.
     pub async fn backup(
@@ -455,14 +661,9 @@ impl BackupMachine {
                     .filter(|s| r.session_was_part_of_the_backup(s))
                     .collect();
 
-                for session in &sessions {
-                    session.mark_as_backed_up();
-                }
-
                 trace!(request_id = ?r.request_id, keys = ?r.sessions, "Marking room keys as backed up");
 
-                let changes = Changes { inbound_group_sessions: sessions, ..Default::default() };
-                self.store.save_changes(changes).await?;
+                self.store.mark_as_backed_up(sessions).await?;
 
                 let counts = self.store.inbound_group_session_counts().await?;
 
@@ -500,16 +701,52 @@ impl BackupMachine {
             return Ok(None);
         };
 
-        let sessions =
-            self.store.inbound_group_sessions_for_backup(Self::BACKUP_BATCH_SIZE).await?;
+        let max_first_index = *self.max_first_index.read().await;
+        let mut skipped_sessions = self.skipped_sessions.write().await;
 
-        if sessions.is_empty() {
-            trace!(?backup_key, "No room keys need to be backed up");
-            return Ok(None);
+        // Don't let the skipped room keys pile up between backups, we would
+        // have to fetch all of them from the store for every batch. Forgetting
+        // them only means that they are checked once more.
+        if skipped_sessions.len() >= Self::MAX_SKIPPED_SESSIONS {
+            debug!(count = skipped_sessions.len(), "Forgetting the skipped room keys");
+            skipped_sessions.clear();
         }
 
-        let key_count = sessions.len();
-        let (backup, session_record) = Self::backup_keys(sessions, backup_key).await;
+        // Room keys we can't back up are never marked as backed up, so the
+        // store keeps returning them. Fetch enough room keys to fill a batch
+        // after the skipped ones are left out, and keep going until a batch
+        // contains a room key we can back up.
+        let (backup, session_record) = loop {
+            let limit = Self::BACKUP_BATCH_SIZE + skipped_sessions.len();
+            let sessions: Vec<_> = self
+                .store
+                .inbound_group_sessions_for_backup(limit)
+                .await?
+                .into_iter()
+                .filter(|s| {
+                    !skipped_sessions.contains(&(s.room_id().to_owned(), s.session_id().to_owned()))
+                })
+                .take(Self::BACKUP_BATCH_SIZE)
+                .collect();
+
+            if sessions.is_empty() {
+                trace!(?backup_key, "No room keys need to be backed up");
+                return Ok(None);
+            }
+
+            let (backup, session_record) =
+                Self::backup_keys(sessions, backup_key, max_first_index, &mut skipped_sessions)
+                    .await;
+
+            if backup.is_empty() {
+                trace!(?backup_key, "None of the room keys in the batch can be backed up");
+            } else {
+                break (backup, session_record);
+            }
+        };
+
+        let key_count: usize =
+            session_record.values().flat_map(|s| s.values()).map(|s| s.len()).sum();
 
         info!(
             key_count = key_count,
@@ -528,9 +765,14 @@ impl BackupMachine {
     }
 
     /// Backup all the non-backed up room keys we know about
+    ///
+    /// The room and session IDs of the room keys that can't be backed up are
+    /// added to `skipped_sessions`.
     async fn backup_keys(
         sessions: Vec<InboundGroupSession>,
-        backup_key: &MegolmV1BackupKey,
+        backup_key: &BackupKey,
+        max_first_index: Option<u32>,
+        skipped_sessions: &mut BTreeSet<(OwnedRoomId, String)>,
     ) -> (
         BTreeMap<OwnedRoomId, RoomKeyBackup>,
         BTreeMap<OwnedRoomId, BTreeMap<String, BTreeSet<String>>>,
@@ -543,7 +785,24 @@ impl BackupMachine {
             let room_id = session.room_id().to_owned();
             let session_id = session.session_id().to_owned();
             let sender_key = session.sender_key().to_owned();
-            let session = backup_key.encrypt(session).await;
+            let session = match backup_key.encrypt_with_policy(session, max_first_index).await {
+                Ok(Some(s)) => s,
+                Ok(None) => {
+                    debug!(
+                        ?room_id,
+                        session_id,
+                        ?max_first_index,
+                        "Not backing up a room key, its first known index is too large"
+                    );
+                    skipped_sessions.insert((room_id, session_id));
+                    continue;
+                }
+                Err(e) => {
+                    warn!(?room_id, session_id, error = ?e, "Can't back up a room key");
+                    skipped_sessions.insert((room_id, session_id));
+                    continue;
+                }
+            };
 
             session_record
                 .entry(room_id.to_owned())
@@ -567,11 +826,17 @@ impl BackupMachine {
 
 #[cfg(test)]
 mod tests {
+    use futures_util::{FutureExt, StreamExt};
     use matrix_sdk_test::async_test;
-    use ruma::{device_id, room_id, user_id, CanonicalJsonValue, DeviceId, RoomId, UserId};
+    use ruma::{
+        device_id, room_id, serde::Raw, user_id, CanonicalJsonValue, DeviceId, RoomId, UserId,
+    };
     use serde_json::json;
 
-    use crate::{store::BackupDecryptionKey, types::RoomKeyBackupInfo, OlmError, OlmMachine};
+    use super::{BackupMachine, RestoreProgress};
+    use crate::{
+        store::BackupDecryptionKey, types::RoomKeyBackupInfo, OlmError, OlmMachine, ReadOnlyAccount,
+    };
 
     fn alice_id() -> &'static UserId {
         user_id!("@alice:example.org")
@@ -640,6 +905,162 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn reset_backup_state_for_version_change() -> Result<(), OlmError> {
+        let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
+        let backup_machine = machine.backup_machine();
+
+        machine.create_outbound_group_session_with_defaults(room_id()).await?;
+        machine.create_outbound_group_session_with_defaults(room_id2()).await?;
+
+        let decryption_key = BackupDecryptionKey::new().expect("Can't create new recovery key");
+        let backup_key = decryption_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+
+        backup_machine.save_decryption_key(None, Some("1".to_owned())).await?;
+        backup_machine.enable_backup_v1(backup_key).await?;
+
+        let (request_id, _) =
+            backup_machine.backup().await?.expect("Created a backup request successfully");
+        backup_machine.mark_request_as_sent(&request_id).await?;
+
+        let counts = backup_machine.store.inbound_group_session_counts().await?;
+        assert_eq!(counts.backed_up, 2, "All room keys have been backed up");
+
+        assert_eq!(
+            backup_machine.reset_all_backup_state_for_version_change("1").await?,
+            0,
+            "The backup version didn't change, nothing should be reset"
+        );
+        let counts = backup_machine.store.inbound_group_session_counts().await?;
+        assert_eq!(counts.backed_up, 2);
+
+        let mut stream = Box::pin(backup_machine.store.backup_state_stream());
+        assert_eq!(backup_machine.reset_all_backup_state_for_version_change("2").await?, 2);
+        for _ in 0..2 {
+            let (_, _, backed_up) =
+                stream.next().now_or_never().flatten().expect("The reset should be reported");
+            assert!(!backed_up);
+        }
+        assert!(stream.next().now_or_never().is_none());
+
+        let counts = backup_machine.store.inbound_group_session_counts().await?;
+        assert_eq!(counts.backed_up, 0, "The room keys need to be backed up to the new backup");
+        assert_eq!(
+            backup_machine.get_backup_keys().await?.backup_version.as_deref(),
+            Some("2"),
+            "The new backup version should be stored"
+        );
+
+        assert_eq!(backup_machine.reset_all_backup_state_for_version_change("2").await?, 0);
+
+        Ok(())
+    }
+
+    #[async_test]
+    #[cfg(feature = "experimental-algorithms")]
+    async fn backup_skips_a_full_batch_of_incompatible_room_keys() -> Result<(), OlmError> {
+        use crate::{olm::EncryptionSettings, types::EventEncryptionAlgorithm};
+
+        let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
+        let backup_machine = machine.backup_machine();
+        let account = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+
+        let mut incompatible = Vec::new();
+
+        for _ in 0..BackupMachine::BACKUP_BATCH_SIZE {
+            let settings = EncryptionSettings {
+                algorithm: EventEncryptionAlgorithm::MegolmV2AesSha2,
+                ..Default::default()
+            };
+            let (_, session) =
+                account.create_group_session_pair(room_id(), settings).await.unwrap();
+            incompatible.push(session);
+        }
+
+        machine.store().save_inbound_group_sessions(&incompatible).await?;
+
+        let backup_key = BackupDecryptionKey::new().expect("Can't create new recovery key");
+        let backup_key = backup_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+        backup_machine.enable_backup_v1(backup_key).await?;
+
+        assert!(
+            backup_machine.backup().await?.is_none(),
+            "None of the room keys can be backed up with a megolm.v1 backup key"
+        );
+
+        let (_, compatible) = account.create_group_session_pair_with_defaults(room_id()).await;
+        machine.store().save_inbound_group_sessions(&[compatible.clone()]).await?;
+
+        let (request_id, request) = backup_machine
+            .backup()
+            .await?
+            .expect("The compatible room key should be backed up despite the skipped ones");
+
+        let sessions = &request.rooms[room_id()].sessions;
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions.contains_key(compatible.session_id()));
+
+        backup_machine.mark_request_as_sent(&request_id).await?;
+        assert!(backup_machine.backup().await?.is_none());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn backup_skips_a_full_batch_of_room_keys_above_the_max_first_index(
+    ) -> Result<(), OlmError> {
+        let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
+        let backup_machine = machine.backup_machine();
+        let account = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+
+        let mut late = Vec::new();
+
+        for _ in 0..BackupMachine::BACKUP_BATCH_SIZE {
+            let (outbound, _) = account.create_group_session_pair_with_defaults(room_id()).await;
+            outbound.encrypt_helper("Hello".to_owned()).await;
+            late.push(outbound.create_matching_inbound().await.unwrap());
+        }
+
+        machine.store().save_inbound_group_sessions(&late).await?;
+
+        let backup_key = BackupDecryptionKey::new().expect("Can't create new recovery key");
+        let backup_key = backup_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+        backup_machine.enable_backup_v1(backup_key).await?;
+        backup_machine.set_max_first_index(Some(0)).await;
+
+        assert!(
+            backup_machine.backup().await?.is_none(),
+            "None of the room keys start at the first message index"
+        );
+
+        let (_, early) = account.create_group_session_pair_with_defaults(room_id()).await;
+        machine.store().save_inbound_group_sessions(&[early.clone()]).await?;
+
+        let (request_id, request) = backup_machine
+            .backup()
+            .await?
+            .expect("The room key starting at index 0 should be backed up");
+
+        let sessions = &request.rooms[room_id()].sessions;
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions.contains_key(early.session_id()));
+
+        backup_machine.mark_request_as_sent(&request_id).await?;
+        assert!(backup_machine.backup().await?.is_none());
+
+        // Lifting the limit makes the skipped room keys eligible again.
+        backup_machine.set_max_first_index(None).await;
+
+        let (_, request) =
+            backup_machine.backup().await?.expect("The skipped room keys should be backed up");
+        assert_eq!(request.rooms[room_id()].sessions.len(), BackupMachine::BACKUP_BATCH_SIZE);
+
+        Ok(())
+    }
+
     #[async_test]
     async fn memory_store_backups() -> Result<(), OlmError> {
         let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
@@ -725,4 +1146,56 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_test]
+    async fn restore_backup_progress() {
+        let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
+        machine.create_outbound_group_session_with_defaults(room_id()).await.unwrap();
+        machine.create_outbound_group_session_with_defaults(room_id2()).await.unwrap();
+        let sessions = machine.store().get_inbound_group_sessions().await.unwrap();
+
+        let decryption_key = BackupDecryptionKey::new().expect("Can't create new recovery key");
+        let backup_key = decryption_key.megolm_v1_public_key();
+        backup_key.set_version("1".to_owned());
+        let (mut backup, _) =
+            BackupMachine::backup_keys(sessions, &backup_key.into(), None, &mut Default::default())
+                .await;
+
+        let garbage = Raw::from_json(serde_json::value::to_raw_value(&json!({})).unwrap());
+        backup
+            .get_mut(room_id())
+            .expect("The first room should be part of the backup")
+            .sessions
+            .insert("garbage".to_owned(), garbage);
+
+        let other = OlmMachine::new(alice_id(), device_id!("OTHERDEVICE")).await;
+        let backup_machine = other.backup_machine();
+
+        let progress: Vec<_> = backup_machine
+            .restore_backup(decryption_key.clone(), backup.clone())
+            .map(|p| p.expect("Restoring the backup should work"))
+            .collect()
+            .await;
+
+        assert_eq!(progress.len(), 1, "All the room keys should fit into a single batch");
+        assert_eq!(
+            progress.last(),
+            Some(&RestoreProgress { decrypted: 2, total: 3, imported: 2, failed: 1 })
+        );
+
+        let counts = other.store().inbound_group_session_counts().await.unwrap();
+        assert_eq!(counts.total, 2);
+
+        let progress: Vec<_> = backup_machine
+            .restore_backup(decryption_key, backup)
+            .map(|p| p.expect("Restoring the backup should work"))
+            .collect()
+            .await;
+
+        assert_eq!(
+            progress.last(),
+            Some(&RestoreProgress { decrypted: 2, total: 3, imported: 0, failed: 1 }),
+            "Restoring the same backup twice shouldn't import anything"
+        );
+    }
 }
@@ -284,4 +284,13 @@ pub enum SessionCreationError {
     /// Error when creating an Olm Session from an incoming Olm message.
     #[error(transparent)]
     InboundCreation(#[from] vodozemac::olm::SessionCreationError),
+
+    /// The session ID of an exported room key doesn't match its session key.
+    #[error("The session ID {claimed} doesn't match the session key, which has the ID {actual}")]
+    SessionIdMismatch {
+        /// The session ID found in the room key export.
+        claimed: String,
+        /// The session ID of the session key.
+        actual: String,
+    },
 }
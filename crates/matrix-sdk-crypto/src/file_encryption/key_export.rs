@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::{
+    io::{Cursor, Read, Seek, SeekFrom},
+    iter,
+};
 
 use aes::{
     cipher::{generic_array::GenericArray, KeyIvInit, StreamCipher},
@@ -42,6 +45,9 @@ const VERSION: u8 = 1;
 
 const HEADER: &str = "-----BEGIN MEGOLM SESSION DATA-----";
 const FOOTER: &str = "-----END MEGOLM SESSION DATA-----";
+/// The number of base64 characters per line of an armored key export, this
+/// matches the line length used by Element.
+const LINE_LENGTH: usize = 96;
 
 /// Error representing a failure during key export or import.
 #[derive(Error, Debug)]
@@ -97,14 +103,8 @@ pub fn decrypt_room_key_export(
 
     input.read_to_string(&mut x)?;
 
-    if !(x.trim_start().starts_with(HEADER) && x.trim_end().ends_with(FOOTER)) {
-        return Err(KeyExportError::InvalidHeaders);
-    }
-
-    let payload: String =
-        x.lines().filter(|l| !(l.starts_with(HEADER) || l.starts_with(FOOTER))).collect();
-
-    let mut decrypted = decrypt_helper(&payload, passphrase)?;
+    let payload = decode_megolm_export_armor(&x)?;
+    let mut decrypted = decrypt_helper(payload, passphrase)?;
 
     let ret = serde_json::from_str(&decrypted);
 
@@ -156,10 +156,65 @@ pub fn encrypt_room_key_export(
 
     plaintext.zeroize();
 
-    Ok([HEADER.to_owned(), ciphertext, FOOTER.to_owned()].join("\n"))
+    Ok(encode_megolm_export_armor(&ciphertext))
+}
+
+/// Wrap an encrypted key export in the armor of the key export file format.
+///
+/// The encrypted payload is encoded as base64, split into lines and surrounded
+/// by the `-----BEGIN MEGOLM SESSION DATA-----` header and the
+/// `-----END MEGOLM SESSION DATA-----` footer.
+///
+/// This is the format [`encrypt_room_key_export()`] produces, and that other
+/// clients, like Element, use for their key export files.
+pub fn encode_megolm_export_armor(encrypted: &[u8]) -> String {
+    let payload = encode(encrypted);
+
+    let lines = payload
+        .as_bytes()
+        .chunks(LINE_LENGTH)
+        .map(|line| std::str::from_utf8(line).expect("Base64 is always valid ASCII"));
+
+    iter::once(HEADER).chain(lines).chain(iter::once(FOOTER)).collect::<Vec<_>>().join("\n")
+}
+
+/// Extract the encrypted payload out of the armor of a key export file, see
+/// [`encode_megolm_export_armor()`].
+///
+/// Both LF and CRLF line endings are accepted, leading and trailing
+/// whitespace of every line, as well as empty lines, are ignored.
+///
+/// Returns [`KeyExportError::InvalidHeaders`] if the header or the footer is
+/// missing, or if anything but whitespace follows the footer, and
+/// [`KeyExportError::Decode`] if the payload isn't valid base64.
+pub fn decode_megolm_export_armor(armored: &str) -> Result<Vec<u8>, KeyExportError> {
+    let mut lines = armored.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    if lines.next() != Some(HEADER) {
+        return Err(KeyExportError::InvalidHeaders);
+    }
+
+    let mut payload = String::new();
+    let mut found_footer = false;
+
+    for line in lines {
+        if found_footer {
+            return Err(KeyExportError::InvalidHeaders);
+        } else if line == FOOTER {
+            found_footer = true;
+        } else {
+            payload.push_str(line);
+        }
+    }
+
+    if !found_footer {
+        return Err(KeyExportError::InvalidHeaders);
+    }
+
+    Ok(decode(payload)?)
 }
 
-fn encrypt_helper(plaintext: &mut [u8], passphrase: &str, rounds: u32) -> String {
+fn encrypt_helper(plaintext: &mut [u8], passphrase: &str, rounds: u32) -> Vec<u8> {
     let mut salt = [0u8; SALT_SIZE];
     let mut iv = [0u8; IV_SIZE];
     let mut derived_keys = [0u8; KEY_SIZE * 2];
@@ -199,13 +254,11 @@ fn encrypt_helper(plaintext: &mut [u8], passphrase: &str, rounds: u32) -> String
 
     derived_keys.zeroize();
 
-    encode(payload)
+    payload
 }
 
-fn decrypt_helper(ciphertext: &str, passphrase: &str) -> Result<String, KeyExportError> {
-    let decoded = decode(ciphertext)?;
-
-    let mut decoded = Cursor::new(decoded);
+fn decrypt_helper(payload: Vec<u8>, passphrase: &str) -> Result<String, KeyExportError> {
+    let mut decoded = Cursor::new(payload);
 
     let mut salt = [0u8; SALT_SIZE];
     let mut iv = [0u8; IV_SIZE];
@@ -265,7 +318,7 @@ mod proptests {
             let mut plaintext_bytes = plaintext.clone().into_bytes();
 
             let ciphertext = encrypt_helper(&mut plaintext_bytes, "test", 1);
-            let decrypted = decrypt_helper(&ciphertext, "test").unwrap();
+            let decrypted = decrypt_helper(ciphertext, "test").unwrap();
 
             prop_assert!(plaintext == decrypted);
         }
@@ -279,12 +332,14 @@ mod tests {
         io::Cursor,
     };
 
+    use assert_matches::assert_matches;
     use indoc::indoc;
     use matrix_sdk_test::async_test;
     use ruma::{room_id, user_id};
 
     use super::{
-        decode, decrypt_helper, decrypt_room_key_export, encrypt_helper, encrypt_room_key_export,
+        decode, decode_megolm_export_armor, decrypt_helper, decrypt_room_key_export,
+        encode_megolm_export_armor, encrypt_helper, encrypt_room_key_export, KeyExportError,
     };
     use crate::{error::OlmResult, machine::tests::get_prepared_machine, RoomKeyImportResult};
 
@@ -317,13 +372,59 @@ mod tests {
         decode(export).unwrap();
     }
 
+    #[test]
+    fn test_armor_roundtrip() {
+        let payload: Vec<u8> = (0..=255).collect();
+
+        let armored = encode_megolm_export_armor(&payload);
+        let lines: Vec<_> = armored.lines().collect();
+        assert_eq!(lines.first(), Some(&"-----BEGIN MEGOLM SESSION DATA-----"));
+        assert_eq!(lines.last(), Some(&"-----END MEGOLM SESSION DATA-----"));
+        assert!(lines.iter().all(|line| line.len() <= 96));
+
+        assert_eq!(decode_megolm_export_armor(&armored).unwrap(), payload);
+
+        // Windows line endings, indentation and trailing whitespace are fine.
+        let mangled = format!("\r\n{}\r\n\r\n", armored.replace('\n', " \r\n  "));
+        assert_eq!(decode_megolm_export_armor(&mangled).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_armor_invalid() {
+        let armored = encode_megolm_export_armor(b"It's a secret to everybody");
+
+        let without_header = armored.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_matches!(
+            decode_megolm_export_armor(&without_header),
+            Err(KeyExportError::InvalidHeaders)
+        );
+
+        let without_footer = armored.trim_end_matches("-----END MEGOLM SESSION DATA-----");
+        assert_matches!(
+            decode_megolm_export_armor(without_footer),
+            Err(KeyExportError::InvalidHeaders)
+        );
+
+        let trailing_data = format!("{armored}\nAAAA");
+        assert_matches!(
+            decode_megolm_export_armor(&trailing_data),
+            Err(KeyExportError::InvalidHeaders)
+        );
+
+        let invalid_base64 = armored.replacen('\n', "\n!!!!", 1);
+        assert_matches!(
+            decode_megolm_export_armor(&invalid_base64),
+            Err(KeyExportError::Decode(_))
+        );
+    }
+
     #[test]
     fn test_encrypt_decrypt() {
         let data = "It's a secret to everybody";
         let mut bytes = data.to_owned().into_bytes();
 
         let encrypted = encrypt_helper(&mut bytes, PASSPHRASE, 10);
-        let decrypted = decrypt_helper(&encrypted, PASSPHRASE).unwrap();
+        let decrypted = decrypt_helper(encrypted, PASSPHRASE).unwrap();
 
         assert_eq!(data, decrypted);
     }
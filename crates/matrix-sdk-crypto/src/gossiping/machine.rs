@@ -900,9 +900,14 @@ impl GossipMachine {
         &self,
         info: &GossipRequest,
         sender_key: Curve25519PublicKey,
+        forwarded_by_verified_device: bool,
         event: &DecryptedForwardedRoomKeyEvent,
     ) -> Result<Option<InboundGroupSession>, CryptoStoreError> {
-        match InboundGroupSession::try_from(event) {
+        match InboundGroupSession::from_forwarded_room_key(
+            sender_key,
+            event,
+            forwarded_by_verified_device,
+        ) {
             Ok(session) => {
                 if self.inner.store.compare_group_session(&session).await?
                     == SessionOrdering::Better
@@ -939,19 +944,9 @@ impl GossipMachine {
         }
     }
 
-    async fn should_accept_forward(
-        &self,
-        info: &GossipRequest,
-        sender_key: Curve25519PublicKey,
-    ) -> Result<bool, CryptoStoreError> {
-        let device =
-            self.inner.store.get_device_from_curve_key(&info.request_recipient, sender_key).await?;
-
-        if let Some(device) = device {
-            Ok(device.user_id() == self.user_id() && device.is_verified())
-        } else {
-            Ok(false)
-        }
+    /// Should we accept a room key that the given device forwarded to us?
+    fn should_accept_forward(&self, forwarder: &Device) -> bool {
+        forwarder.user_id() == self.user_id() && forwarder.is_verified()
     }
 
     /// Receive a forwarded room key event that was sent using any of our
@@ -984,8 +979,25 @@ impl GossipMachine {
             return Ok(None);
         };
 
-        if self.should_accept_forward(&request, sender_key).await? {
-            self.accept_forwarded_room_key(&request, sender_key, event).await
+        let forwarder = self
+            .inner
+            .store
+            .get_device_from_curve_key(&request.request_recipient, sender_key)
+            .await?;
+
+        // Record the verification state of the forwarder independently of the
+        // decision to accept the room key, the session keeps it for later
+        // trust decisions.
+        let forwarded_by_verified_device = forwarder.as_ref().is_some_and(|d| d.is_verified());
+
+        if forwarder.is_some_and(|d| self.should_accept_forward(&d)) {
+            self.accept_forwarded_room_key(
+                &request,
+                sender_key,
+                forwarded_by_verified_device,
+                event,
+            )
+            .await
         } else {
             warn!(
                 ?sender_key,
@@ -1013,12 +1025,12 @@ impl GossipMachine {
             ForwardedRoomKeyContent::MegolmV2AesSha2(_) => {
                 self.receive_supported_keys(sender_key, event).await
             }
-            ForwardedRoomKeyContent::Unknown(_) => {
+            ForwardedRoomKeyContent::Unknown(ref c) => {
                 warn!(
                     sender = event.sender.as_str(),
                     sender_key = sender_key.to_base64(),
-                    algorithm = ?event.content.algorithm(),
-                    "Received a forwarded room key with an unsupported algorithm",
+                    algorithm = %c.algorithm,
+                    "Rejected a forwarded room key with an unsupported algorithm",
                 );
 
                 Ok(None)
@@ -1052,7 +1064,7 @@ mod tests {
     #[cfg(feature = "automatic-room-key-forwarding")]
     use crate::{
         gossiping::KeyForwardDecision,
-        olm::OutboundGroupSession,
+        olm::{KeySourceKind, OutboundGroupSession},
         types::{
             events::{
                 forwarded_room_key::ForwardedRoomKeyContent, olm_v1::AnyDecryptedOlmEvent,
@@ -1446,6 +1458,64 @@ mod tests {
         assert_eq!(second_session.unwrap().first_known_index(), 0);
     }
 
+    #[async_test]
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    async fn received_forwarded_key_records_the_forwarder() {
+        let machine = get_machine().await;
+        let account = account();
+
+        let second_account = alice_2_account();
+        let alice_device = ReadOnlyDevice::from_account(&second_account).await;
+        let forwarder_key = alice_device.curve25519_key().unwrap();
+
+        alice_device.set_trust_state(LocalTrust::Verified);
+        machine.inner.store.save_devices(&[alice_device.clone()]).await.unwrap();
+
+        let (outbound, session) = account.create_group_session_pair_with_defaults(room_id()).await;
+        let content = outbound.encrypt(json!({}), "m.dummy").await;
+        let room_event = wrap_encrypted_content(machine.user_id(), content);
+
+        machine.create_outgoing_key_request(session.room_id(), &room_event).await.unwrap();
+
+        let requests = machine.outgoing_to_device_requests().await.unwrap();
+        machine.mark_outgoing_request_as_sent(&requests[0].request_id).await.unwrap();
+
+        // The session is forwarded to us by its creator, the chain in the
+        // content is empty.
+        let export = session.export_at_index(0).await;
+        assert!(export.forwarding_curve25519_key_chain.is_empty());
+
+        let content: ForwardedRoomKeyContent = export.try_into().unwrap();
+        let event = DecryptedOlmV1Event::new(
+            alice_id(),
+            alice_id(),
+            alice_device.ed25519_key().unwrap(),
+            content,
+        );
+
+        let received =
+            machine.receive_forwarded_room_key(forwarder_key, &event).await.unwrap().unwrap();
+
+        assert_eq!(received.forwarding_chain(), [forwarder_key]);
+        assert_eq!(received.key_source_kind(), KeySourceKind::Forwarded);
+        assert!(received.forwarded_by_verified_device());
+
+        machine.inner.store.save_inbound_group_sessions(&[received]).await.unwrap();
+
+        let stored = machine
+            .inner
+            .store
+            .get_inbound_group_session(session.room_id(), session.session_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.forwarding_chain(), [forwarder_key]);
+
+        let stats = machine.inner.store.session_stats().await.unwrap();
+        assert_eq!(stats.forwarded, 1);
+        assert_eq!(stats.imported, 0);
+    }
+
     #[async_test]
     #[cfg(feature = "automatic-room-key-forwarding")]
     async fn should_share_key_test() {
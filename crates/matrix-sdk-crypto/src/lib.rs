@@ -71,8 +71,9 @@ impl RoomKeyImportResult {
 
 pub use error::{EventError, MegolmError, OlmError, SessionCreationError, SignatureError};
 pub use file_encryption::{
-    decrypt_room_key_export, encrypt_room_key_export, AttachmentDecryptor, AttachmentEncryptor,
-    DecryptorError, KeyExportError, MediaEncryptionInfo,
+    decode_megolm_export_armor, decrypt_room_key_export, encode_megolm_export_armor,
+    encrypt_room_key_export, AttachmentDecryptor, AttachmentEncryptor, DecryptorError,
+    KeyExportError, MediaEncryptionInfo,
 };
 pub use gossiping::{GossipRequest, GossippedSecret};
 pub use identities::{
@@ -19,6 +19,8 @@ use std::{
 };
 
 use dashmap::DashMap;
+use futures_core::Stream;
+use futures_util::{stream, StreamExt};
 use matrix_sdk_common::deserialized_responses::{
     AlgorithmInfo, DeviceLinkProblem, EncryptionInfo, TimelineEvent, VerificationLevel,
     VerificationState,
@@ -39,8 +41,8 @@ use ruma::{
         secret::request::SecretName, AnyMessageLikeEvent, AnyToDeviceEvent, MessageLikeEventContent,
     },
     serde::Raw,
-    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedDeviceKeyId, OwnedTransactionId, OwnedUserId,
-    RoomId, TransactionId, UInt, UserId,
+    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedDeviceKeyId, OwnedRoomId, OwnedTransactionId,
+    OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
 use serde_json::{value::to_raw_value, Value};
 use tokio::sync::Mutex;
@@ -62,15 +64,16 @@ use crate::{
     gossiping::GossipMachine,
     identities::{user::UserIdentities, Device, IdentityManager, UserDevices},
     olm::{
-        Account, CrossSigningStatus, EncryptionSettings, ExportedRoomKey, IdentityKeys,
-        InboundGroupSession, OlmDecryptionInfo, PrivateCrossSigningIdentity, ReadOnlyAccount,
-        SessionType,
+        Account, CrossSigningStatus, DecryptionCache, DecryptionWarning, EncryptionSettings,
+        ExportedRoomKey, IdentityKeys, InboundGroupSession, OlmDecryptionInfo,
+        PrivateCrossSigningIdentity, ReadOnlyAccount, SessionType,
     },
     requests::{IncomingResponse, OutgoingRequest, UploadSigningKeysRequest},
     session_manager::{GroupSessionManager, SessionManager},
     store::{
-        locks::LockStoreError, Changes, DeviceChanges, DynCryptoStore, IdentityChanges,
-        IntoCryptoStore, MemoryStore, Result as StoreResult, RoomKeyInfo, SecretImportError, Store,
+        locks::LockStoreError, BroadcastStreamRecvError, Changes, DeviceChanges, DynCryptoStore,
+        IdentityChanges, IntoCryptoStore, MemoryStore, Result as StoreResult, RoomKeyInfo,
+        SecretImportError, Store,
     },
     types::{
         events::{
@@ -131,6 +134,8 @@ pub struct OlmMachineInner {
     /// A state machine that handles creating room key backups.
     #[cfg(feature = "backups_v1")]
     backup_machine: BackupMachine,
+    /// A cache of decrypted room events, disabled by default.
+    decryption_cache: DecryptionCache,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -235,6 +240,7 @@ impl OlmMachine {
             identity_manager,
             #[cfg(feature = "backups_v1")]
             backup_machine,
+            decryption_cache: DecryptionCache::default(),
         });
 
         Self { inner }
@@ -334,6 +340,16 @@ impl OlmMachine {
         &self.inner.store
     }
 
+    /// Receive notifications of room keys being received as a [`Stream`].
+    ///
+    /// This is a shortcut for [`Store::room_keys_received_stream()`], see its
+    /// documentation for details.
+    pub fn room_keys_received_stream(
+        &self,
+    ) -> impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>> {
+        self.store().room_keys_received_stream()
+    }
+
     /// The unique user id that owns this `OlmMachine` instance.
     pub fn user_id(&self) -> &UserId {
         &self.inner.user_id
@@ -377,6 +393,22 @@ impl OlmMachine {
         self.inner.key_request_machine.is_room_key_forwarding_enabled()
     }
 
+    /// Set the number of decrypted room events the machine keeps in memory.
+    ///
+    /// Decrypting an event that was decrypted before, for example because the
+    /// timeline got rebuilt, is then served from the cache instead of
+    /// decrypting the ciphertext again. The cache holds plaintext, it's
+    /// disabled by default and can be disabled again by setting its capacity
+    /// to zero.
+    pub fn set_decryption_cache_capacity(&self, capacity: usize) {
+        self.inner.decryption_cache.set_capacity(capacity)
+    }
+
+    /// The number of decrypted room events the machine keeps in memory.
+    pub fn decryption_cache_capacity(&self) -> usize {
+        self.inner.decryption_cache.capacity()
+    }
+
     /// Get the outgoing requests that need to be sent out.
     ///
     /// This returns a list of [`OutgoingRequest`]. Those requests need to be
@@ -682,14 +714,7 @@ impl OlmMachine {
         event: &DecryptedRoomKeyEvent,
         content: &MegolmV1AesSha2Content,
     ) -> OlmResult<Option<InboundGroupSession>> {
-        let session = InboundGroupSession::new(
-            sender_key,
-            event.keys.ed25519,
-            &content.room_id,
-            &content.session_key,
-            event.content.algorithm(),
-            None,
-        );
+        let session = InboundGroupSession::new_from_room_key_event(sender_key, event);
 
         match session {
             Ok(session) => {
@@ -732,8 +757,11 @@ impl OlmMachine {
             RoomKeyContent::MegolmV2AesSha2(content) => {
                 self.handle_key(sender_key, event, content).await
             }
-            RoomKeyContent::Unknown(_) => {
-                warn!("Received a room key with an unsupported algorithm");
+            RoomKeyContent::Unknown(c) => {
+                warn!(
+                    algorithm = %c.algorithm,
+                    "Rejected a room key with an unsupported algorithm"
+                );
                 Ok(None)
             }
         }
@@ -1151,12 +1179,18 @@ impl OlmMachine {
     ) -> OlmResult<(Vec<Raw<AnyToDeviceEvent>>, Vec<RoomKeyInfo>)> {
         let (events, changes) = self.preprocess_sync_changes(sync_changes).await?;
 
-        // Technically save_changes also does the same work, so if it's slow we could
-        // refactor this to do it only once.
-        let room_key_updates: Vec<_> =
+        let room_keys: Vec<_> =
             changes.inbound_group_sessions.iter().map(RoomKeyInfo::from).collect();
 
-        self.store().save_changes(changes).await?;
+        // Another task, e.g. a backup restore, might have stored a better copy
+        // of one of the room keys since we received it, only report the room
+        // keys that were actually stored.
+        let outcomes = self.store().save_changes_if_better(changes).await?;
+        let room_key_updates = room_keys
+            .into_iter()
+            .zip(outcomes)
+            .filter_map(|(info, outcome)| outcome.was_written().then_some(info))
+            .collect();
 
         Ok((events, room_key_updates))
     }
@@ -1325,7 +1359,8 @@ impl OlmMachine {
         room_id: &RoomId,
         event: &EncryptedEvent,
         content: &SupportedEventEncryptionSchemes<'_>,
-    ) -> MegolmResult<TimelineEvent> {
+        tolerance: Option<Duration>,
+    ) -> MegolmResult<(TimelineEvent, Vec<DecryptionWarning>)> {
         if let Some(session) =
             self.store().get_inbound_group_session(room_id, content.session_id()).await?
         {
@@ -1336,26 +1371,30 @@ impl OlmMachine {
             // sender key in the event is deprecated, so let's record it now.
             tracing::Span::current().record("sender_key", debug(session.sender_key()));
 
-            let result = session.decrypt(event).await;
+            let result = session.decrypt_with_cache(event, &self.inner.decryption_cache).await;
             match result {
                 Ok((decrypted_event, _)) => {
                     let encryption_info = self.get_encryption_info(&session, &event.sender).await?;
-                    Ok(TimelineEvent {
-                        encryption_info: Some(encryption_info),
-                        event: decrypted_event,
-                        push_actions: None,
-                    })
+                    let warnings = tolerance
+                        .and_then(|t| session.timestamp_warning(event.origin_server_ts, t))
+                        .into_iter()
+                        .collect();
+
+                    Ok((
+                        TimelineEvent {
+                            encryption_info: Some(encryption_info),
+                            event: decrypted_event,
+                            push_actions: None,
+                        },
+                        warnings,
+                    ))
                 }
                 Err(error) => Err(
                     if let MegolmError::Decryption(DecryptionError::UnknownMessageIndex(_, _)) =
                         error
                     {
-                        let withheld_code = self
-                            .inner
-                            .store
-                            .get_withheld_info(room_id, content.session_id())
-                            .await?
-                            .map(|e| e.content.withheld_code());
+                        let withheld_code =
+                            self.inner.store.withheld_code(room_id, content.session_id()).await?;
 
                         if withheld_code.is_some() {
                             // Partially withheld, report with a withheld code if we have one.
@@ -1369,12 +1408,8 @@ impl OlmMachine {
                 ),
             }
         } else {
-            let withheld_code = self
-                .inner
-                .store
-                .get_withheld_info(room_id, content.session_id())
-                .await?
-                .map(|e| e.content.withheld_code());
+            let withheld_code =
+                self.inner.store.withheld_code(room_id, content.session_id()).await?;
 
             Err(MegolmError::MissingRoomKey(withheld_code))
         }
@@ -1387,12 +1422,53 @@ impl OlmMachine {
     /// * `event` - The event that should be decrypted.
     ///
     /// * `room_id` - The ID of the room where the event was sent to.
-    #[instrument(skip_all, fields(?room_id, event_id, sender, algorithm, session_id, sender_key))]
     pub async fn decrypt_room_event(
         &self,
         event: &Raw<EncryptedEvent>,
         room_id: &RoomId,
     ) -> MegolmResult<TimelineEvent> {
+        Ok(self.decrypt_room_event_inner(event, room_id, None).await?.0)
+    }
+
+    /// Decrypt an event from a room timeline, and check it for problems that
+    /// don't prevent it from being decrypted.
+    ///
+    /// This behaves exactly like [`OlmMachine::decrypt_room_event()`], see
+    /// [`InboundGroupSession::decrypt_with_warnings()`] for the problems that
+    /// are reported as [`DecryptionWarning`]s.
+    ///
+    /// **Note**: The creation time of a room key is the time at which we
+    /// received it. If this device was offline when the room key was sent,
+    /// the room key is only received once the device syncs again, after the
+    /// events that were sent in the meantime. Those events are reported if
+    /// they predate the sync by more than `tolerance`, so warnings about
+    /// events that were received while catching up are likely false
+    /// positives.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be decrypted.
+    ///
+    /// * `room_id` - The ID of the room where the event was sent to.
+    ///
+    /// * `tolerance` - How long before the creation of the room key an event
+    ///   may have been sent without being reported.
+    pub async fn decrypt_room_event_with_warnings(
+        &self,
+        event: &Raw<EncryptedEvent>,
+        room_id: &RoomId,
+        tolerance: Duration,
+    ) -> MegolmResult<(TimelineEvent, Vec<DecryptionWarning>)> {
+        self.decrypt_room_event_inner(event, room_id, Some(tolerance)).await
+    }
+
+    #[instrument(skip_all, fields(?room_id, event_id, sender, algorithm, session_id, sender_key))]
+    async fn decrypt_room_event_inner(
+        &self,
+        event: &Raw<EncryptedEvent>,
+        room_id: &RoomId,
+        tolerance: Option<Duration>,
+    ) -> MegolmResult<(TimelineEvent, Vec<DecryptionWarning>)> {
         let event = event.deserialize()?;
 
         tracing::Span::current()
@@ -1414,7 +1490,7 @@ impl OlmMachine {
         };
 
         tracing::Span::current().record("session_id", content.session_id());
-        let result = self.decrypt_megolm_events(room_id, &event, &content).await;
+        let result = self.decrypt_megolm_events(room_id, &event, &content, tolerance).await;
 
         if let Err(e) = &result {
             #[cfg(feature = "automatic-room-key-forwarding")]
@@ -1601,45 +1677,17 @@ impl OlmMachine {
     ) -> StoreResult<RoomKeyImportResult> {
         let mut sessions = Vec::new();
 
-        async fn new_session_better(
-            session: &InboundGroupSession,
-            old_session: Option<InboundGroupSession>,
-        ) -> bool {
-            if let Some(old_session) = &old_session {
-                session.compare(old_session).await == SessionOrdering::Better
-            } else {
-                true
-            }
-        }
-
         let total_count = exported_keys.len();
-        let mut keys = BTreeMap::new();
 
         for (i, key) in exported_keys.into_iter().enumerate() {
             match InboundGroupSession::from_export(&key) {
                 Ok(session) => {
-                    let old_session = self
-                        .inner
-                        .store
-                        .get_inbound_group_session(session.room_id(), session.session_id())
-                        .await?;
-
-                    // Only import the session if we didn't have this session or
-                    // if it's a better version of the same session.
-                    if new_session_better(&session, old_session).await {
-                        #[cfg(feature = "backups_v1")]
-                        if from_backup {
-                            session.mark_as_backed_up();
-                        }
-
-                        keys.entry(session.room_id().to_owned())
-                            .or_insert_with(BTreeMap::new)
-                            .entry(session.sender_key().to_base64())
-                            .or_insert_with(BTreeSet::new)
-                            .insert(session.session_id().to_owned());
-
-                        sessions.push(session);
+                    #[cfg(feature = "backups_v1")]
+                    if from_backup {
+                        session.mark_as_backed_up();
                     }
+
+                    sessions.push(session);
                 }
                 Err(e) => {
                     warn!(
@@ -1655,11 +1703,25 @@ impl OlmMachine {
             progress_listener(i, total_count);
         }
 
-        let imported_count = sessions.len();
+        let changes = Changes { inbound_group_sessions: sessions.clone(), ..Default::default() };
+
+        // Only the sessions we didn't have, or that are a better version of
+        // the same session, are imported.
+        let outcomes = self.store().save_changes_if_better(changes).await?;
+        let mut imported_count = 0;
+        let mut keys = BTreeMap::new();
 
-        let changes = Changes { inbound_group_sessions: sessions, ..Default::default() };
+        for (session, outcome) in sessions.iter().zip(outcomes) {
+            if outcome.was_written() {
+                imported_count += 1;
 
-        self.store().save_changes(changes).await?;
+                keys.entry(session.room_id().to_owned())
+                    .or_insert_with(BTreeMap::new)
+                    .entry(session.sender_key().to_base64())
+                    .or_insert_with(BTreeSet::new)
+                    .insert(session.session_id().to_owned());
+            }
+        }
 
         info!(total_count, imported_count, room_keys = ?keys, "Successfully imported room keys");
 
@@ -1715,6 +1777,36 @@ impl OlmMachine {
         Ok(exported)
     }
 
+    /// Export the keys of the given rooms as a stream.
+    ///
+    /// Unlike [`OlmMachine::export_room_keys()`], this doesn't collect all the
+    /// exported keys up front, a key is only exported once the stream is
+    /// polled for it. Only the sessions of the given rooms are loaded from the
+    /// store, sessions belonging to other rooms are never unpickled.
+    ///
+    /// The stream can be collected and encrypted using the
+    /// [`encrypt_room_key_export()`] function.
+    ///
+    /// # Arguments
+    ///
+    /// * `rooms` - The rooms for which the room keys should be exported.
+    ///
+    /// [`encrypt_room_key_export()`]: crate::encrypt_room_key_export
+    pub async fn export_room_keys_for_rooms(
+        &self,
+        rooms: &[OwnedRoomId],
+    ) -> StoreResult<impl Stream<Item = ExportedRoomKey>> {
+        let rooms: BTreeSet<&RoomId> = rooms.iter().map(|r| r.as_ref()).collect();
+
+        let mut sessions: Vec<InboundGroupSession> = Vec::new();
+
+        for room_id in rooms {
+            sessions.extend(self.store().get_inbound_group_sessions_for_room(room_id).await?);
+        }
+
+        Ok(stream::iter(sessions).then(|session| async move { session.export().await }))
+    }
+
     /// Get the status of the private cross signing keys.
     ///
     /// This can be used to check which private cross signing keys we have
@@ -2002,11 +2094,17 @@ pub(crate) mod tests {
     use crate::{
         error::EventError,
         machine::{EncryptionSyncChanges, OlmMachine},
-        olm::{InboundGroupSession, OutboundGroupSession, VerifyJson},
-        store::Changes,
+        olm::{
+            DecryptionWarning, ExportedRoomKey, InboundGroupSession, OutboundGroupSession,
+            VerifyJson,
+        },
+        store::{Changes, UpsertOutcome},
         types::{
             events::{
-                room::encrypted::{EncryptedToDeviceEvent, ToDeviceEncryptedEventContent},
+                room::encrypted::{
+                    EncryptedEvent, EncryptedToDeviceEvent, RoomEventEncryptionScheme,
+                    ToDeviceEncryptedEventContent,
+                },
                 room_key_withheld::{RoomKeyWithheldContent, WithheldCode},
                 ToDeviceEvent,
             },
@@ -2224,6 +2322,378 @@ pub(crate) mod tests {
             .invalidated());
     }
 
+    #[async_test]
+    async fn sessions_from_sender() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+
+        machine
+            .create_outbound_group_session_with_defaults(room_id!("!test:example.org"))
+            .await
+            .unwrap();
+        machine
+            .create_outbound_group_session_with_defaults(room_id!("!test2:example.org"))
+            .await
+            .unwrap();
+
+        let sessions =
+            machine.store().sessions_from_sender(machine.identity_keys().curve25519).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        let other_key =
+            Curve25519PublicKey::from_base64("LTpv2DGMhggPAXO02+7f68CNEp6A40F0Yl8B094Y8gc")
+                .unwrap();
+        let sessions = machine.store().sessions_from_sender(other_key).await.unwrap();
+        assert!(sessions.is_empty());
+    }
+
+    #[async_test]
+    async fn room_keys_received_stream_skips_redundant_keys() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        machine
+            .create_outbound_group_session_with_defaults(room_id!("!test:example.org"))
+            .await
+            .unwrap();
+        let session = machine.store().get_inbound_group_sessions().await.unwrap().remove(0);
+        let worse = InboundGroupSession::from_export(&session.export_at_index(5).await).unwrap();
+
+        let other = OlmMachine::new(user_id(), device_id!("OTHERDEVICE")).await;
+        let mut stream = Box::pin(other.room_keys_received_stream());
+
+        other.store().upsert_if_better(worse.clone()).await.unwrap();
+        let room_keys =
+            stream.next().now_or_never().flatten().expect("A new key should be reported").unwrap();
+        assert_eq!(room_keys.len(), 1);
+        assert_eq!(room_keys[0].session_id, session.session_id());
+
+        // Receiving the same key again doesn't store it, so nothing is reported.
+        other.store().upsert_if_better(worse).await.unwrap();
+        assert!(stream.next().now_or_never().is_none());
+
+        // A better copy of the key replaces the stored one, which is reported.
+        other.store().upsert_if_better(session.clone()).await.unwrap();
+        let room_keys = stream
+            .next()
+            .now_or_never()
+            .flatten()
+            .expect("A better key should be reported")
+            .unwrap();
+        assert_eq!(room_keys[0].session_id, session.session_id());
+    }
+
+    #[async_test]
+    async fn room_key_counts() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+
+        for room_id in [room_id, room_id, room_id!("!other:example.org")] {
+            machine.create_outbound_group_session_with_defaults(room_id).await.unwrap();
+        }
+
+        let counts = machine.store().room_key_counts(room_id).await.unwrap();
+        assert_eq!(counts.total, 2);
+        assert_eq!(counts.backed_up, 0);
+
+        let session = machine.store().get_inbound_group_sessions().await.unwrap();
+        let session = session.iter().find(|s| s.room_id() == room_id).unwrap();
+        session.mark_as_backed_up();
+        machine.store().save_inbound_group_sessions(&[session.clone()]).await.unwrap();
+
+        let counts = machine.store().room_key_counts(room_id).await.unwrap();
+        assert_eq!(counts.total, 2);
+        assert_eq!(counts.backed_up, 1);
+
+        let counts =
+            machine.store().room_key_counts(room_id!("!unknown:example.org")).await.unwrap();
+        assert_eq!(counts.total, 0);
+    }
+
+    #[async_test]
+    async fn backup_state_stream() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let mut stream = Box::pin(machine.store().backup_state_stream());
+
+        let (_, session) = machine.account().create_group_session_pair_with_defaults(room_id).await;
+        let session_id = session.session_id().to_owned();
+        machine.store().save_inbound_group_sessions(&[session.clone()]).await.unwrap();
+        assert!(stream.next().now_or_never().is_none());
+
+        machine.store().mark_as_backed_up(vec![session.clone()]).await.unwrap();
+        // Marking the session again doesn't change its state.
+        machine.store().mark_as_backed_up(vec![session.clone()]).await.unwrap();
+
+        assert_eq!(
+            stream.next().now_or_never().flatten(),
+            Some((room_id.to_owned(), session_id.clone(), true))
+        );
+        assert!(stream.next().now_or_never().is_none());
+
+        // Flips that happen while the stream isn't polled are coalesced.
+        machine.store().reset_backup_state().await.unwrap();
+        machine.store().mark_as_backed_up(vec![session]).await.unwrap();
+        machine.store().reset_backup_state().await.unwrap();
+
+        assert_eq!(
+            stream.next().now_or_never().flatten(),
+            Some((room_id.to_owned(), session_id, false))
+        );
+        assert!(stream.next().now_or_never().is_none());
+    }
+
+    #[async_test]
+    #[cfg(feature = "backups_v1")]
+    async fn backup_state_stream_reports_room_keys_imported_from_backup() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let mut stream = Box::pin(machine.store().backup_state_stream());
+
+        let (_, session) = machine.account().create_group_session_pair_with_defaults(room_id).await;
+        let export = session.export().await;
+
+        machine.import_room_keys(vec![export.clone()], true, |_, _| {}).await.unwrap();
+
+        assert_eq!(
+            stream.next().now_or_never().flatten(),
+            Some((room_id.to_owned(), session.session_id().to_owned(), true))
+        );
+
+        // Importing the same room key again doesn't store or report anything.
+        machine.import_room_keys(vec![export], true, |_, _| {}).await.unwrap();
+        assert!(stream.next().now_or_never().is_none());
+    }
+
+    #[async_test]
+    async fn prune_sessions_created_before() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let account = machine.account();
+
+        let (_, backed_up) = account.create_group_session_pair_with_defaults(room_id).await;
+        let (_, not_backed_up) = account.create_group_session_pair_with_defaults(room_id).await;
+        let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+        let imported = InboundGroupSession::from_export(&session.export().await).unwrap();
+        backed_up.mark_as_backed_up();
+        imported.mark_as_backed_up();
+
+        machine
+            .store()
+            .save_inbound_group_sessions(&[backed_up, not_backed_up, imported.clone()])
+            .await
+            .unwrap();
+
+        let store = machine.store();
+        let now = MilliSecondsSinceUnixEpoch::now();
+        let past = MilliSecondsSinceUnixEpoch(now.get() - uint!(60_000));
+        let future = MilliSecondsSinceUnixEpoch(now.get() + uint!(60_000));
+
+        assert_eq!(store.prune_sessions_created_before(past, false).await.unwrap(), 0);
+        assert_eq!(store.prune_sessions_created_before(future, true).await.unwrap(), 1);
+        assert_eq!(store.prune_sessions_created_before(future, false).await.unwrap(), 1);
+
+        // Sessions with an unknown creation time are never pruned.
+        assert_eq!(store.get_inbound_group_sessions().await.unwrap(), vec![imported]);
+    }
+
+    #[async_test]
+    async fn lazy_inbound_group_session() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let store = machine.store();
+
+        let (outbound, session) =
+            machine.account().create_group_session_pair_with_defaults(room_id).await;
+        store.save_inbound_group_sessions(&[session.clone()]).await.unwrap();
+
+        assert!(store.get_lazy_inbound_group_session(room_id, "missing").await.unwrap().is_none());
+
+        let lazy = store
+            .get_lazy_inbound_group_session(room_id, session.session_id())
+            .await
+            .unwrap()
+            .expect("We should be able to load the session lazily");
+
+        assert_eq!(lazy.room_id(), session.room_id());
+        assert_eq!(lazy.session_id(), session.session_id());
+        assert_eq!(lazy.sender_key(), session.sender_key());
+        assert_eq!(lazy.first_known_index(), session.first_known_index());
+        assert_eq!(lazy.algorithm(), session.algorithm());
+        assert_eq!(lazy.creation_time(), session.creation_time());
+
+        let content = outbound.encrypt(json!({}), "m.dummy").await;
+        let event = json_convert(&json!({
+            "event_id": "$xxxxx:example.org",
+            "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+            "sender": machine.user_id(),
+            "type": "m.room.encrypted",
+            "content": content,
+        }))
+        .unwrap();
+
+        let (_, message_index) = lazy.decrypt(&event).await.unwrap();
+        assert_eq!(message_index, 0);
+
+        // The ratchet is loaded from the store on every decryption, so the
+        // session can't be used anymore once it's removed.
+        let changes =
+            Changes { deleted_inbound_group_sessions: vec![session], ..Default::default() };
+        store.save_changes(changes).await.unwrap();
+
+        assert_matches!(lazy.decrypt(&event).await, Err(MegolmError::MissingRoomKey(None)));
+    }
+
+    #[async_test]
+    async fn lazy_inbound_group_sessions_for_room() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let store = machine.store();
+
+        let mut sessions = Vec::new();
+        for room_id in [room_id, room_id, room_id!("!other:example.org")] {
+            let (_, inbound) =
+                machine.account().create_group_session_pair_with_defaults(room_id).await;
+            sessions.push(inbound);
+        }
+        store.save_inbound_group_sessions(&sessions).await.unwrap();
+
+        let lazy = store.get_lazy_inbound_group_sessions_for_room(room_id).await.unwrap();
+        assert_eq!(lazy.len(), 2);
+
+        for session in &sessions[..2] {
+            let lazy = lazy
+                .iter()
+                .find(|l| l.session_id() == session.session_id())
+                .expect("Both sessions of the room should be loaded lazily");
+
+            assert_eq!(lazy.room_id(), room_id);
+            assert_eq!(lazy.sender_key(), session.sender_key());
+            assert_eq!(lazy.first_known_index(), session.first_known_index());
+            assert_eq!(lazy.algorithm(), session.algorithm());
+            assert_eq!(lazy.creation_time(), session.creation_time());
+        }
+    }
+
+    #[async_test]
+    async fn decrypt_room_event_with_warnings() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+        let tolerance = Duration::from_secs(60);
+
+        let (outbound, session) =
+            machine.account().create_group_session_pair_with_defaults(room_id).await;
+        machine.store().save_inbound_group_sessions(&[session.clone()]).await.unwrap();
+
+        let session_creation_time = session.creation_time().unwrap();
+        let early = MilliSecondsSinceUnixEpoch(session_creation_time.0 - uint!(90_000));
+
+        let mut events = Vec::new();
+        for (event_id, origin_server_ts) in
+            [("$ontime:example.org", session_creation_time), ("$early:example.org", early)]
+        {
+            let content = outbound.encrypt(json!({}), "m.dummy").await;
+            let event: Raw<EncryptedEvent> = json_convert(&json!({
+                "event_id": event_id,
+                "origin_server_ts": origin_server_ts,
+                "sender": machine.user_id(),
+                "type": "m.room.encrypted",
+                "content": content,
+            }))
+            .unwrap();
+            events.push(event);
+        }
+
+        let (_, warnings) =
+            machine.decrypt_room_event_with_warnings(&events[0], room_id, tolerance).await.unwrap();
+        assert!(warnings.is_empty());
+
+        let (decrypted, warnings) =
+            machine.decrypt_room_event_with_warnings(&events[1], room_id, tolerance).await.unwrap();
+        assert!(decrypted.encryption_info.is_some());
+        assert_eq!(
+            warnings,
+            [DecryptionWarning::TimestampBeforeSession {
+                event_timestamp: early,
+                session_creation_time
+            }]
+        );
+
+        // The plain decryption method doesn't report anything.
+        machine.decrypt_room_event(&events[1], room_id).await.unwrap();
+    }
+
+    #[async_test]
+    async fn session_stats() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:example.org");
+
+        for room_id in [room_id, room_id!("!other:example.org")] {
+            machine.create_outbound_group_session_with_defaults(room_id).await.unwrap();
+        }
+
+        let (_, session) = machine.account().create_group_session_pair_with_defaults(room_id).await;
+        let imported = InboundGroupSession::from_export(&session.export().await).unwrap();
+        imported.mark_as_backed_up();
+        machine.store().save_inbound_group_sessions(&[imported]).await.unwrap();
+
+        let stats = machine.store().session_stats().await.unwrap();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.backed_up, 1);
+        assert_eq!(stats.direct, 2);
+        assert_eq!(stats.forwarded, 0);
+        assert_eq!(stats.imported, 1);
+        assert_eq!(stats.rooms.len(), 2);
+        assert_eq!(stats.rooms[room_id].total, 2);
+        assert_eq!(stats.rooms[room_id].backed_up, 1);
+    }
+
+    #[async_test]
+    async fn sessions_needing_backup_after() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+
+        for room_id in [room_id!("!b:example.org"), room_id!("!a:example.org")] {
+            machine.create_outbound_group_session_with_defaults(room_id).await.unwrap();
+        }
+
+        let sessions = machine.store().sessions_needing_backup_after(None).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].room_id(), room_id!("!a:example.org"));
+        assert_eq!(sessions[1].room_id(), room_id!("!b:example.org"));
+
+        let cursor = sessions[0].backup_order_key();
+        let remaining = machine.store().sessions_needing_backup_after(Some(&cursor)).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].room_id(), room_id!("!b:example.org"));
+
+        let cursor = sessions[1].backup_order_key();
+        let remaining = machine.store().sessions_needing_backup_after(Some(&cursor)).await.unwrap();
+        assert!(remaining.is_empty());
+    }
+
+    #[async_test]
+    async fn rooms_needing_backup() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let first_room = room_id!("!b:example.org");
+        let second_room = room_id!("!a:example.org");
+
+        let mut sessions = Vec::new();
+        for room_id in [first_room, first_room, second_room] {
+            let (_, inbound) =
+                machine.account().create_group_session_pair_with_defaults(room_id).await;
+            sessions.push(inbound);
+        }
+        machine.store().save_inbound_group_sessions(&sessions).await.unwrap();
+
+        let rooms = machine.store().rooms_needing_backup().await.unwrap();
+        assert_eq!(rooms, [second_room.to_owned(), first_room.to_owned()]);
+
+        // The first room still has a room key that needs to be backed up.
+        sessions[0].mark_as_backed_up();
+        sessions[2].mark_as_backed_up();
+        machine.store().save_inbound_group_sessions(&sessions).await.unwrap();
+
+        let rooms = machine.store().rooms_needing_backup().await.unwrap();
+        assert_eq!(rooms, [first_room.to_owned()]);
+    }
+
     #[async_test]
     async fn test_invalid_signature() {
         let machine = OlmMachine::new(user_id(), alice_device_id()).await;
@@ -2607,7 +3077,8 @@ pub(crate) mod tests {
             .next()
             .now_or_never()
             .flatten()
-            .expect("We should have received an update of room key infos");
+            .expect("We should have received an update of room key infos")
+            .unwrap();
         assert_eq!(room_keys.len(), 1);
         assert_eq!(room_keys[0].session_id, group_session.session_id());
 
@@ -2720,6 +3191,17 @@ pub(crate) mod tests {
 
         let err = decrypt_result.err().unwrap();
         assert_matches!(err, MegolmError::MissingRoomKey(Some(WithheldCode::Unverified)));
+
+        let session_id = content.deserialize().unwrap().scheme;
+        let session_id = assert_matches!(
+            session_id,
+            RoomEventEncryptionScheme::MegolmV1AesSha2(c) => c.session_id
+        );
+        let withheld_code = bob.store().withheld_code(room_id, &session_id).await.unwrap();
+        assert_eq!(withheld_code, Some(WithheldCode::Unverified));
+
+        let withheld_code = bob.store().withheld_code(room_id, "unknown session").await.unwrap();
+        assert_eq!(withheld_code, None);
     }
 
     #[async_test]
@@ -3633,4 +4115,92 @@ pub(crate) mod tests {
             "Our identity should not be verified when there's a mismatch in the cross-signing keys"
         );
     }
+
+    #[async_test]
+    async fn export_room_keys_for_rooms() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let first_room = room_id!("!first:localhost");
+        let second_room = room_id!("!second:localhost");
+        let third_room = room_id!("!third:localhost");
+
+        let mut sessions = Vec::new();
+        for room_id in [first_room, second_room, third_room] {
+            let (_, inbound) =
+                machine.account().create_group_session_pair_with_defaults(room_id).await;
+            sessions.push(inbound);
+        }
+        machine.store().save_inbound_group_sessions(&sessions).await.unwrap();
+
+        let rooms = [first_room.to_owned(), second_room.to_owned()];
+        let exported: Vec<ExportedRoomKey> =
+            machine.export_room_keys_for_rooms(&rooms).await.unwrap().collect().await;
+
+        assert_eq!(exported.len(), 2);
+        assert!(exported.iter().any(|k| k.room_id == first_room));
+        assert!(exported.iter().any(|k| k.room_id == second_room));
+        assert!(
+            !exported.iter().any(|k| k.room_id == third_room),
+            "The keys of the third room should not be exported"
+        );
+    }
+
+    #[async_test]
+    async fn export_session() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:localhost");
+
+        let (_, session) = machine.account().create_group_session_pair_with_defaults(room_id).await;
+        machine.store().save_inbound_group_sessions(&[session.clone()]).await.unwrap();
+
+        let exported = machine
+            .store()
+            .export_session(room_id, session.session_id(), None)
+            .await
+            .unwrap()
+            .expect("We should be able to export a known session");
+        assert_eq!(exported.session_id, session.session_id());
+        assert_eq!(InboundGroupSession::from_export(&exported).unwrap().first_known_index(), 0);
+
+        let exported = machine
+            .store()
+            .export_session(room_id, session.session_id(), Some(10))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(InboundGroupSession::from_export(&exported).unwrap().first_known_index(), 10);
+
+        assert!(machine
+            .store()
+            .export_session(room_id!("!other:localhost"), session.session_id(), None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[async_test]
+    async fn upsert_if_better() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:localhost");
+
+        let (_, session) = machine.account().create_group_session_pair_with_defaults(room_id).await;
+        let worse = InboundGroupSession::from_export(&session.export_at_index(10).await).unwrap();
+
+        let outcome = machine.store().upsert_if_better(worse.clone()).await.unwrap();
+        assert_eq!(outcome, UpsertOutcome::Inserted);
+
+        let outcome = machine.store().upsert_if_better(worse).await.unwrap();
+        assert_eq!(outcome, UpsertOutcome::Unchanged);
+        assert!(!outcome.was_written());
+
+        let outcome = machine.store().upsert_if_better(session.clone()).await.unwrap();
+        assert_eq!(outcome, UpsertOutcome::Replaced);
+
+        let stored = machine
+            .store()
+            .get_inbound_group_session(room_id, session.session_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.first_known_index(), 0);
+    }
 }
@@ -1244,9 +1244,6 @@ impl ReadOnlyAccount {
     ) -> Result<(OutboundGroupSession, InboundGroupSession), MegolmSessionCreationError> {
         trace!(?room_id, algorithm = settings.algorithm.as_str(), "Creating a new room key");
 
-        let visibility = settings.history_visibility.clone();
-        let algorithm = settings.algorithm.to_owned();
-
         let outbound = OutboundGroupSession::new(
             self.device_id.clone(),
             self.identity_keys.clone(),
@@ -1254,19 +1251,7 @@ impl ReadOnlyAccount {
             settings,
         )?;
 
-        let identity_keys = self.identity_keys();
-
-        let sender_key = identity_keys.curve25519;
-        let signing_key = identity_keys.ed25519;
-
-        let inbound = InboundGroupSession::new(
-            sender_key,
-            signing_key,
-            room_id,
-            &outbound.session_key().await,
-            algorithm,
-            Some(visibility),
-        )?;
+        let inbound = outbound.create_matching_inbound().await?;
 
         Ok((outbound, inbound))
     }
@@ -0,0 +1,191 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use vodozemac::megolm::MegolmMessage;
+use zeroize::Zeroizing;
+
+/// The key of a cache entry, the session ID and the message index of a Megolm
+/// message.
+type CacheKey = (String, u32);
+
+struct CacheEntry {
+    message: MegolmMessage,
+    plaintext: Zeroizing<Vec<u8>>,
+    /// The value of [`CacheInner::clock`] when the entry was last used, the
+    /// key of the entry in [`CacheInner::order`].
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    capacity: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// The keys of the entries, ordered from the least to the most recently
+    /// used one by the time they were last used.
+    order: BTreeMap<u64, CacheKey>,
+    /// A counter that is bumped every time an entry is used.
+    clock: u64,
+}
+
+impl CacheInner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn touch(&mut self, key: &CacheKey) {
+        let now = self.tick();
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            let previous = std::mem::replace(&mut entry.last_used, now);
+
+            if let Some(key) = self.order.remove(&previous) {
+                self.order.insert(now, key);
+            }
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let Some((_, key)) = self.order.pop_first() else { break };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// A bounded, least recently used, cache of decrypted Megolm messages.
+///
+/// Entries are keyed by the session ID and the message index of a message,
+/// but a cached plaintext is only returned if the whole message matches the
+/// one that was decrypted, so a different ciphertext claiming the same
+/// message index is never served from the cache.
+///
+/// The cache holds plaintext in memory, which is why it's disabled, i.e. has a
+/// capacity of zero, by default. The plaintext is zeroized once an entry is
+/// evicted.
+#[derive(Clone, Default)]
+pub(crate) struct DecryptionCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for DecryptionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+
+        f.debug_struct("DecryptionCache")
+            .field("capacity", &inner.capacity)
+            .field("len", &inner.entries.len())
+            .finish()
+    }
+}
+
+impl DecryptionCache {
+    /// The maximum number of plaintexts the cache holds.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
+    /// Set the maximum number of plaintexts the cache holds, evicting the
+    /// least recently used entries if the cache is over the new capacity.
+    ///
+    /// A capacity of zero disables the cache.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        inner.evict();
+    }
+
+    /// Get the cached plaintext of the given message.
+    pub fn get(&self, session_id: &str, message: &MegolmMessage) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (session_id.to_owned(), message.message_index());
+
+        let plaintext = inner
+            .entries
+            .get(&key)
+            .filter(|entry| entry.message == *message)
+            .map(|entry| entry.plaintext.to_vec())?;
+
+        inner.touch(&key);
+
+        Some(plaintext)
+    }
+
+    /// Store the plaintext of the given message in the cache.
+    pub fn insert(&self, session_id: &str, message: &MegolmMessage, plaintext: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.capacity == 0 {
+            return;
+        }
+
+        let key = (session_id.to_owned(), message.message_index());
+        let last_used = inner.tick();
+        let entry = CacheEntry {
+            message: message.clone(),
+            plaintext: Zeroizing::new(plaintext.to_vec()),
+            last_used,
+        };
+
+        if let Some(previous) = inner.entries.insert(key.clone(), entry) {
+            inner.order.remove(&previous.last_used);
+        }
+
+        inner.order.insert(last_used, key);
+
+        inner.evict();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vodozemac::megolm::{GroupSession, SessionConfig};
+
+    use super::DecryptionCache;
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut session = GroupSession::new(SessionConfig::version_1());
+        let session_id = session.session_id();
+        let messages: Vec<_> = (0..3).map(|_| session.encrypt("plaintext")).collect();
+
+        let cache = DecryptionCache::default();
+        cache.set_capacity(2);
+
+        cache.insert(&session_id, &messages[0], b"first");
+        cache.insert(&session_id, &messages[1], b"second");
+
+        // Using the first entry makes the second one the least recently used.
+        assert_eq!(cache.get(&session_id, &messages[0]).as_deref(), Some(&b"first"[..]));
+        cache.insert(&session_id, &messages[2], b"third");
+
+        assert_eq!(cache.get(&session_id, &messages[0]).as_deref(), Some(&b"first"[..]));
+        assert!(cache.get(&session_id, &messages[1]).is_none());
+        assert_eq!(cache.get(&session_id, &messages[2]).as_deref(), Some(&b"third"[..]));
+
+        // Replacing an entry counts as a use as well.
+        cache.insert(&session_id, &messages[0], b"first");
+        cache.set_capacity(1);
+
+        assert_eq!(cache.get(&session_id, &messages[0]).as_deref(), Some(&b"first"[..]));
+        assert!(cache.get(&session_id, &messages[2]).is_none());
+    }
+}
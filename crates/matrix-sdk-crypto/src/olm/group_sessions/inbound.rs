@@ -16,19 +16,23 @@ use std::{
     fmt,
     ops::Deref,
     sync::{
-        atomic::{AtomicBool, Ordering::SeqCst},
+        atomic::{AtomicBool, AtomicU32, Ordering::SeqCst},
         Arc,
     },
+    time::Duration,
 };
 
+use futures_util::{stream, StreamExt};
 use ruma::{
     events::{room::history_visibility::HistoryVisibility, AnyTimelineEvent},
     serde::Raw,
-    DeviceKeyAlgorithm, OwnedRoomId, RoomId,
+    DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedRoomId, RoomId,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use tokio::sync::Mutex;
+use tracing::{field::debug, instrument, Span};
 use vodozemac::{
     megolm::{
         DecryptedMessage, DecryptionError, InboundGroupSession as InnerSession,
@@ -38,27 +42,33 @@ use vodozemac::{
 };
 
 use super::{
-    BackedUpRoomKey, ExportedRoomKey, OutboundGroupSession, SessionCreationError, SessionKey,
+    BackedUpRoomKey, DecryptionCache, ExportedRoomKey, OutboundGroupSession, SessionCreationError,
+    SessionExportError, SessionKey,
 };
 use crate::{
     error::{EventError, MegolmResult},
     types::{
-        deserialize_curve_key,
+        deserialize_curve_key, deserialize_curve_key_vec,
         events::{
             forwarded_room_key::{
                 ForwardedMegolmV1AesSha2Content, ForwardedMegolmV2AesSha2Content,
                 ForwardedRoomKeyContent,
             },
-            olm_v1::DecryptedForwardedRoomKeyEvent,
+            olm_v1::{DecryptedForwardedRoomKeyEvent, DecryptedRoomKeyEvent},
             room::encrypted::{EncryptedEvent, RoomEventEncryptionScheme},
+            room_key::RoomKeyContent,
         },
-        serialize_curve_key, EventEncryptionAlgorithm, SigningKeys,
+        serialize_curve_key, serialize_curve_key_vec, EventEncryptionAlgorithm, SigningKeys,
     },
+    utilities::encode,
 };
 
-// TODO add creation times to the inbound group sessions so we can export
-// sessions that were created between some time period, this should only be set
-// for non-imported sessions.
+/// The maximum number of sessions that [`InboundGroupSession::pickle_many()`]
+/// pickles concurrently.
+const PICKLE_CONCURRENCY: usize = 16;
+
+// TODO use the creation times of the inbound group sessions so we can export
+// sessions that were created between some time period.
 
 /// Information about the creator of an inbound group session.
 #[derive(Clone)]
@@ -95,6 +105,18 @@ pub(crate) struct SessionCreatorInfo {
     pub signing_keys: Arc<SigningKeys<DeviceKeyAlgorithm>>,
 }
 
+impl SessionCreatorInfo {
+    /// Create the creator info of a session from the Curve25519 key the
+    /// session was received from and the signing keys the sender claims to
+    /// own, as found in the decrypted Olm event carrying the session.
+    pub(crate) fn from_olm_event_keys(
+        curve25519_key: Curve25519PublicKey,
+        keys: SigningKeys<DeviceKeyAlgorithm>,
+    ) -> Self {
+        Self { curve25519_key, signing_keys: keys.into() }
+    }
+}
+
 /// A structure representing an inbound group session.
 ///
 /// Inbound group sessions, also known as "room keys", are used to facilitate
@@ -144,8 +166,31 @@ pub struct InboundGroupSession {
     /// created.
     history_visibility: Arc<Option<HistoryVisibility>>,
 
+    /// Chain of Curve25519 keys through which this session was forwarded, via
+    /// `m.forwarded_room_key` events or file exports.
+    ///
+    /// For sessions received as an `m.forwarded_room_key` event, the last key
+    /// in the chain is the one of the device that forwarded the session to us.
+    /// This is empty if the session was received directly from its creator or
+    /// if the forwarding history is unknown.
+    forwarding_chain: Arc<Vec<Curve25519PublicKey>>,
+
     /// Was this room key backed up to the server.
     backed_up: Arc<AtomicBool>,
+
+    /// The number of messages this room key successfully decrypted since it
+    /// was created or loaded from the store.
+    decrypt_count: Arc<AtomicU32>,
+
+    /// The time at which we received the room key from its creator.
+    ///
+    /// This is only known for sessions that were received directly as a
+    /// `m.room_key` event, it's `None` for imported or forwarded sessions.
+    creation_time: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// Was the session forwarded to us by a device we had verified when we
+    /// received it.
+    forwarded_by_verified_device: bool,
 }
 
 impl InboundGroupSession {
@@ -172,6 +217,65 @@ impl InboundGroupSession {
         session_key: &SessionKey,
         encryption_algorithm: EventEncryptionAlgorithm,
         history_visibility: Option<HistoryVisibility>,
+    ) -> Result<Self, SessionCreationError> {
+        let mut keys = SigningKeys::new();
+        keys.insert(DeviceKeyAlgorithm::Ed25519, signing_key.into());
+
+        Self::new_helper(
+            SessionCreatorInfo::from_olm_event_keys(sender_key, keys),
+            room_id,
+            session_key,
+            encryption_algorithm,
+            history_visibility,
+        )
+    }
+
+    /// Create a new inbound group session from a decrypted `m.room_key` event.
+    ///
+    /// The room, session key and algorithm are taken from the content of the
+    /// event, while the signing key of the creator is the Ed25519 key that
+    /// the Olm event claims to be sent from.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender_key` - The Curve25519 key of the device that sent us the
+    /// event.
+    ///
+    /// * `event` - The decrypted `m.room_key` event.
+    ///
+    /// Returns [`SessionCreationError::Algorithm`], carrying the algorithm of
+    /// the event, if the room key uses an unsupported algorithm, like
+    /// forwarded room keys do.
+    pub(crate) fn new_from_room_key_event(
+        sender_key: Curve25519PublicKey,
+        event: &DecryptedRoomKeyEvent,
+    ) -> Result<Self, SessionCreationError> {
+        let content = match &event.content {
+            RoomKeyContent::MegolmV1AesSha2(c) => c,
+            #[cfg(feature = "experimental-algorithms")]
+            RoomKeyContent::MegolmV2AesSha2(c) => c,
+            RoomKeyContent::Unknown(c) => {
+                return Err(SessionCreationError::Algorithm(c.algorithm.to_owned()));
+            }
+        };
+
+        let keys = SigningKeys::from([(DeviceKeyAlgorithm::Ed25519, event.keys.ed25519.into())]);
+
+        Self::new_helper(
+            SessionCreatorInfo::from_olm_event_keys(sender_key, keys),
+            &content.room_id,
+            &content.session_key,
+            event.content.algorithm(),
+            None,
+        )
+    }
+
+    fn new_helper(
+        creator_info: SessionCreatorInfo,
+        room_id: &RoomId,
+        session_key: &SessionKey,
+        encryption_algorithm: EventEncryptionAlgorithm,
+        history_visibility: Option<HistoryVisibility>,
     ) -> Result<Self, SessionCreationError> {
         let config = OutboundGroupSession::session_config(&encryption_algorithm)?;
 
@@ -179,22 +283,20 @@ impl InboundGroupSession {
         let session_id = session.session_id();
         let first_known_index = session.first_known_index();
 
-        let mut keys = SigningKeys::new();
-        keys.insert(DeviceKeyAlgorithm::Ed25519, signing_key.into());
-
         Ok(InboundGroupSession {
             inner: Arc::new(Mutex::new(session)),
             history_visibility: history_visibility.into(),
             session_id: session_id.into(),
             first_known_index,
-            creator_info: SessionCreatorInfo {
-                curve25519_key: sender_key,
-                signing_keys: keys.into(),
-            },
+            creator_info,
             room_id: room_id.into(),
             imported: false,
             algorithm: encryption_algorithm.into(),
+            forwarding_chain: Vec::new().into(),
             backed_up: AtomicBool::new(false).into(),
+            decrypt_count: AtomicU32::new(0).into(),
+            creation_time: Some(MilliSecondsSinceUnixEpoch::now()),
+            forwarded_by_verified_device: false,
         })
     }
 
@@ -204,13 +306,19 @@ impl InboundGroupSession {
     /// Most notably this can be called with an `ExportedRoomKey` from a
     /// previous [`export()`] call.
     ///
+    /// Returns an error if the session ID of the export doesn't match its
+    /// session key.
+    ///
     /// [`export()`]: #method.export
     pub fn from_export(exported_session: &ExportedRoomKey) -> Result<Self, SessionCreationError> {
         Self::try_from(exported_session)
     }
 
-    #[allow(dead_code)]
-    fn from_backup(
+    /// Create a InboundGroupSession from a room key that was restored from a
+    /// server-side backup, see [`BackupDecryptionKey::decrypt_and_import()`].
+    ///
+    /// [`BackupDecryptionKey::decrypt_and_import()`]: crate::store::BackupDecryptionKey::decrypt_and_import
+    pub(crate) fn from_backup(
         room_id: &RoomId,
         backup: BackedUpRoomKey,
     ) -> Result<Self, SessionCreationError> {
@@ -224,7 +332,7 @@ impl InboundGroupSession {
             room_id: room_id.to_owned(),
             sender_key: backup.sender_key,
             session_id,
-            forwarding_curve25519_key_chain: vec![],
+            forwarding_curve25519_key_chain: backup.forwarding_curve25519_key_chain,
             session_key: backup.session_key,
             sender_claimed_keys: backup.sender_claimed_keys,
         })
@@ -248,9 +356,25 @@ impl InboundGroupSession {
             backed_up: self.backed_up(),
             history_visibility: self.history_visibility.as_ref().clone(),
             algorithm: (*self.algorithm).to_owned(),
+            forwarding_curve25519_key_chain: self.forwarding_chain.as_ref().clone(),
+            creation_time: self.creation_time,
+            forwarded_by_verified_device: self.forwarded_by_verified_device,
         }
     }
 
+    /// Pickle many group sessions at once.
+    ///
+    /// The sessions are pickled concurrently, with a bounded number of them
+    /// being in flight at any time. The pickles are returned in the same
+    /// order as the given sessions.
+    pub async fn pickle_many(sessions: &[InboundGroupSession]) -> Vec<PickledInboundGroupSession> {
+        stream::iter(sessions)
+            .map(|session| session.pickle())
+            .buffered(PICKLE_CONCURRENCY)
+            .collect()
+            .await
+    }
+
     /// Export this session at the first known message index.
     ///
     /// If only a limited part of this session should be exported use
@@ -280,11 +404,61 @@ impl InboundGroupSession {
         self.backed_up.store(true, SeqCst)
     }
 
+    /// Get the time at which we received this session from its creator.
+    ///
+    /// Returns `None` if the creation time is unknown, i.e. if the session was
+    /// imported or forwarded to us.
+    pub fn creation_time(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.creation_time
+    }
+
+    /// Get how old this session is at the given point in time.
+    ///
+    /// This can be used to decide whether to request a fresher copy of the
+    /// room key. Returns `None` if the creation time of the session is
+    /// unknown, see [`InboundGroupSession::creation_time()`].
+    pub fn age(&self, now: MilliSecondsSinceUnixEpoch) -> Option<Duration> {
+        let creation_time = self.creation_time?;
+        Some(Duration::from_millis(now.get().saturating_sub(creation_time.get()).into()))
+    }
+
+    /// Get the number of messages this session successfully decrypted.
+    ///
+    /// The counter isn't persisted, it starts at zero when the session is
+    /// created or restored from a pickle.
+    pub fn decrypt_count(&self) -> u32 {
+        self.decrypt_count.load(SeqCst)
+    }
+
+    /// Get the chain of Curve25519 keys through which this session was
+    /// forwarded.
+    ///
+    /// If the session was forwarded to us, the last key in the chain is the
+    /// one of the device that sent us the `m.forwarded_room_key` event. The
+    /// chain is empty if the session was received directly from its creator,
+    /// or if the forwarding history of the session is unknown.
+    pub fn forwarding_chain(&self) -> &[Curve25519PublicKey] {
+        &self.forwarding_chain
+    }
+
     /// Get the map of signing keys this session was received from.
     pub fn signing_keys(&self) -> &SigningKeys<DeviceKeyAlgorithm> {
         &self.creator_info.signing_keys
     }
 
+    /// Get the map of keys the creator of this session claims to own.
+    ///
+    /// This is the same map as [`InboundGroupSession::signing_keys()`], the
+    /// name is meant to make the trust level explicit at the call site. The
+    /// keys are only trusted if the session was received directly from its
+    /// creator, see [`InboundGroupSession::is_trusted_source()`]. For
+    /// forwarded or imported sessions, e.g. ones created from the
+    /// `sender_claimed_keys` of an [`ExportedRoomKey`], they are a claim made
+    /// by whoever sent us the session and haven't been verified.
+    pub fn claimed_keys(&self) -> &SigningKeys<DeviceKeyAlgorithm> {
+        self.signing_keys()
+    }
+
     /// Export this session at the given message index.
     pub async fn export_at_index(&self, message_index: u32) -> ExportedRoomKey {
         let message_index = std::cmp::max(self.first_known_index(), message_index);
@@ -297,16 +471,106 @@ impl InboundGroupSession {
             room_id: self.room_id().to_owned(),
             sender_key: self.creator_info.curve25519_key,
             session_id: self.session_id().to_owned(),
-            forwarding_curve25519_key_chain: vec![],
+            forwarding_curve25519_key_chain: self.forwarding_chain().to_vec(),
             sender_claimed_keys: (*self.creator_info.signing_keys).clone(),
             session_key,
         }
     }
 
+    /// Export this session to share the messages in the given range of message
+    /// indices.
+    ///
+    /// **Note**: Megolm ratchets can only be advanced, an exported room key
+    /// can't be limited to an upper bound. The recipient of the room key will
+    /// be able to decrypt every message starting at `from`, including the ones
+    /// sent after `to`. The `to` index is only used to check that the range
+    /// makes sense. To stop sharing future messages, the outbound session has
+    /// to be rotated.
+    ///
+    /// If `from` comes before the [first known
+    /// index](InboundGroupSession::first_known_index()), the room key is
+    /// exported at the first known index instead, like
+    /// [`InboundGroupSession::export_at_index()`] does.
+    ///
+    /// Returns an error if `from` comes after `to`, or if this session can't
+    /// decrypt any message of the range.
+    pub async fn export_range(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<ExportedRoomKey, SessionExportError> {
+        if from > to {
+            return Err(SessionExportError::InvalidRange { from, to });
+        }
+
+        if self.first_known_index() > to {
+            return Err(SessionExportError::UnknownRange {
+                first_known_index: self.first_known_index(),
+                to,
+            });
+        }
+
+        Ok(self.export_at_index(from).await)
+    }
+
+    /// Create an `InboundGroupSession` from the content of an
+    /// `m.forwarded_room_key` event, recording whether the device that
+    /// forwarded it was verified.
+    ///
+    /// The [`From`] implementation for [`ForwardedMegolmV1AesSha2Content`]
+    /// treats the forwarding device as unverified.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the forwarded room key event.
+    ///
+    /// * `forwarded_by_verified_device` - Whether we had verified the device
+    /// that forwarded the room key to us.
+    pub fn from_forwarded_content(
+        content: &ForwardedMegolmV1AesSha2Content,
+        forwarded_by_verified_device: bool,
+    ) -> Self {
+        Self { forwarded_by_verified_device, ..Self::from(content) }
+    }
+
+    /// Create an `InboundGroupSession` from a received `m.forwarded_room_key`
+    /// event.
+    ///
+    /// Unlike the [`TryFrom`] implementation, this appends the Curve25519 key
+    /// of the device that forwarded the session to us to the forwarding chain,
+    /// as the spec requires, and records whether that device was verified.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender_key` - The Curve25519 key of the device that sent us the
+    /// event.
+    ///
+    /// * `event` - The decrypted `m.forwarded_room_key` event.
+    ///
+    /// * `forwarded_by_verified_device` - Whether we had verified the device
+    /// that forwarded the room key to us.
+    pub(crate) fn from_forwarded_room_key(
+        sender_key: Curve25519PublicKey,
+        event: &DecryptedForwardedRoomKeyEvent,
+        forwarded_by_verified_device: bool,
+    ) -> Result<Self, SessionCreationError> {
+        let session = Self::try_from(event)?;
+
+        let mut forwarding_chain = session.forwarding_chain.as_ref().clone();
+        forwarding_chain.push(sender_key);
+
+        Ok(Self {
+            forwarding_chain: forwarding_chain.into(),
+            forwarded_by_verified_device,
+            ..session
+        })
+    }
+
     /// Restore a Session from a previously pickled string.
     ///
-    /// Returns the restored group session or a `UnpicklingError` if there
-    /// was an error.
+    /// Returns the restored group session. The pickle is already deserialized
+    /// at this point, so this can't currently fail; stores report corrupt
+    /// entries when they deserialize them.
     ///
     /// # Arguments
     ///
@@ -331,7 +595,11 @@ impl InboundGroupSession {
             room_id: (*pickle.room_id).into(),
             backed_up: AtomicBool::from(pickle.backed_up).into(),
             algorithm: pickle.algorithm.into(),
+            forwarding_chain: pickle.forwarding_curve25519_key_chain.into(),
             imported: pickle.imported,
+            decrypt_count: AtomicU32::new(0).into(),
+            creation_time: pickle.creation_time,
+            forwarded_by_verified_device: pickle.forwarded_by_verified_device,
         })
     }
 
@@ -345,29 +613,174 @@ impl InboundGroupSession {
         &self.session_id
     }
 
+    /// Get the key that orders this session for backups.
+    ///
+    /// Sessions are totally ordered by their room ID and then by their
+    /// session ID, which lets an uploader checkpoint its progress and resume
+    /// it with [`Store::sessions_needing_backup_after()`].
+    ///
+    /// [`Store::sessions_needing_backup_after()`]: crate::store::Store::sessions_needing_backup_after
+    pub fn backup_order_key(&self) -> (OwnedRoomId, String) {
+        (self.room_id.clone(), self.session_id.to_string())
+    }
+
     /// The algorithm that this inbound group session is using to decrypt
     /// events.
     pub fn algorithm(&self) -> &EventEncryptionAlgorithm {
         &self.algorithm
     }
 
+    /// Whether this session uses an experimental algorithm, i.e.
+    /// `m.megolm.v2.aes-sha2`.
+    ///
+    /// This always returns `false` if the `experimental-algorithms` feature is
+    /// disabled.
+    pub fn is_experimental_algorithm(&self) -> bool {
+        #[cfg(feature = "experimental-algorithms")]
+        {
+            self.algorithm() == &EventEncryptionAlgorithm::MegolmV2AesSha2
+        }
+
+        #[cfg(not(feature = "experimental-algorithms"))]
+        {
+            false
+        }
+    }
+
     /// Get the first message index we know how to decrypt.
     pub fn first_known_index(&self) -> u32 {
         self.first_known_index
     }
 
+    /// Check, for each of the given message indices, whether this session can
+    /// decrypt a message encrypted at that index.
+    ///
+    /// A Megolm ratchet can only move forward, so this is the case for every
+    /// index starting at the [first known
+    /// index](InboundGroupSession::first_known_index()). This doesn't need to
+    /// touch the ratchet, so it's cheap even for a large number of indices.
+    pub fn probe_indices(&self, indices: &[u32]) -> Vec<(u32, bool)> {
+        indices.iter().map(|&index| (index, index >= self.first_known_index)).collect()
+    }
+
+    /// Check if this session can decrypt enough of the room history to be
+    /// useful.
+    ///
+    /// Returns `false` if the first message index we know how to decrypt is
+    /// larger than the given `threshold`, this is useful to warn users if
+    /// imported or restored room keys won't cover much of the history.
+    ///
+    /// **Note**: The threshold is only a heuristic, a session that passes
+    /// this check isn't more trustworthy than one that doesn't.
+    pub fn is_usable_for_history(&self, threshold: u32) -> bool {
+        self.first_known_index <= threshold
+    }
+
     /// Has the session been imported from a file or server-side backup? As
     /// opposed to being directly received as an `m.room_key` event.
     pub fn has_been_imported(&self) -> bool {
         self.imported
     }
 
+    /// Was this session received directly from its creator?
+    ///
+    /// This is the case if the session was received as an `m.room_key` event,
+    /// over an Olm channel with the device that created it. Sessions that were
+    /// forwarded to us, or imported from a file or a server-side backup, are
+    /// not received from a trusted source.
+    pub fn is_trusted_source(&self) -> bool {
+        !self.imported
+    }
+
+    /// Was the Ed25519 signing key of the session creator received from the
+    /// creator itself?
+    ///
+    /// If the session wasn't received directly from its creator, the signing
+    /// key is only a claim made by the device that sent us the session.
+    pub fn signing_key_verified(&self) -> bool {
+        self.is_trusted_source() && self.signing_keys().get(&DeviceKeyAlgorithm::Ed25519).is_some()
+    }
+
+    /// Was this session forwarded to us by a device that we had verified at
+    /// the time we received it?
+    ///
+    /// This records the trust decision made when the `m.forwarded_room_key`
+    /// event was received, see
+    /// [`InboundGroupSession::from_forwarded_content()`]. It's always `false`
+    /// for sessions that weren't forwarded to us.
+    pub fn forwarded_by_verified_device(&self) -> bool {
+        self.forwarded_by_verified_device
+    }
+
+    /// Get how this session was received.
+    pub(crate) fn key_source_kind(&self) -> KeySourceKind {
+        KeySourceKind::new(self.imported, &self.forwarding_chain)
+    }
+
+    /// Get the metadata of this session, see
+    /// [`PickledInboundGroupSession::metadata()`].
+    pub(crate) fn metadata(&self) -> SessionMetadata {
+        SessionMetadata {
+            room_id: self.room_id.clone(),
+            sender_key: self.sender_key(),
+            algorithm: self.algorithm().clone(),
+            imported: self.imported,
+            backed_up: self.backed_up(),
+            key_source: self.key_source_kind(),
+        }
+    }
+
+    /// Get a flat summary of the trust state of this session.
+    ///
+    /// The summary only contains owned values, keys are encoded as unpadded
+    /// base64 strings, so it can easily be passed to bindings.
+    pub fn trust_summary(&self) -> SessionTrustSummary {
+        let key_source = self.key_source_kind();
+        let signing_key_verified = self.signing_key_verified();
+
+        SessionTrustSummary {
+            is_trusted: self.is_trusted_source() && signing_key_verified,
+            key_source,
+            sender_key: self.sender_key().to_base64(),
+            claimed_ed25519: self
+                .signing_keys()
+                .get(&DeviceKeyAlgorithm::Ed25519)
+                .map(|key| key.to_base64()),
+            signing_key_verified,
+        }
+    }
+
+    /// Get a stable fingerprint of the logical room key this session
+    /// represents.
+    ///
+    /// The fingerprint is an unpadded base64 encoded SHA-256 hash over the
+    /// session ID, room ID, sender key and algorithm of the session. It
+    /// deliberately doesn't include the first known index or any other ratchet
+    /// state, so two copies of the same chain, exported at different message
+    /// indices, will produce the same fingerprint.
+    ///
+    /// This is meant to be used to deduplicate room keys across stores, it
+    /// **must not** be used to make any security decisions.
+    pub fn key_fingerprint(&self) -> String {
+        let hash = Sha256::new()
+            .chain_update(self.session_id().as_bytes())
+            .chain_update([0])
+            .chain_update(self.room_id().as_bytes())
+            .chain_update([0])
+            .chain_update(self.sender_key().as_bytes())
+            .chain_update([0])
+            .chain_update(self.algorithm().as_str().as_bytes())
+            .finalize();
+
+        encode(hash)
+    }
+
     /// Check if the `InboundGroupSession` is better than the given other
     /// `InboundGroupSession`
     pub async fn compare(&self, other: &InboundGroupSession) -> SessionOrdering {
         // If this is the same object the ordering is the same, we can't compare because
         // we would deadlock while trying to acquire the same lock twice.
-        if Arc::ptr_eq(&self.inner, &other.inner) {
+        if self.is_same_instance(other) {
             SessionOrdering::Equal
         } else if self.sender_key() != other.sender_key()
             || self.signing_keys() != other.signing_keys()
@@ -381,19 +794,104 @@ impl InboundGroupSession {
         }
     }
 
+    /// Get a key that sorts copies of this session by their quality.
+    ///
+    /// The key is made of the session ID and the first known index, so
+    /// sorting sessions by it groups copies of the same session together, the
+    /// best copy, i.e. the one that can decrypt the most messages, coming
+    /// first. This makes it possible to pick the best copy out of a collection
+    /// without comparing each pair of sessions using
+    /// [`InboundGroupSession::compare()`].
+    ///
+    /// **Note**: The key is only meaningful for copies of a session that are
+    /// connected, it doesn't check that the sessions were received from the
+    /// same sender or for the same room. Use
+    /// [`InboundGroupSession::compare()`] if the sessions might be
+    /// [`SessionOrdering::Unconnected`].
+    pub fn quality_key(&self) -> (Arc<str>, u32) {
+        (self.session_id.clone(), self.first_known_index)
+    }
+
+    /// Check if this `InboundGroupSession` and the given other one are handles
+    /// to the same underlying session object.
+    ///
+    /// This checks identity, not logical equality: a session that was loaded
+    /// from the store twice, or that was cloned from its pickle, isn't the
+    /// same instance even though it represents the same ratchet. Use
+    /// [`InboundGroupSession::eq_exact()`] to compare sessions logically.
+    pub fn is_same_instance(&self, other: &InboundGroupSession) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Check if this `InboundGroupSession` is exactly the same as the given
+    /// other `InboundGroupSession`, including the position of its ratchet.
+    ///
+    /// Unlike the [`PartialEq`] implementation, which only compares session
+    /// IDs, this returns `false` for two copies of the same chain that start
+    /// at different message indices.
+    pub async fn eq_exact(&self, other: &InboundGroupSession) -> bool {
+        self.session_id() == other.session_id()
+            && self.first_known_index() == other.first_known_index()
+            && self.compare(other).await == SessionOrdering::Equal
+    }
+
     /// Decrypt the given ciphertext.
     ///
     /// Returns the decrypted plaintext or an `DecryptionError` if
     /// decryption failed.
     ///
+    /// Decryption happens in a span carrying the session and room IDs and the
+    /// sender key of this session, so logs of every decryption path can be
+    /// attributed to a session. The message index, or the error if decryption
+    /// failed, is recorded in the span as well.
+    ///
     /// # Arguments
     ///
     /// * `message` - The message that should be decrypted.
+    #[instrument(
+        skip_all,
+        fields(
+            session_id = self.session_id(),
+            room_id = ?self.room_id(),
+            sender_key = %self.sender_key().to_base64(),
+            message_index,
+            error,
+        )
+    )]
     pub(crate) async fn decrypt_helper(
         &self,
         message: &MegolmMessage,
     ) -> Result<DecryptedMessage, DecryptionError> {
-        self.inner.lock().await.decrypt(message)
+        let decrypted = self.inner.lock().await.decrypt(message).map_err(|e| {
+            Span::current().record("error", debug(&e));
+            e
+        })?;
+
+        Span::current().record("message_index", decrypted.message_index);
+        self.decrypt_count.fetch_add(1, SeqCst);
+
+        Ok(decrypted)
+    }
+
+    /// Decrypt the given Megolm message into its raw plaintext.
+    ///
+    /// Unlike [`InboundGroupSession::decrypt()`], this doesn't try to parse the
+    /// plaintext as an event, nor does it check that it belongs to the room of
+    /// this session. It's meant for tooling that needs to inspect payloads
+    /// which might not be events.
+    ///
+    /// Returns the plaintext bytes and the message index of the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message that should be decrypted.
+    pub async fn decrypt_to_bytes(
+        &self,
+        message: &MegolmMessage,
+    ) -> Result<(Vec<u8>, u32), DecryptionError> {
+        let decrypted = self.decrypt_helper(message).await?;
+
+        Ok((decrypted.plaintext, decrypted.message_index))
     }
 
     /// Export the inbound group session into a format that can be uploaded to
@@ -412,77 +910,438 @@ impl InboundGroupSession {
         &self,
         event: &EncryptedEvent,
     ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32)> {
-        let decrypted = match &event.content.scheme {
+        self.decrypt_checked(event, None).await
+    }
+
+    /// Decrypt an event from a room timeline, serving the plaintext from the
+    /// given cache if the same message was decrypted before.
+    pub(crate) async fn decrypt_with_cache(
+        &self,
+        event: &EncryptedEvent,
+        cache: &DecryptionCache,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32)> {
+        self.decrypt_checked(event, Some(cache)).await
+    }
+
+    async fn decrypt_checked(
+        &self,
+        event: &EncryptedEvent,
+        cache: Option<&DecryptionCache>,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32)> {
+        let (decrypted, message_index, room_id) = self.decrypt_event_helper(event, cache).await?;
+
+        // Check that we have a room id and that the event wasn't forwarded from
+        // another room.
+        if room_id.as_deref() != Some(self.room_id()) {
+            return Err(EventError::MismatchedRoom(self.room_id().to_owned(), room_id).into());
+        }
+
+        Ok((decrypted, message_index))
+    }
+
+    /// Decrypt an event from a room timeline, and return the metadata of the
+    /// encrypted event alongside the decrypted event.
+    ///
+    /// This behaves exactly like [`InboundGroupSession::decrypt()`], the
+    /// returned [`DecryptionMetadata`] is useful for audit logging or to
+    /// reconstruct the original `m.room.encrypted` content without having to
+    /// parse the event again.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be decrypted.
+    pub async fn decrypt_with_metadata(
+        &self,
+        event: &EncryptedEvent,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, DecryptionMetadata)> {
+        let (session_id, sender_key, device_id) = match &event.content.scheme {
             RoomEventEncryptionScheme::MegolmV1AesSha2(c) => {
-                self.decrypt_helper(&c.ciphertext).await?
+                (c.session_id.clone(), Some(c.sender_key), Some(c.device_id.clone()))
             }
             #[cfg(feature = "experimental-algorithms")]
-            RoomEventEncryptionScheme::MegolmV2AesSha2(c) => {
-                self.decrypt_helper(&c.ciphertext).await?
-            }
+            RoomEventEncryptionScheme::MegolmV2AesSha2(c) => (c.session_id.clone(), None, None),
             RoomEventEncryptionScheme::Unknown(_) => {
                 return Err(EventError::UnsupportedAlgorithm.into());
             }
         };
 
-        let plaintext = String::from_utf8_lossy(&decrypted.plaintext);
-
-        let mut decrypted_value = serde_json::from_str::<Value>(&plaintext)?;
-        let decrypted_object = decrypted_value.as_object_mut().ok_or(EventError::NotAnObject)?;
+        let (decrypted, message_index) = self.decrypt(event).await?;
 
-        let server_ts: i64 = event.origin_server_ts.0.into();
+        Ok((decrypted, DecryptionMetadata { session_id, sender_key, device_id, message_index }))
+    }
 
-        decrypted_object.insert("sender".to_owned(), event.sender.to_string().into());
-        decrypted_object.insert("event_id".to_owned(), event.event_id.to_string().into());
-        decrypted_object.insert("origin_server_ts".to_owned(), server_ts.into());
+    /// Decrypt an event from a room timeline, and check it for problems that
+    /// don't prevent it from being decrypted.
+    ///
+    /// This behaves exactly like [`InboundGroupSession::decrypt()`], the
+    /// returned [`DecryptionWarning`]s can be fed into abuse detection. The
+    /// following problems are detected:
+    ///
+    /// * The timestamp of the event comes before the time at which we received
+    ///   this session by more than `tolerance`. Room keys can reach us a bit
+    ///   after the first events that were encrypted with them, the tolerance
+    ///   should account for that. This is never reported for sessions whose
+    ///   creation time is unknown, see
+    ///   [`InboundGroupSession::creation_time()`]. Since the creation time is
+    ///   the time at which we received the session, events sent while this
+    ///   device was offline are reported as well if the session only reached us
+    ///   once the device came back online.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be decrypted.
+    ///
+    /// * `tolerance` - How long before the creation of this session an event
+    ///   may have been sent without being reported.
+    pub async fn decrypt_with_warnings(
+        &self,
+        event: &EncryptedEvent,
+        tolerance: Duration,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32, Vec<DecryptionWarning>)> {
+        let (decrypted, message_index) = self.decrypt(event).await?;
 
-        let room_id = decrypted_object
-            .get("room_id")
-            .and_then(|r| r.as_str().and_then(|r| RoomId::parse(r).ok()));
+        let warnings =
+            self.timestamp_warning(event.origin_server_ts, tolerance).into_iter().collect();
 
-        // Check that we have a room id and that the event wasn't forwarded from
-        // another room.
-        if room_id.as_deref() != Some(self.room_id()) {
-            return Err(EventError::MismatchedRoom(self.room_id().to_owned(), room_id).into());
-        }
+        Ok((decrypted, message_index, warnings))
+    }
 
-        decrypted_object.insert(
-            "unsigned".to_owned(),
-            serde_json::to_value(&event.unsigned).unwrap_or_default(),
+    pub(crate) fn timestamp_warning(
+        &self,
+        event_timestamp: MilliSecondsSinceUnixEpoch,
+        tolerance: Duration,
+    ) -> Option<DecryptionWarning> {
+        let session_creation_time = self.creation_time?;
+        let skew = Duration::from_millis(
+            session_creation_time.get().saturating_sub(event_timestamp.get()).into(),
         );
 
-        if let Some(decrypted_content) =
-            decrypted_object.get_mut("content").and_then(|c| c.as_object_mut())
-        {
-            if !decrypted_content.contains_key("m.relates_to") {
-                if let Some(relation) = &event.content.relates_to {
-                    decrypted_content.insert("m.relates_to".to_owned(), relation.to_owned());
-                }
-            }
-        }
-
-        Ok((
-            serde_json::from_value::<Raw<AnyTimelineEvent>>(decrypted_value)?,
-            decrypted.message_index,
-        ))
-    }
-}
-
-#[cfg(not(tarpaulin_include))]
-impl fmt::Debug for InboundGroupSession {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("InboundGroupSession").field("session_id", &self.session_id()).finish()
+        (skew > tolerance).then_some(DecryptionWarning::TimestampBeforeSession {
+            event_timestamp,
+            session_creation_time,
+        })
     }
-}
 
-impl PartialEq for InboundGroupSession {
-    fn eq(&self, other: &Self) -> bool {
-        self.session_id() == other.session_id()
+    /// Decrypt an event from a room timeline without checking that the event
+    /// belongs to the room of this session.
+    ///
+    /// **Warning**: This method is **not** meant to be used in production, it
+    /// skips the check that protects against events being forwarded from
+    /// another room. It's only available with the `testing` feature, for
+    /// forensic tooling that needs to inspect the payload of events that were
+    /// captured without a known room context. Use
+    /// [`InboundGroupSession::decrypt()`] for anything else.
+    ///
+    /// Returns the decrypted event, the message index, and the room ID the
+    /// decrypted event claims to belong to, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be decrypted.
+    #[cfg(any(test, feature = "testing"))]
+    pub async fn decrypt_unchecked(
+        &self,
+        event: &EncryptedEvent,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32, Option<OwnedRoomId>)> {
+        self.decrypt_event_helper(event, None).await
     }
-}
 
-/// A pickled version of an `InboundGroupSession`.
-///
+    /// Check if this session can decrypt the given event, and explain why not
+    /// if it can't.
+    ///
+    /// This is meant to diagnose why an event is shown as undecryptable. It
+    /// checks that the event was encrypted with this session, that the
+    /// message index of the event is one this session knows about and that
+    /// a trial decryption succeeds and yields an event for the room of this
+    /// session.
+    ///
+    /// The trial decryption happens on a copy of the session, neither the
+    /// ratchet nor any other state of this session is modified and the
+    /// plaintext is thrown away.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be diagnosed.
+    pub async fn diagnose_event(&self, event: &EncryptedEvent) -> DecryptDiagnosis {
+        let (session_id, message) = match &event.content.scheme {
+            RoomEventEncryptionScheme::MegolmV1AesSha2(c) => (&c.session_id, &c.ciphertext),
+            #[cfg(feature = "experimental-algorithms")]
+            RoomEventEncryptionScheme::MegolmV2AesSha2(c) => (&c.session_id, &c.ciphertext),
+            RoomEventEncryptionScheme::Unknown(_) => {
+                return DecryptDiagnosis::UnsupportedAlgorithm;
+            }
+        };
+
+        if session_id != self.session_id() {
+            return DecryptDiagnosis::SessionMismatch { event_session_id: session_id.to_owned() };
+        }
+
+        let message_index = message.message_index();
+        let first_known_index = self.first_known_index();
+
+        if message_index < first_known_index {
+            return DecryptDiagnosis::UnknownMessageIndex { message_index, first_known_index };
+        }
+
+        let Ok(config) = OutboundGroupSession::session_config(self.algorithm()) else {
+            return DecryptDiagnosis::UnsupportedAlgorithm;
+        };
+
+        // Exporting at any other index might move the ratchet of our session.
+        let session_key = self.inner.lock().await.export_at_first_known_index();
+        let mut trial_session = InnerSession::import(&session_key, config);
+
+        let plaintext = match trial_session.decrypt(message) {
+            Ok(decrypted) => decrypted.plaintext,
+            Err(error) => {
+                return DecryptDiagnosis::DecryptionFailed {
+                    message_index,
+                    error: error.to_string(),
+                };
+            }
+        };
+
+        let Some(claimed_room_id) = serde_json::from_slice::<Value>(&plaintext)
+            .ok()
+            .filter(Value::is_object)
+            .map(|decrypted| {
+                decrypted
+                    .get("room_id")
+                    .and_then(|r| r.as_str().and_then(|r| RoomId::parse(r).ok()))
+            })
+        else {
+            return DecryptDiagnosis::InvalidPlaintext { message_index };
+        };
+
+        if claimed_room_id.as_deref() != Some(self.room_id()) {
+            return DecryptDiagnosis::RoomMismatch { message_index, claimed_room_id };
+        }
+
+        DecryptDiagnosis::Decryptable { message_index }
+    }
+
+    /// Decrypt the given message, using the cache if one is given.
+    async fn decrypt_cached(
+        &self,
+        message: &MegolmMessage,
+        cache: Option<&DecryptionCache>,
+    ) -> Result<(Vec<u8>, u32), DecryptionError> {
+        let Some(cache) = cache else {
+            return self.decrypt_to_bytes(message).await;
+        };
+
+        if let Some(plaintext) = cache.get(self.session_id(), message) {
+            return Ok((plaintext, message.message_index()));
+        }
+
+        let (plaintext, message_index) = self.decrypt_to_bytes(message).await?;
+        cache.insert(self.session_id(), message, &plaintext);
+
+        Ok((plaintext, message_index))
+    }
+
+    async fn decrypt_event_helper(
+        &self,
+        event: &EncryptedEvent,
+        cache: Option<&DecryptionCache>,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32, Option<OwnedRoomId>)> {
+        let (plaintext, message_index) = match &event.content.scheme {
+            RoomEventEncryptionScheme::MegolmV1AesSha2(c) => {
+                self.decrypt_cached(&c.ciphertext, cache).await?
+            }
+            #[cfg(feature = "experimental-algorithms")]
+            RoomEventEncryptionScheme::MegolmV2AesSha2(c) => {
+                self.decrypt_cached(&c.ciphertext, cache).await?
+            }
+            RoomEventEncryptionScheme::Unknown(_) => {
+                return Err(EventError::UnsupportedAlgorithm.into());
+            }
+        };
+
+        let plaintext = String::from_utf8_lossy(&plaintext);
+
+        let mut decrypted_value = serde_json::from_str::<Value>(&plaintext)?;
+        let decrypted_object = decrypted_value.as_object_mut().ok_or(EventError::NotAnObject)?;
+
+        let server_ts: i64 = event.origin_server_ts.0.into();
+
+        decrypted_object.insert("sender".to_owned(), event.sender.to_string().into());
+        decrypted_object.insert("event_id".to_owned(), event.event_id.to_string().into());
+        decrypted_object.insert("origin_server_ts".to_owned(), server_ts.into());
+
+        let room_id = decrypted_object
+            .get("room_id")
+            .and_then(|r| r.as_str().and_then(|r| RoomId::parse(r).ok()));
+
+        decrypted_object.insert(
+            "unsigned".to_owned(),
+            serde_json::to_value(&event.unsigned).unwrap_or_default(),
+        );
+
+        if let Some(decrypted_content) =
+            decrypted_object.get_mut("content").and_then(|c| c.as_object_mut())
+        {
+            if !decrypted_content.contains_key("m.relates_to") {
+                if let Some(relation) = &event.content.relates_to {
+                    decrypted_content.insert("m.relates_to".to_owned(), relation.to_owned());
+                }
+            }
+        }
+
+        Ok((
+            serde_json::from_value::<Raw<AnyTimelineEvent>>(decrypted_value)?,
+            message_index,
+            room_id,
+        ))
+    }
+}
+
+/// A problem detected while decrypting an event that doesn't prevent the event
+/// from being decrypted, as returned by
+/// [`InboundGroupSession::decrypt_with_warnings()`] and
+/// [`OlmMachine::decrypt_room_event_with_warnings()`].
+///
+/// [`OlmMachine::decrypt_room_event_with_warnings()`]: crate::OlmMachine::decrypt_room_event_with_warnings
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecryptionWarning {
+    /// The timestamp of the event comes before the time at which we received
+    /// the session, by more than the allowed tolerance.
+    ///
+    /// An event can't be encrypted with a session that doesn't exist yet, so
+    /// the timestamp of the event might have been spoofed or its content
+    /// might have been replayed.
+    TimestampBeforeSession {
+        /// The `origin_server_ts` of the event.
+        event_timestamp: MilliSecondsSinceUnixEpoch,
+        /// The time at which we received the session, see
+        /// [`InboundGroupSession::creation_time()`].
+        session_creation_time: MilliSecondsSinceUnixEpoch,
+    },
+}
+
+/// The result of [`InboundGroupSession::diagnose_event()`], explains whether
+/// an event can be decrypted with a session and, if not, why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecryptDiagnosis {
+    /// The event can be decrypted with the session.
+    Decryptable {
+        /// The message index of the event.
+        message_index: u32,
+    },
+    /// The event is encrypted with an algorithm we don't support.
+    UnsupportedAlgorithm,
+    /// The event was encrypted with a different session.
+    SessionMismatch {
+        /// The ID of the session the event was encrypted with.
+        event_session_id: String,
+    },
+    /// The event was encrypted at a message index that comes before the
+    /// first index the session knows about.
+    UnknownMessageIndex {
+        /// The message index of the event.
+        message_index: u32,
+        /// The first message index the session can decrypt.
+        first_known_index: u32,
+    },
+    /// The ciphertext of the event couldn't be decrypted, e.g. because its
+    /// MAC or its signature is invalid.
+    DecryptionFailed {
+        /// The message index of the event.
+        message_index: u32,
+        /// A description of the decryption error.
+        error: String,
+    },
+    /// The event was decrypted, but the plaintext isn't a JSON object.
+    InvalidPlaintext {
+        /// The message index of the event.
+        message_index: u32,
+    },
+    /// The event was decrypted, but it claims to belong to a different room
+    /// than the session, it might have been forwarded from another room.
+    RoomMismatch {
+        /// The message index of the event.
+        message_index: u32,
+        /// The room ID the decrypted event claims to belong to, if any.
+        claimed_room_id: Option<OwnedRoomId>,
+    },
+}
+
+/// The metadata of an `m.room.encrypted` event, as returned by
+/// [`InboundGroupSession::decrypt_with_metadata()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecryptionMetadata {
+    /// The ID of the session the event claims to be encrypted with.
+    pub session_id: String,
+    /// The Curve25519 key of the sender, as claimed by the event.
+    ///
+    /// This is `None` for algorithms that don't include the sender key in
+    /// the event content.
+    pub sender_key: Option<Curve25519PublicKey>,
+    /// The ID of the sending device, as claimed by the event.
+    ///
+    /// This is `None` for algorithms that don't include the device ID in the
+    /// event content.
+    pub device_id: Option<OwnedDeviceId>,
+    /// The message index of the decrypted message.
+    pub message_index: u32,
+}
+
+/// Get the ranges of message indices, inclusive, that none of the given copies
+/// of a session can decrypt.
+///
+/// The copies are compared to the first session of the slice, copies that
+/// aren't connected to it, see [`InboundGroupSession::compare()`], are
+/// ignored.
+///
+/// A Megolm ratchet can only move forward, a copy of a session can decrypt
+/// every message starting from its first known index. The union of the copies
+/// can thus decrypt every message starting from the lowest first known index,
+/// the only messages that can't be decrypted are the ones before it. The
+/// returned list contains at most a single range, it's empty if one of the
+/// copies starts at index 0 or if no sessions are given.
+pub async fn decryptable_gaps(sessions: &[InboundGroupSession]) -> Vec<(u32, u32)> {
+    let Some((first, others)) = sessions.split_first() else {
+        return Vec::new();
+    };
+
+    let mut lowest_index = first.first_known_index();
+
+    for session in others {
+        if first.compare(session).await != SessionOrdering::Unconnected {
+            lowest_index = lowest_index.min(session.first_known_index());
+        }
+    }
+
+    if lowest_index > 0 {
+        vec![(0, lowest_index - 1)]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for InboundGroupSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InboundGroupSession").field("session_id", &self.session_id()).finish()
+    }
+}
+
+/// **Warning**: Two `InboundGroupSession`s are considered equal if they have
+/// the same session ID, regardless of the position of their ratchets. A copy of
+/// a session that can only decrypt messages from a later index compares equal
+/// to the original one, even though it can decrypt fewer messages.
+///
+/// This makes it suitable to deduplicate sessions, e.g. as map keys, use
+/// [`InboundGroupSession::eq_exact()`] to check if two sessions are exactly the
+/// same.
+impl PartialEq for InboundGroupSession {
+    fn eq(&self, other: &Self) -> bool {
+        self.session_id() == other.session_id()
+    }
+}
+
+/// A pickled version of an `InboundGroupSession`.
+///
 /// Holds all the information that needs to be stored in a database to restore
 /// an InboundGroupSession.
 #[derive(Serialize, Deserialize)]
@@ -508,6 +1367,112 @@ pub struct PickledInboundGroupSession {
     /// The algorithm of this inbound group session.
     #[serde(default = "default_algorithm")]
     pub algorithm: EventEncryptionAlgorithm,
+    /// Chain of Curve25519 keys through which this session was forwarded.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_curve_key_vec",
+        serialize_with = "serialize_curve_key_vec"
+    )]
+    pub forwarding_curve25519_key_chain: Vec<Curve25519PublicKey>,
+    /// The time at which we received the session from its creator, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_time: Option<MilliSecondsSinceUnixEpoch>,
+    /// Flag remembering if the session was forwarded to us by a device we had
+    /// verified.
+    #[serde(default)]
+    pub forwarded_by_verified_device: bool,
+}
+
+impl PickledInboundGroupSession {
+    /// Get the metadata of the pickled session.
+    ///
+    /// This is a cheap projection of the plain fields of the pickle, it
+    /// doesn't require the ratchet to be unpickled. Note that the session ID
+    /// isn't part of the metadata since it's derived from the ratchet.
+    pub fn metadata(&self) -> SessionMetadata {
+        SessionMetadata {
+            room_id: self.room_id.clone(),
+            sender_key: self.sender_key,
+            algorithm: self.algorithm.clone(),
+            imported: self.imported,
+            backed_up: self.backed_up,
+            key_source: KeySourceKind::new(self.imported, &self.forwarding_curve25519_key_chain),
+        }
+    }
+}
+
+/// Metadata about a pickled `InboundGroupSession`, see
+/// [`PickledInboundGroupSession::metadata()`].
+#[derive(Clone, Debug)]
+pub struct SessionMetadata {
+    /// The id of the room that the session is used in.
+    pub room_id: OwnedRoomId,
+    /// The public Curve25519 key of the account that sent us the session.
+    pub sender_key: Curve25519PublicKey,
+    /// The algorithm of the session.
+    pub algorithm: EventEncryptionAlgorithm,
+    /// Was the session imported, as opposed to being directly sent to us by
+    /// its creator.
+    pub imported: bool,
+    /// Has the session been backed up.
+    pub backed_up: bool,
+    /// How the session was received.
+    pub key_source: KeySourceKind,
+}
+
+/// How an `InboundGroupSession` was received, see
+/// [`InboundGroupSession::trust_summary()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySourceKind {
+    /// The session was received directly from its creator, as an
+    /// `m.room_key` event.
+    Direct,
+    /// The session was forwarded to us by another device, as an
+    /// `m.forwarded_room_key` event.
+    Forwarded,
+    /// The session was imported from a file or a server-side backup, or its
+    /// origin is otherwise unknown.
+    Imported,
+}
+
+impl KeySourceKind {
+    fn new(imported: bool, forwarding_chain: &[Curve25519PublicKey]) -> Self {
+        if !imported {
+            KeySourceKind::Direct
+        } else if !forwarding_chain.is_empty() {
+            KeySourceKind::Forwarded
+        } else {
+            KeySourceKind::Imported
+        }
+    }
+
+    /// Get a stable, human-readable label for this key source, e.g. to display
+    /// it in a UI.
+    pub fn description(&self) -> &'static str {
+        match self {
+            KeySourceKind::Direct => "Received directly",
+            KeySourceKind::Forwarded => "Forwarded",
+            KeySourceKind::Imported => "Imported from a file or backup",
+        }
+    }
+}
+
+/// A summary of the trust state of an `InboundGroupSession`, see
+/// [`InboundGroupSession::trust_summary()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionTrustSummary {
+    /// Was the session received from a trusted source, with a verified signing
+    /// key.
+    pub is_trusted: bool,
+    /// How the session was received.
+    pub key_source: KeySourceKind,
+    /// The base64 encoded Curve25519 key of the session creator.
+    pub sender_key: String,
+    /// The base64 encoded Ed25519 key the session creator claims to own, if
+    /// any.
+    pub claimed_ed25519: Option<String>,
+    /// Was the Ed25519 signing key received from the session creator itself.
+    pub signing_key_verified: bool,
 }
 
 fn default_algorithm() -> EventEncryptionAlgorithm {
@@ -522,9 +1487,19 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
         let session = InnerSession::import(&key.session_key, config);
         let first_known_index = session.first_known_index();
 
+        // Don't trust the session ID of the export, a malicious export could
+        // otherwise shadow another session.
+        let session_id = session.session_id();
+        if session_id != key.session_id {
+            return Err(SessionCreationError::SessionIdMismatch {
+                claimed: key.session_id.to_owned(),
+                actual: session_id,
+            });
+        }
+
         Ok(InboundGroupSession {
             inner: Mutex::new(session).into(),
-            session_id: key.session_id.to_owned().into(),
+            session_id: session_id.into(),
             creator_info: SessionCreatorInfo {
                 curve25519_key: key.sender_key,
                 signing_keys: key.sender_claimed_keys.to_owned().into(),
@@ -534,7 +1509,11 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
             room_id: key.room_id.to_owned(),
             imported: true,
             algorithm: key.algorithm.to_owned().into(),
+            forwarding_chain: key.forwarding_curve25519_key_chain.to_owned().into(),
             backed_up: AtomicBool::from(false).into(),
+            decrypt_count: AtomicU32::new(0).into(),
+            creation_time: None,
+            forwarded_by_verified_device: false,
         })
     }
 }
@@ -561,7 +1540,11 @@ impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
             room_id: value.room_id.to_owned(),
             imported: true,
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
+            forwarding_chain: value.forwarding_curve25519_key_chain.to_owned().into(),
             backed_up: AtomicBool::from(false).into(),
+            decrypt_count: AtomicU32::new(0).into(),
+            creation_time: None,
+            forwarded_by_verified_device: false,
         }
     }
 }
@@ -584,7 +1567,11 @@ impl From<&ForwardedMegolmV2AesSha2Content> for InboundGroupSession {
             room_id: value.room_id.to_owned(),
             imported: true,
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
+            forwarding_chain: Vec::new().into(),
             backed_up: AtomicBool::from(false).into(),
+            decrypt_count: AtomicU32::new(0).into(),
+            creation_time: None,
+            forwarded_by_verified_device: false,
         }
     }
 }
@@ -593,24 +1580,59 @@ impl TryFrom<&DecryptedForwardedRoomKeyEvent> for InboundGroupSession {
     type Error = SessionCreationError;
 
     fn try_from(value: &DecryptedForwardedRoomKeyEvent) -> Result<Self, Self::Error> {
-        match &value.content {
-            ForwardedRoomKeyContent::MegolmV1AesSha2(c) => Ok(Self::from(c.deref())),
+        let (session, claimed) = match &value.content {
+            ForwardedRoomKeyContent::MegolmV1AesSha2(c) => (Self::from(c.deref()), &c.session_id),
             #[cfg(feature = "experimental-algorithms")]
-            ForwardedRoomKeyContent::MegolmV2AesSha2(c) => Ok(Self::from(c.deref())),
+            ForwardedRoomKeyContent::MegolmV2AesSha2(c) => (Self::from(c.deref()), &c.session_id),
             ForwardedRoomKeyContent::Unknown(c) => {
-                Err(SessionCreationError::Algorithm(c.algorithm.to_owned()))
+                return Err(SessionCreationError::Algorithm(c.algorithm.to_owned()));
             }
+        };
+
+        // The key request matching the event is found using the claimed
+        // session ID, it needs to be the one of the session we create.
+        if session.session_id() != claimed {
+            return Err(SessionCreationError::SessionIdMismatch {
+                claimed: claimed.to_owned(),
+                actual: session.session_id().to_owned(),
+            });
         }
+
+        Ok(session)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
     use matrix_sdk_test::async_test;
-    use ruma::{device_id, room_id, user_id, DeviceId, UserId};
+    use ruma::{
+        device_id, room_id, serde::Raw, uint, user_id, DeviceId, DeviceKeyAlgorithm,
+        MilliSecondsSinceUnixEpoch, UserId,
+    };
+    use serde_json::json;
     use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey};
 
-    use crate::{olm::InboundGroupSession, ReadOnlyAccount};
+    use crate::{
+        olm::{
+            decryptable_gaps, diff_room_key_exports, DecryptDiagnosis, DecryptionCache,
+            DecryptionWarning, ExportComparisonError, ExportValidationError, ExportedRoomKey,
+            InboundGroupSession, KeySourceKind, RoomKeyIndexDifference, SessionExportError,
+        },
+        types::{
+            events::{
+                forwarded_room_key::ForwardedRoomKeyContent,
+                olm_v1::DecryptedOlmV1Event,
+                room::encrypted::{EncryptedEvent, RoomEncryptedEventContent},
+                room_key::RoomKeyContent,
+            },
+            EventEncryptionAlgorithm,
+        },
+        utilities::json_convert,
+        ReadOnlyAccount, SessionCreationError,
+    };
 
     fn alice_id() -> &'static UserId {
         user_id!("@alice:example.org")
@@ -686,4 +1708,663 @@ mod tests {
 
         assert_eq!(inbound.compare(&copy).await, SessionOrdering::Unconnected);
     }
+
+    #[async_test]
+    async fn quality_key() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let worse = InboundGroupSession::from_export(&inbound.export_at_index(10).await).unwrap();
+        let worst = InboundGroupSession::from_export(&inbound.export_at_index(20).await).unwrap();
+
+        assert_eq!(inbound.compare(&worse).await, SessionOrdering::Better);
+        assert!(inbound.quality_key() < worse.quality_key());
+
+        let mut sessions = vec![worst, other.clone(), worse, inbound.clone()];
+        sessions.sort_by_key(InboundGroupSession::quality_key);
+        sessions.dedup_by_key(|s| s.session_id().to_owned());
+
+        let mut expected = vec![inbound, other];
+        expected.sort_by_key(InboundGroupSession::quality_key);
+
+        assert_eq!(sessions.len(), 2);
+        for (session, expected) in sessions.iter().zip(&expected) {
+            assert!(session.eq_exact(expected).await);
+        }
+    }
+
+    #[async_test]
+    async fn gaps_between_copies() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let at_10 = InboundGroupSession::from_export(&inbound.export_at_index(10).await).unwrap();
+        let at_50 = InboundGroupSession::from_export(&inbound.export_at_index(50).await).unwrap();
+
+        assert!(decryptable_gaps(&[]).await.is_empty());
+
+        // The copy at index 0 can reach every index the copy at index 50 can.
+        assert!(decryptable_gaps(&[at_50.clone(), inbound.clone()]).await.is_empty());
+        assert_eq!(decryptable_gaps(&[at_50.clone(), at_10.clone()]).await, [(0, 9)]);
+
+        // Sessions of another chain are ignored.
+        assert_eq!(decryptable_gaps(&[at_10, other, at_50]).await, [(0, 9)]);
+    }
+
+    #[async_test]
+    async fn exact_equality() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let windowed =
+            InboundGroupSession::from_export(&inbound.export_at_index(10).await).unwrap();
+        let copy = InboundGroupSession::from_pickle(inbound.pickle().await).unwrap();
+
+        // The session IDs are the same, so the sessions compare equal…
+        assert_eq!(inbound, windowed);
+        // …but they don't start at the same index.
+        assert!(!inbound.eq_exact(&windowed).await);
+        assert!(!windowed.eq_exact(&inbound).await);
+
+        assert!(inbound.eq_exact(&inbound).await);
+        assert!(inbound.eq_exact(&copy).await);
+
+        // Clones share the same session object, unpickled copies don't.
+        assert!(inbound.is_same_instance(&inbound.clone()));
+        assert!(!inbound.is_same_instance(&copy));
+    }
+
+    #[async_test]
+    async fn validate_export() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        inbound.export().await.validate().expect("A fresh export should be valid");
+
+        let mut export = inbound.export().await;
+        export.algorithm = EventEncryptionAlgorithm::OlmV1Curve25519AesSha2;
+        assert_matches!(export.validate(), Err(ExportValidationError::Algorithm(_)));
+
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let mut export = inbound.export().await;
+        export.session_id = other.session_id().to_owned();
+        assert_matches!(
+            export.validate(),
+            Err(ExportValidationError::SessionIdMismatch { claimed, actual }) => {
+                assert_eq!(claimed, other.session_id());
+                assert_eq!(actual, inbound.session_id());
+            }
+        );
+    }
+
+    #[async_test]
+    async fn better_export() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let full = inbound.export().await;
+        let windowed = inbound.export_at_index(10).await;
+
+        let better = ExportedRoomKey::better_of(&windowed, &full).unwrap();
+        assert!(std::ptr::eq(better, &full));
+        let better = ExportedRoomKey::better_of(&full, &windowed).unwrap();
+        assert!(std::ptr::eq(better, &full));
+
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert_matches!(
+            ExportedRoomKey::better_of(&full, &other.export().await),
+            Err(ExportComparisonError::DifferentSessions)
+        );
+
+        let mut invalid = inbound.export().await;
+        invalid.session_id = other.session_id().to_owned();
+        assert_matches!(
+            ExportedRoomKey::better_of(&full, &invalid),
+            Err(ExportComparisonError::Invalid(ExportValidationError::SessionIdMismatch { .. }))
+        );
+    }
+
+    #[async_test]
+    async fn export_diff() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, shared) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, windowed) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, only_a) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, only_b) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        // A forwarded copy of a room key is the same room key.
+        let mut forwarded = shared.export().await;
+        forwarded.forwarding_curve25519_key_chain.push(alice.identity_keys().curve25519);
+
+        let a = vec![shared.export().await, windowed.export().await, only_a.export().await];
+        let b = vec![forwarded, windowed.export_at_index(5).await, only_b.export().await];
+
+        let diff = diff_room_key_exports(&a, &b);
+
+        assert_eq!(diff.only_in_a.len(), 1);
+        assert_eq!(diff.only_in_a[0].session_id, only_a.session_id());
+        assert_eq!(diff.only_in_a[0].first_known_index, 0);
+        assert_eq!(diff.only_in_b.len(), 1);
+        assert_eq!(diff.only_in_b[0].session_id, only_b.session_id());
+        assert_eq!(
+            diff.different_indices,
+            vec![RoomKeyIndexDifference {
+                room_id: room_id.to_owned(),
+                session_id: windowed.session_id().to_owned(),
+                first_known_index_a: 0,
+                first_known_index_b: 5,
+            }]
+        );
+
+        assert!(diff_room_key_exports(&a, &a).is_empty());
+    }
+
+    #[async_test]
+    async fn export_range() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let export = inbound.export_range(100, 200).await.unwrap();
+        let imported = InboundGroupSession::from_export(&export).unwrap();
+        assert_eq!(imported.first_known_index(), 100);
+
+        // The end of the range isn't enforced, only checked.
+        let unbounded = InboundGroupSession::from_export(&inbound.export_at_index(100).await);
+        assert_eq!(imported.compare(&unbounded.unwrap()).await, SessionOrdering::Equal);
+
+        assert_matches!(
+            inbound.export_range(200, 100).await,
+            Err(SessionExportError::InvalidRange { from: 200, to: 100 })
+        );
+        assert_matches!(
+            imported.export_range(0, 50).await,
+            Err(SessionExportError::UnknownRange { first_known_index: 100, to: 50 })
+        );
+
+        // A range starting before the first known index is exported from there.
+        let export = imported.export_range(0, 150).await.unwrap();
+        assert_eq!(InboundGroupSession::from_export(&export).unwrap().first_known_index(), 100);
+    }
+
+    #[async_test]
+    async fn export_content_hash() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let export = inbound.export().await;
+
+        assert_eq!(export.content_hash(), inbound.export().await.content_hash());
+
+        // The hash survives a round trip through JSON.
+        let json = serde_json::to_value(&export).unwrap();
+        let deserialized: ExportedRoomKey = serde_json::from_value(json).unwrap();
+        assert_eq!(export.content_hash(), deserialized.content_hash());
+
+        // Unlike the fingerprint, the hash covers the session key.
+        let later = inbound.export_at_index(1).await;
+        assert_ne!(export.content_hash(), later.content_hash());
+    }
+
+    #[async_test]
+    async fn forwarded_by_verified_device() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert!(!inbound.forwarded_by_verified_device());
+
+        let bob = ReadOnlyAccount::with_device_id(user_id!("@bob:localhost"), device_id!("BOB"));
+        let carol =
+            ReadOnlyAccount::with_device_id(user_id!("@carol:localhost"), device_id!("CAROL"));
+        let bob_key = bob.identity_keys().curve25519;
+        let carol_key = carol.identity_keys().curve25519;
+
+        // The session already went through Bob before Carol forwarded it to us.
+        let mut export = inbound.export().await;
+        export.forwarding_curve25519_key_chain.push(bob_key);
+
+        let content: ForwardedRoomKeyContent = export.try_into().unwrap();
+        let event = DecryptedOlmV1Event::new(
+            alice_id(),
+            alice_id(),
+            carol.identity_keys().ed25519,
+            content,
+        );
+
+        let session = InboundGroupSession::try_from(&event).unwrap();
+        assert!(!session.forwarded_by_verified_device());
+
+        let content = assert_matches!(
+            &event.content,
+            ForwardedRoomKeyContent::MegolmV1AesSha2(c) => c
+        );
+        assert!(!InboundGroupSession::from(&**content).forwarded_by_verified_device());
+        assert!(InboundGroupSession::from_forwarded_content(content, true)
+            .forwarded_by_verified_device());
+
+        let session =
+            InboundGroupSession::from_forwarded_room_key(carol_key, &event, false).unwrap();
+        assert!(!session.forwarded_by_verified_device());
+
+        let session =
+            InboundGroupSession::from_forwarded_room_key(carol_key, &event, true).unwrap();
+        assert!(session.forwarded_by_verified_device());
+        assert_eq!(session.forwarding_chain(), [bob_key, carol_key]);
+        assert_eq!(session.trust_summary().key_source, KeySourceKind::Forwarded);
+
+        let pickle = session.pickle().await;
+        assert!(pickle.forwarded_by_verified_device);
+        let unpickled = InboundGroupSession::from_pickle(pickle).unwrap();
+        assert!(unpickled.forwarded_by_verified_device());
+    }
+
+    #[async_test]
+    async fn from_export_with_spoofed_session_id() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let mut export = inbound.export().await;
+        export.session_id = other.session_id().to_owned();
+
+        let error = InboundGroupSession::from_export(&export).unwrap_err();
+        assert_matches!(
+            error,
+            SessionCreationError::SessionIdMismatch { claimed, actual } => {
+                assert_eq!(claimed, other.session_id());
+                assert_eq!(actual, inbound.session_id());
+            }
+        );
+
+        let content: ForwardedRoomKeyContent = export.try_into().unwrap();
+        let event = DecryptedOlmV1Event::new(
+            alice_id(),
+            alice_id(),
+            alice.identity_keys().ed25519,
+            content,
+        );
+        assert_matches!(
+            InboundGroupSession::try_from(&event),
+            Err(SessionCreationError::SessionIdMismatch { .. })
+        );
+    }
+
+    #[async_test]
+    async fn new_from_room_key_event() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let identity_keys = alice.identity_keys();
+
+        let (outbound, _) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let event = DecryptedOlmV1Event::new(
+            alice_id(),
+            alice_id(),
+            identity_keys.ed25519,
+            outbound.as_content().await,
+        );
+        let session =
+            InboundGroupSession::new_from_room_key_event(identity_keys.curve25519, &event).unwrap();
+
+        assert_eq!(session.session_id(), outbound.session_id());
+        assert_eq!(session.room_id(), room_id);
+        assert_eq!(session.sender_key(), identity_keys.curve25519);
+        assert_eq!(
+            session.signing_keys().get(&DeviceKeyAlgorithm::Ed25519).map(|k| k.to_base64()),
+            Some(identity_keys.ed25519.to_base64())
+        );
+        assert_eq!(session.trust_summary().key_source, KeySourceKind::Direct);
+    }
+
+    #[async_test]
+    async fn unsupported_algorithm() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let identity_keys = alice.identity_keys();
+        let algorithm = EventEncryptionAlgorithm::from("org.example.unsupported");
+
+        let (outbound, _) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert_matches!(
+            InboundGroupSession::new(
+                identity_keys.curve25519,
+                identity_keys.ed25519,
+                room_id,
+                &outbound.session_key().await,
+                algorithm.clone(),
+                None,
+            ),
+            Err(SessionCreationError::Algorithm(a)) if a == algorithm
+        );
+
+        let content: RoomKeyContent = serde_json::from_value(json!({
+            "algorithm": algorithm,
+            "room_id": room_id,
+            "session_id": outbound.session_id(),
+        }))
+        .unwrap();
+        let event =
+            DecryptedOlmV1Event::new(alice_id(), alice_id(), identity_keys.ed25519, content);
+        assert_matches!(
+            InboundGroupSession::new_from_room_key_event(identity_keys.curve25519, &event),
+            Err(SessionCreationError::Algorithm(a)) if a == algorithm
+        );
+
+        let content: ForwardedRoomKeyContent = serde_json::from_value(json!({
+            "algorithm": algorithm,
+            "room_id": room_id,
+            "session_id": outbound.session_id(),
+        }))
+        .unwrap();
+        let event =
+            DecryptedOlmV1Event::new(alice_id(), alice_id(), identity_keys.ed25519, content);
+        assert_matches!(
+            InboundGroupSession::try_from(&event),
+            Err(SessionCreationError::Algorithm(a)) if a == algorithm
+        );
+    }
+
+    #[async_test]
+    async fn session_age() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let creation_time = inbound.creation_time().unwrap();
+        let later = MilliSecondsSinceUnixEpoch(creation_time.get() + uint!(5000));
+
+        assert_eq!(inbound.age(creation_time), Some(Duration::ZERO));
+        assert_eq!(inbound.age(later), Some(Duration::from_secs(5)));
+
+        let unpickled = InboundGroupSession::from_pickle(inbound.pickle().await).unwrap();
+        assert_eq!(unpickled.creation_time(), Some(creation_time));
+
+        let imported = InboundGroupSession::from_export(&inbound.export().await).unwrap();
+        assert_eq!(imported.creation_time(), None);
+        assert_eq!(imported.age(later), None);
+    }
+
+    #[async_test]
+    async fn timestamp_before_session() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let tolerance = Duration::from_secs(60);
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let creation_time = inbound.creation_time().unwrap();
+        let slightly_before = MilliSecondsSinceUnixEpoch(creation_time.get() - uint!(30_000));
+        let long_before = MilliSecondsSinceUnixEpoch(creation_time.get() - uint!(90_000));
+        let later = MilliSecondsSinceUnixEpoch(creation_time.get() + uint!(90_000));
+
+        assert_eq!(inbound.timestamp_warning(later, tolerance), None);
+        assert_eq!(inbound.timestamp_warning(slightly_before, tolerance), None);
+        assert_eq!(
+            inbound.timestamp_warning(long_before, tolerance),
+            Some(DecryptionWarning::TimestampBeforeSession {
+                event_timestamp: long_before,
+                session_creation_time: creation_time,
+            })
+        );
+
+        // We don't know when imported sessions were created.
+        let imported = InboundGroupSession::from_export(&inbound.export().await).unwrap();
+        assert_eq!(imported.timestamp_warning(long_before, tolerance), None);
+    }
+
+    fn encrypted_event(content: Raw<RoomEncryptedEventContent>) -> EncryptedEvent {
+        json_convert(&json!({
+            "sender": alice_id(),
+            "event_id": "$event:example.org",
+            "origin_server_ts": 0u64,
+            "type": "m.room.encrypted",
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    #[async_test]
+    async fn diagnose_event() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (other_outbound, _) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let first = encrypted_event(outbound.encrypt(json!({}), "m.dummy").await);
+        let second = encrypted_event(outbound.encrypt(json!({}), "m.dummy").await);
+        let other = encrypted_event(other_outbound.encrypt(json!({}), "m.dummy").await);
+
+        assert_eq!(
+            inbound.diagnose_event(&first).await,
+            DecryptDiagnosis::Decryptable { message_index: 0 }
+        );
+        assert_eq!(
+            inbound.diagnose_event(&second).await,
+            DecryptDiagnosis::Decryptable { message_index: 1 }
+        );
+        assert_eq!(
+            inbound.diagnose_event(&other).await,
+            DecryptDiagnosis::SessionMismatch {
+                event_session_id: other_outbound.session_id().to_owned()
+            }
+        );
+
+        let windowed = InboundGroupSession::from_export(&inbound.export_at_index(1).await).unwrap();
+        assert_eq!(
+            windowed.diagnose_event(&first).await,
+            DecryptDiagnosis::UnknownMessageIndex { message_index: 0, first_known_index: 1 }
+        );
+
+        let mut export = inbound.export().await;
+        export.room_id = room_id!("!other:localhost").to_owned();
+        let moved = InboundGroupSession::from_export(&export).unwrap();
+        assert_eq!(
+            moved.diagnose_event(&first).await,
+            DecryptDiagnosis::RoomMismatch {
+                message_index: 0,
+                claimed_room_id: Some(room_id.to_owned())
+            }
+        );
+
+        // Diagnosing doesn't touch the session.
+        assert_eq!(inbound.decrypt_count(), 0);
+    }
+
+    #[async_test]
+    async fn probe_indices() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let windowed =
+            InboundGroupSession::from_export(&inbound.export_at_index(10).await).unwrap();
+
+        assert_eq!(inbound.probe_indices(&[0, 10]), [(0, true), (10, true)]);
+        assert_eq!(
+            windowed.probe_indices(&[0, 9, 10, 11]),
+            [(0, false), (9, false), (10, true), (11, true)]
+        );
+    }
+
+    #[async_test]
+    async fn key_fingerprint() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        let (_, other) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let windowed =
+            InboundGroupSession::from_export(&inbound.export_at_index(10).await).unwrap();
+
+        assert_eq!(inbound.key_fingerprint(), windowed.key_fingerprint());
+        assert_ne!(inbound.key_fingerprint(), other.key_fingerprint());
+    }
+
+    #[async_test]
+    async fn decrypt_to_bytes() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        // The plaintext doesn't need to be an event, or even JSON.
+        let plaintext = "This is a secret to everybody".to_owned();
+        let first = outbound.encrypt_helper(plaintext.clone()).await;
+        let second = outbound.encrypt_helper(plaintext.clone()).await;
+
+        let (decrypted, message_index) = inbound.decrypt_to_bytes(&first).await.unwrap();
+        assert_eq!(decrypted, plaintext.as_bytes());
+        assert_eq!(message_index, 0);
+
+        let (_, message_index) = inbound.decrypt_to_bytes(&second).await.unwrap();
+        assert_eq!(message_index, 1);
+
+        let windowed = InboundGroupSession::from_export(&inbound.export_at_index(1).await).unwrap();
+        windowed.decrypt_to_bytes(&first).await.expect_err("The message index is too old");
+    }
+
+    #[async_test]
+    async fn decryption_cache() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let plaintext = "This is a secret to everybody".to_owned();
+        let first = outbound.encrypt_helper(plaintext.clone()).await;
+        let second = outbound.encrypt_helper(plaintext.clone()).await;
+
+        // The cache is disabled by default.
+        let cache = DecryptionCache::default();
+        inbound.decrypt_cached(&first, Some(&cache)).await.unwrap();
+        inbound.decrypt_cached(&first, Some(&cache)).await.unwrap();
+        assert_eq!(inbound.decrypt_count(), 2);
+
+        cache.set_capacity(1);
+        inbound.decrypt_cached(&first, Some(&cache)).await.unwrap();
+        let (decrypted, message_index) =
+            inbound.decrypt_cached(&first, Some(&cache)).await.unwrap();
+        assert_eq!(decrypted, plaintext.as_bytes());
+        assert_eq!(message_index, 0);
+        assert_eq!(inbound.decrypt_count(), 3);
+
+        // Decrypting another message evicts the first one.
+        inbound.decrypt_cached(&second, Some(&cache)).await.unwrap();
+        inbound.decrypt_cached(&first, Some(&cache)).await.unwrap();
+        assert_eq!(inbound.decrypt_count(), 5);
+    }
+
+    #[async_test]
+    async fn trust_summary() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let summary = inbound.trust_summary();
+        assert!(summary.is_trusted);
+        assert!(summary.signing_key_verified);
+        assert_eq!(summary.key_source, KeySourceKind::Direct);
+        assert_eq!(summary.key_source.description(), "Received directly");
+        assert_eq!(summary.sender_key, alice.identity_keys().curve25519.to_base64());
+        assert_eq!(summary.claimed_ed25519, Some(alice.identity_keys().ed25519.to_base64()));
+
+        let imported = InboundGroupSession::from_export(&inbound.export().await).unwrap();
+
+        let summary = imported.trust_summary();
+        assert!(!summary.is_trusted);
+        assert!(!summary.signing_key_verified);
+        assert_eq!(summary.key_source, KeySourceKind::Imported);
+        assert_eq!(summary.key_source.description(), "Imported from a file or backup");
+        assert_eq!(summary.sender_key, alice.identity_keys().curve25519.to_base64());
+        assert_eq!(imported.claimed_keys(), inbound.signing_keys());
+    }
+
+    #[async_test]
+    async fn pickle_many() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let mut sessions = Vec::new();
+        for _ in 0..20 {
+            let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+            sessions.push(inbound);
+        }
+
+        let pickles = InboundGroupSession::pickle_many(&sessions).await;
+        assert_eq!(pickles.len(), sessions.len());
+
+        for (session, pickle) in sessions.iter().zip(pickles) {
+            let unpickled = InboundGroupSession::from_pickle(pickle).unwrap();
+            assert_eq!(session.session_id(), unpickled.session_id());
+        }
+    }
+
+    #[async_test]
+    async fn usable_for_history() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert!(inbound.is_usable_for_history(0));
+
+        let truncated =
+            InboundGroupSession::from_export(&inbound.export_at_index(100).await).unwrap();
+        assert!(truncated.is_usable_for_history(100));
+        assert!(!truncated.is_usable_for_history(99));
+    }
+
+    #[async_test]
+    async fn pickle_metadata() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        inbound.mark_as_backed_up();
+
+        let metadata = inbound.pickle().await.metadata();
+
+        assert_eq!(metadata.room_id, room_id);
+        assert_eq!(metadata.sender_key, inbound.sender_key());
+        assert_eq!(&metadata.algorithm, inbound.algorithm());
+        assert!(!metadata.imported);
+        assert!(metadata.backed_up);
+    }
+
+    #[async_test]
+    async fn experimental_algorithm() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert!(!inbound.is_experimental_algorithm());
+
+        #[cfg(feature = "experimental-algorithms")]
+        {
+            use crate::olm::EncryptionSettings;
+
+            let settings = EncryptionSettings {
+                algorithm: EventEncryptionAlgorithm::MegolmV2AesSha2,
+                ..Default::default()
+            };
+            let (_, inbound) = alice.create_group_session_pair(room_id, settings).await.unwrap();
+            assert!(inbound.is_experimental_algorithm());
+        }
+    }
 }
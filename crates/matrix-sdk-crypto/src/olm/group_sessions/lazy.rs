@@ -0,0 +1,171 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, sync::Arc};
+
+use ruma::{
+    events::AnyTimelineEvent, serde::Raw, DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch,
+    OwnedRoomId, RoomId,
+};
+use vodozemac::{megolm::InboundGroupSession as InnerSession, Curve25519PublicKey};
+
+use super::{InboundGroupSession, PickledInboundGroupSession};
+use crate::{
+    error::{MegolmError, MegolmResult},
+    store::{DynCryptoStore, Result as StoreResult},
+    types::{events::room::encrypted::EncryptedEvent, EventEncryptionAlgorithm, SigningKeys},
+};
+
+/// An [`InboundGroupSession`] that doesn't keep its Megolm ratchet in memory.
+///
+/// Only the metadata of the session is kept resident, the ratchet stays
+/// pickled in the store and is loaded every time an event is decrypted, then
+/// dropped again. This trades decryption latency for memory, which is useful on
+/// memory-constrained devices that need to hold on to many sessions, e.g. to
+/// list them.
+///
+/// The metadata is a snapshot taken when the session was loaded. If a better
+/// copy of the session is stored afterwards, e.g. one with a lower first known
+/// index, [`LazyInboundGroupSession::decrypt()`] uses the new ratchet while the
+/// accessors keep returning the old metadata. Load the session from the store
+/// again to refresh the metadata.
+///
+/// **Note**: This only saves memory if the store doesn't keep the sessions in
+/// memory itself, which the `MemoryStore` does.
+#[derive(Clone)]
+pub struct LazyInboundGroupSession {
+    store: Arc<DynCryptoStore>,
+    session_id: Arc<str>,
+    room_id: OwnedRoomId,
+    sender_key: Curve25519PublicKey,
+    signing_keys: Arc<SigningKeys<DeviceKeyAlgorithm>>,
+    first_known_index: u32,
+    algorithm: Arc<EventEncryptionAlgorithm>,
+    imported: bool,
+    creation_time: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl LazyInboundGroupSession {
+    /// Create a lazy session from the given pickle which loads the ratchet
+    /// from the given store.
+    ///
+    /// The metadata is taken from [`PickledInboundGroupSession::metadata()`].
+    /// Only the session ID and the first known index are derived from the
+    /// ratchet, which is dropped again right away.
+    ///
+    /// The session needs to be saved in the store, otherwise decryption fails
+    /// with a [`MegolmError::MissingRoomKey`] error.
+    pub(crate) fn from_pickle(
+        pickle: PickledInboundGroupSession,
+        store: Arc<DynCryptoStore>,
+    ) -> Self {
+        let metadata = pickle.metadata();
+        let ratchet: InnerSession = pickle.pickle.into();
+
+        Self {
+            store,
+            session_id: ratchet.session_id().into(),
+            room_id: metadata.room_id,
+            sender_key: metadata.sender_key,
+            signing_keys: pickle.signing_key.into(),
+            first_known_index: ratchet.first_known_index(),
+            algorithm: metadata.algorithm.into(),
+            imported: metadata.imported,
+            creation_time: pickle.creation_time,
+        }
+    }
+
+    /// The room where this session is used in.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+
+    /// Returns the unique identifier for this session.
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// The Curve25519 key of the device that created this session.
+    pub fn sender_key(&self) -> Curve25519PublicKey {
+        self.sender_key
+    }
+
+    /// Get the map of signing keys this session was received from.
+    pub fn signing_keys(&self) -> &SigningKeys<DeviceKeyAlgorithm> {
+        &self.signing_keys
+    }
+
+    /// Get the first message index we know how to decrypt.
+    pub fn first_known_index(&self) -> u32 {
+        self.first_known_index
+    }
+
+    /// The algorithm that this inbound group session is using to decrypt
+    /// events.
+    pub fn algorithm(&self) -> &EventEncryptionAlgorithm {
+        &self.algorithm
+    }
+
+    /// Has the session been imported from a file or server-side backup? As
+    /// opposed to being directly received as an `m.room_key` event.
+    pub fn has_been_imported(&self) -> bool {
+        self.imported
+    }
+
+    /// Get the time at which we received this session from its creator.
+    ///
+    /// Returns `None` if the creation time is unknown, i.e. if the session was
+    /// imported or forwarded to us.
+    pub fn creation_time(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.creation_time
+    }
+
+    /// Load the full session, including its ratchet, from the store.
+    ///
+    /// Returns `None` if the session was removed from the store in the
+    /// meantime.
+    pub async fn load(&self) -> StoreResult<Option<InboundGroupSession>> {
+        self.store.get_inbound_group_session(&self.room_id, &self.session_id).await
+    }
+
+    /// Decrypt an event from a room timeline.
+    ///
+    /// The ratchet is loaded from the store for the duration of the decryption
+    /// only, see [`InboundGroupSession::decrypt()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that should be decrypted.
+    pub async fn decrypt(
+        &self,
+        event: &EncryptedEvent,
+    ) -> MegolmResult<(Raw<AnyTimelineEvent>, u32)> {
+        let session = self.load().await?.ok_or(MegolmError::MissingRoomKey(None))?;
+        session.decrypt(event).await
+    }
+}
+
+impl fmt::Debug for LazyInboundGroupSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyInboundGroupSession").field("session_id", &self.session_id()).finish()
+    }
+}
+
+/// Two `LazyInboundGroupSession`s are considered equal if they have the same
+/// session ID, like [`InboundGroupSession`]s.
+impl PartialEq for LazyInboundGroupSession {
+    fn eq(&self, other: &Self) -> bool {
+        self.session_id() == other.session_id()
+    }
+}
@@ -12,20 +12,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use ruma::{DeviceKeyAlgorithm, OwnedRoomId};
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use ruma::{CanonicalJsonValue, DeviceKeyAlgorithm, OwnedRoomId};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+mod decryption_cache;
 mod inbound;
+mod lazy;
 mod outbound;
 
-pub use inbound::{InboundGroupSession, PickledInboundGroupSession};
+pub(crate) use decryption_cache::DecryptionCache;
+pub use inbound::{
+    decryptable_gaps, DecryptDiagnosis, DecryptionMetadata, DecryptionWarning, InboundGroupSession,
+    KeySourceKind, PickledInboundGroupSession, SessionMetadata, SessionTrustSummary,
+};
+pub use lazy::LazyInboundGroupSession;
 pub(crate) use outbound::ShareState;
 pub use outbound::{
     EncryptionSettings, GroupSession, OutboundGroupSession, PickledOutboundGroupSession, ShareInfo,
 };
 use thiserror::Error;
 pub use vodozemac::megolm::{ExportedSessionKey, SessionKey};
-use vodozemac::{megolm::SessionKeyDecodeError, Curve25519PublicKey};
+use vodozemac::{
+    megolm::{SessionKeyDecodeError, SessionOrdering},
+    Curve25519PublicKey, Ed25519PublicKey,
+};
+use zeroize::Zeroize;
 
 #[cfg(feature = "experimental-algorithms")]
 use crate::types::events::forwarded_room_key::ForwardedMegolmV2AesSha2Content;
@@ -47,6 +61,37 @@ pub enum SessionCreationError {
     Decode(#[from] SessionKeyDecodeError),
 }
 
+/// An error type for the validation of an [`ExportedRoomKey`].
+#[derive(Debug, Error)]
+pub enum ExportValidationError {
+    /// The room key uses an algorithm that is not supported.
+    #[error("The room key uses an unsupported algorithm: {0}")]
+    Algorithm(EventEncryptionAlgorithm),
+    /// The session ID of the room key doesn't match its session key.
+    #[error("The session ID {claimed} doesn't match the session key, which has the ID {actual}")]
+    SessionIdMismatch {
+        /// The session ID found in the room key export.
+        claimed: String,
+        /// The session ID of the session key.
+        actual: String,
+    },
+    /// The claimed Ed25519 sender key isn't a valid Ed25519 key.
+    #[error("The claimed Ed25519 sender key of the room key isn't a valid Ed25519 key")]
+    InvalidEd25519Key,
+}
+
+/// An error type for the comparison of two [`ExportedRoomKey`]s, see
+/// [`ExportedRoomKey::better_of()`].
+#[derive(Debug, Error)]
+pub enum ExportComparisonError {
+    /// One of the room keys isn't valid.
+    #[error(transparent)]
+    Invalid(#[from] ExportValidationError),
+    /// The room keys don't belong to the same session.
+    #[error("The room keys belong to different sessions")]
+    DifferentSessions,
+}
+
 /// An error type for the export of inbound group sessions.
 ///
 /// Exported inbound group sessions will be either uploaded as backups, sent as
@@ -59,6 +104,27 @@ pub enum SessionExportError {
     /// The session export is missing a claimed Ed25519 sender key.
     #[error("The provided room key export is missing a claimed Ed25519 sender key")]
     MissingEd25519Key,
+    /// The start of the requested range of message indices comes after its
+    /// end.
+    #[error("The message index range {from}..={to} is empty")]
+    InvalidRange {
+        /// The first message index of the range.
+        from: u32,
+        /// The last message index of the range.
+        to: u32,
+    },
+    /// The session can't decrypt any message of the requested range of
+    /// message indices.
+    #[error(
+        "The session can only decrypt messages starting at index {first_known_index}, \
+         the requested range ends at {to}"
+    )]
+    UnknownRange {
+        /// The first message index the session can decrypt.
+        first_known_index: u32,
+        /// The last message index of the range.
+        to: u32,
+    },
 }
 
 /// An exported version of an `InboundGroupSession`
@@ -121,6 +187,245 @@ pub struct BackedUpRoomKey {
     pub forwarding_curve25519_key_chain: Vec<Curve25519PublicKey>,
 }
 
+impl ExportedRoomKey {
+    /// Check that this room key can be imported, without creating an
+    /// [`InboundGroupSession`] for it.
+    ///
+    /// This checks that the algorithm is supported, that the session ID
+    /// matches the session key and that the claimed Ed25519 sender key, if
+    /// any, is valid. The room ID and the session key are already validated
+    /// when the room key is deserialized.
+    ///
+    /// This allows to report every invalid room key of a bulk import before
+    /// importing any of them.
+    pub fn validate(&self) -> Result<(), ExportValidationError> {
+        OutboundGroupSession::session_config(&self.algorithm)
+            .map_err(|_| ExportValidationError::Algorithm(self.algorithm.to_owned()))?;
+
+        let actual = self.session_key_id();
+        if actual != self.session_id {
+            return Err(ExportValidationError::SessionIdMismatch {
+                claimed: self.session_id.to_owned(),
+                actual,
+            });
+        }
+
+        match self.sender_claimed_keys.get(&DeviceKeyAlgorithm::Ed25519) {
+            None | Some(SigningKey::Ed25519(_)) => Ok(()),
+            Some(SigningKey::Unknown(_)) => Err(ExportValidationError::InvalidEd25519Key),
+        }
+    }
+
+    /// Get the ID of the session the session key of this room key belongs to.
+    ///
+    /// The session ID is the public Ed25519 key of the session, which the
+    /// session key ends with, so the ratchet doesn't need to be imported.
+    fn session_key_id(&self) -> String {
+        let mut bytes = self.session_key.to_bytes();
+        let session_id = vodozemac::base64_encode(&bytes[bytes.len() - Ed25519PublicKey::LENGTH..]);
+        bytes.zeroize();
+
+        session_id
+    }
+
+    /// Pick the room key that can decrypt the most messages out of two
+    /// exports of the same session.
+    ///
+    /// This mirrors [`InboundGroupSession::compare()`] for room keys that
+    /// haven't been imported yet, e.g. to deduplicate the room keys of a file
+    /// before importing them. If both room keys start at the same message
+    /// index, `a` is returned.
+    ///
+    /// Returns an error if one of the room keys isn't valid, see
+    /// [`ExportedRoomKey::validate()`], or if they don't belong to the same
+    /// session, i.e. their algorithms, rooms, sender keys or session IDs
+    /// differ, or their session keys aren't connected.
+    pub fn better_of<'a>(
+        a: &'a ExportedRoomKey,
+        b: &'a ExportedRoomKey,
+    ) -> Result<&'a ExportedRoomKey, ExportComparisonError> {
+        a.validate()?;
+        b.validate()?;
+
+        if a.algorithm != b.algorithm
+            || a.room_id != b.room_id
+            || a.sender_key != b.sender_key
+            || a.session_id != b.session_id
+        {
+            return Err(ExportComparisonError::DifferentSessions);
+        }
+
+        let config = OutboundGroupSession::session_config(&a.algorithm)
+            .map_err(|_| ExportValidationError::Algorithm(a.algorithm.to_owned()))?;
+        let mut first = vodozemac::megolm::InboundGroupSession::import(&a.session_key, config);
+        let mut second = vodozemac::megolm::InboundGroupSession::import(&b.session_key, config);
+
+        match first.compare(&mut second) {
+            SessionOrdering::Equal | SessionOrdering::Better => Ok(a),
+            SessionOrdering::Worse => Ok(b),
+            SessionOrdering::Unconnected => Err(ExportComparisonError::DifferentSessions),
+        }
+    }
+
+    /// Compute the SHA-256 hash of the canonical JSON serialization of this
+    /// room key.
+    ///
+    /// Unlike [`InboundGroupSession::key_fingerprint()`], the hash covers the
+    /// session key, so it changes if any part of the export does. This can be
+    /// used to build a manifest of a key export file, which allows detecting
+    /// corrupted room keys when the file is imported.
+    ///
+    /// **Note**: This only provides integrity, not authenticity. Anyone who can
+    /// modify the export can compute the hash of the modified room keys as
+    /// well, so the manifest has to be protected by other means.
+    pub fn content_hash(&self) -> [u8; 32] {
+        let canonical: CanonicalJsonValue = serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.try_into().ok())
+            .expect("An exported room key can always be serialized as canonical JSON");
+
+        Sha256::digest(canonical.to_string().as_bytes()).into()
+    }
+}
+
+/// A summary of a room key that is part of only one of the exports compared
+/// by [`diff_room_key_exports()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoomKeySummary {
+    /// The room where the session is used.
+    pub room_id: OwnedRoomId,
+    /// The ID of the session.
+    pub session_id: String,
+    /// The first message index the room key can decrypt.
+    pub first_known_index: u32,
+}
+
+/// A room key that is part of both exports compared by
+/// [`diff_room_key_exports()`], but that starts at a different message index
+/// in each of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoomKeyIndexDifference {
+    /// The room where the session is used.
+    pub room_id: OwnedRoomId,
+    /// The ID of the session.
+    pub session_id: String,
+    /// The first message index the room key of the first export can decrypt.
+    pub first_known_index_a: u32,
+    /// The first message index the room key of the second export can decrypt.
+    pub first_known_index_b: u32,
+}
+
+/// The difference between two room key exports, see
+/// [`diff_room_key_exports()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportDiff {
+    /// The room keys that are only part of the first export.
+    pub only_in_a: Vec<RoomKeySummary>,
+    /// The room keys that are only part of the second export.
+    pub only_in_b: Vec<RoomKeySummary>,
+    /// The room keys that are part of both exports, but start at a different
+    /// message index.
+    pub different_indices: Vec<RoomKeyIndexDifference>,
+}
+
+impl ExportDiff {
+    /// Are the two exports equivalent, i.e. can they decrypt the same
+    /// messages?
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.different_indices.is_empty()
+    }
+}
+
+/// Group the valid room keys of an export by room and session ID, keeping the
+/// one that can decrypt the most messages if a session is exported multiple
+/// times.
+fn group_room_keys(
+    room_keys: &[ExportedRoomKey],
+) -> BTreeMap<(OwnedRoomId, String), vodozemac::megolm::InboundGroupSession> {
+    let mut sessions = BTreeMap::new();
+
+    for room_key in room_keys {
+        if room_key.validate().is_err() {
+            continue;
+        }
+
+        let Ok(config) = OutboundGroupSession::session_config(&room_key.algorithm) else {
+            continue;
+        };
+        let mut session =
+            vodozemac::megolm::InboundGroupSession::import(&room_key.session_key, config);
+
+        match sessions.entry((room_key.room_id.to_owned(), room_key.session_id.to_owned())) {
+            Entry::Vacant(entry) => {
+                entry.insert(session);
+            }
+            Entry::Occupied(mut entry) => {
+                if session.compare(entry.get_mut()) == SessionOrdering::Better {
+                    entry.insert(session);
+                }
+            }
+        }
+    }
+
+    sessions
+}
+
+/// Compute the difference between two room key exports, e.g. the exports of
+/// two devices that should be merged.
+///
+/// The room keys are grouped by room and session ID. Two room keys of the same
+/// session are considered to be the same room key if they start at the same
+/// message index, no matter how they were received, i.e. their forwarding
+/// chains are ignored. If an export contains the same session multiple times,
+/// the room key that can decrypt the most messages is used.
+///
+/// Room keys that aren't valid, see [`ExportedRoomKey::validate()`], are
+/// ignored. Two room keys that claim to belong to the same session but whose
+/// session keys aren't connected are reported as being only part of their
+/// respective export.
+pub fn diff_room_key_exports(a: &[ExportedRoomKey], b: &[ExportedRoomKey]) -> ExportDiff {
+    let a = group_room_keys(a);
+    let mut b = group_room_keys(b);
+
+    let mut diff = ExportDiff::default();
+
+    let summary =
+        |(room_id, session_id): &(OwnedRoomId, String),
+         session: &vodozemac::megolm::InboundGroupSession| RoomKeySummary {
+            room_id: room_id.to_owned(),
+            session_id: session_id.to_owned(),
+            first_known_index: session.first_known_index(),
+        };
+
+    for (key, mut session) in a {
+        let Some(mut other) = b.remove(&key) else {
+            diff.only_in_a.push(summary(&key, &session));
+            continue;
+        };
+
+        match session.compare(&mut other) {
+            SessionOrdering::Equal => {}
+            SessionOrdering::Better | SessionOrdering::Worse => {
+                diff.different_indices.push(RoomKeyIndexDifference {
+                    first_known_index_a: session.first_known_index(),
+                    first_known_index_b: other.first_known_index(),
+                    room_id: key.0,
+                    session_id: key.1,
+                });
+            }
+            SessionOrdering::Unconnected => {
+                diff.only_in_a.push(summary(&key, &session));
+                diff.only_in_b.push(summary(&key, &other));
+            }
+        }
+    }
+
+    diff.only_in_b.extend(b.iter().map(|(key, session)| summary(key, session)));
+    diff.only_in_b.sort_by(|x, y| (&x.room_id, &x.session_id).cmp(&(&y.room_id, &y.session_id)));
+
+    diff
+}
+
 impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
     type Error = SessionExportError;
 
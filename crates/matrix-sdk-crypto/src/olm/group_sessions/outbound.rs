@@ -41,7 +41,7 @@ pub use vodozemac::{
     PickleError,
 };
 
-use super::SessionCreationError;
+use super::{ExportedRoomKey, InboundGroupSession, SessionCreationError};
 #[cfg(feature = "experimental-algorithms")]
 use crate::types::events::room::encrypted::MegolmV2AesSha2Content;
 use crate::{
@@ -454,6 +454,51 @@ impl OutboundGroupSession {
         session.session_key()
     }
 
+    /// Create the [`InboundGroupSession`] that matches this session.
+    ///
+    /// The inbound session is created from the current session key, so it
+    /// will be able to decrypt messages starting at the current
+    /// [`message_index()`][Self::message_index], i.e. its
+    /// [`first_known_index()`][InboundGroupSession::first_known_index] is the
+    /// message index of this session. It uses the algorithm, room and history
+    /// visibility of this session, and it isn't marked as imported.
+    pub async fn create_matching_inbound(
+        &self,
+    ) -> Result<InboundGroupSession, SessionCreationError> {
+        // Hold the lock while creating the inbound session, so no message can
+        // be encrypted between reading the session key and the message index.
+        let session = self.inner.read().await;
+
+        let inbound = InboundGroupSession::new(
+            self.sender_key(),
+            self.account_identity_keys.ed25519,
+            self.room_id(),
+            &session.session_key(),
+            self.settings.algorithm.to_owned(),
+            Some(self.settings.history_visibility.clone()),
+        )?;
+
+        debug_assert_eq!(
+            inbound.first_known_index(),
+            session.message_index(),
+            "The matching inbound session should start at the current message index"
+        );
+
+        Ok(inbound)
+    }
+
+    /// Export this session at its current [`message_index()`], the index of
+    /// the next message that will be encrypted.
+    ///
+    /// The recipient of the exported key can only decrypt messages sent from
+    /// now on, none of the messages that were sent before. This is useful to
+    /// share a session with a new room member who shouldn't see the history.
+    ///
+    /// [`message_index()`]: Self::message_index
+    pub async fn export_at_latest_index(&self) -> Result<ExportedRoomKey, SessionCreationError> {
+        Ok(self.create_matching_inbound().await?.export().await)
+    }
+
     /// Gets the Sender Key
     pub fn sender_key(&self) -> Curve25519PublicKey {
         self.account_identity_keys.as_ref().curve25519.to_owned()
@@ -749,9 +794,10 @@ mod tests {
         },
         room_id, uint, user_id, EventEncryptionAlgorithm,
     };
+    use vodozemac::megolm::SessionOrdering;
 
     use super::{EncryptionSettings, ROTATION_MESSAGES, ROTATION_PERIOD};
-    use crate::{MegolmError, ReadOnlyAccount};
+    use crate::{olm::InboundGroupSession, MegolmError, ReadOnlyAccount};
 
     #[test]
     fn encryption_settings_conversion() {
@@ -771,6 +817,51 @@ mod tests {
         assert_eq!(settings.rotation_period_msgs, 500);
     }
 
+    #[async_test]
+    async fn export_at_latest_index() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:example.org"), device_id!("DEVICEID"));
+        let room_id = room_id!("!test_room:example.org");
+        let (outbound, _) = account.create_group_session_pair_with_defaults(room_id).await;
+
+        for _ in 0..3 {
+            outbound.encrypt_helper("Hello".to_owned()).await;
+        }
+        assert_eq!(outbound.message_index().await, 3);
+
+        let exported = outbound.export_at_latest_index().await.unwrap();
+        let imported = InboundGroupSession::from_export(&exported).unwrap();
+
+        assert_eq!(imported.first_known_index(), 3);
+        assert_eq!(imported.session_id(), outbound.session_id());
+    }
+
+    #[async_test]
+    async fn create_matching_inbound() {
+        let account =
+            ReadOnlyAccount::with_device_id(user_id!("@alice:example.org"), device_id!("DEVICEID"));
+        let room_id = room_id!("!test_room:example.org");
+        let (outbound, inbound) = account.create_group_session_pair_with_defaults(room_id).await;
+
+        let matching = outbound.create_matching_inbound().await.unwrap();
+
+        assert_eq!(matching.compare(&inbound).await, SessionOrdering::Equal);
+        assert_eq!(matching.session_id(), outbound.session_id());
+        assert_eq!(matching.room_id(), room_id);
+        assert_eq!(matching.algorithm(), &outbound.settings().algorithm);
+        assert_eq!(matching.sender_key(), outbound.sender_key());
+        assert!(!matching.has_been_imported());
+        assert_eq!(matching.first_known_index(), outbound.message_index().await);
+
+        for _ in 0..2 {
+            outbound.encrypt_helper("Hello".to_owned()).await;
+        }
+
+        let matching = outbound.create_matching_inbound().await.unwrap();
+        assert_eq!(matching.first_known_index(), 2);
+        assert_eq!(matching.first_known_index(), outbound.message_index().await);
+    }
+
     #[async_test]
     #[cfg(any(target_os = "linux", target_os = "macos", target_arch = "wasm32"))]
     async fn expiration() -> Result<(), MegolmError> {
@@ -25,12 +25,15 @@ mod utility;
 
 pub(crate) use account::{Account, OlmDecryptionInfo, SessionType};
 pub use account::{OlmMessageHash, PickledAccount, ReadOnlyAccount};
-pub(crate) use group_sessions::ShareState;
 pub use group_sessions::{
-    BackedUpRoomKey, EncryptionSettings, ExportedRoomKey, InboundGroupSession,
-    OutboundGroupSession, PickledInboundGroupSession, PickledOutboundGroupSession,
-    SessionCreationError, SessionExportError, SessionKey, ShareInfo,
+    decryptable_gaps, diff_room_key_exports, BackedUpRoomKey, DecryptDiagnosis, DecryptionMetadata,
+    DecryptionWarning, EncryptionSettings, ExportComparisonError, ExportDiff,
+    ExportValidationError, ExportedRoomKey, InboundGroupSession, KeySourceKind,
+    LazyInboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
+    PickledOutboundGroupSession, RoomKeyIndexDifference, RoomKeySummary, SessionCreationError,
+    SessionExportError, SessionKey, SessionMetadata, SessionTrustSummary, ShareInfo,
 };
+pub(crate) use group_sessions::{DecryptionCache, ShareState};
 pub use session::{PickledSession, Session};
 pub use signing::{CrossSigningStatus, PickledCrossSigningIdentity, PrivateCrossSigningIdentity};
 pub(crate) use utility::{SignedJsonObject, VerifyJson};
@@ -56,11 +59,12 @@ pub(crate) mod tests {
     };
 
     use crate::{
-        olm::{ExportedRoomKey, InboundGroupSession, ReadOnlyAccount, Session},
+        olm::{DecryptionMetadata, ExportedRoomKey, InboundGroupSession, ReadOnlyAccount, Session},
         types::events::{
             forwarded_room_key::ForwardedRoomKeyContent, room::encrypted::EncryptedEvent,
         },
         utilities::json_convert,
+        EventError, MegolmError,
     };
 
     fn alice_id() -> &'static UserId {
@@ -196,10 +200,12 @@ pub(crate) mod tests {
         let plaintext = "This is a secret to everybody".to_owned();
         let ciphertext = outbound.encrypt_helper(plaintext.clone()).await;
 
+        assert_eq!(0, inbound.decrypt_count());
         assert_eq!(
             plaintext.as_bytes(),
             inbound.decrypt_helper(&ciphertext).await.unwrap().plaintext
         );
+        assert_eq!(1, inbound.decrypt_count());
     }
 
     #[async_test]
@@ -255,12 +261,64 @@ pub(crate) mod tests {
             MessageLikeEvent::Original(e),
         )) = decrypted.deserialize().unwrap()
         {
-            assert_matches!(e.content.relates_to, Some(Relation::Replacement(_)));
+            assert_matches!(e.content.relates_to, Some(Relation::Replacement(r)) => {
+                assert_eq!(r.new_content.msgtype.body(), "Hello edit");
+            });
         } else {
             panic!("Invalid event type")
         }
     }
 
+    #[async_test]
+    async fn edit_decryption_with_unencrypted_relation() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let event_id = event_id!("$1234adfad:asdf");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        // Some clients only put the relation of an edit in the unencrypted part
+        // of the event, the new content stays in the encrypted payload.
+        let content = json!({
+            "msgtype": "m.text",
+            "body": "* Hello edit",
+            "m.new_content": {
+                "msgtype": "m.text",
+                "body": "Hello edit",
+            },
+        });
+        let encrypted = outbound.encrypt(content, "m.room.message").await;
+        let mut encrypted: Value = json_convert(&encrypted).unwrap();
+        encrypted.as_object_mut().unwrap().insert(
+            "m.relates_to".to_owned(),
+            json!({ "rel_type": "m.replace", "event_id": event_id }),
+        );
+
+        let event = json!({
+            "sender": alice.user_id(),
+            "event_id": "$edit:asdf",
+            "origin_server_ts": 0u64,
+            "room_id": room_id,
+            "type": "m.room.encrypted",
+            "content": encrypted,
+        });
+        let event: EncryptedEvent = json_convert(&event).unwrap();
+
+        let (decrypted, _) = inbound.decrypt(&event).await.unwrap();
+
+        assert_matches!(
+            decrypted.deserialize().unwrap(),
+            AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+                MessageLikeEvent::Original(e)
+            )) => {
+                assert_matches!(e.content.relates_to, Some(Relation::Replacement(r)) => {
+                    assert_eq!(r.event_id, event_id);
+                    assert_eq!(r.new_content.msgtype.body(), "Hello edit");
+                });
+            }
+        );
+    }
+
     #[async_test]
     async fn relates_to_decryption() {
         let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
@@ -333,6 +391,113 @@ pub(crate) mod tests {
         assert!(relation.is_some(), "The decrypted event should contain a relation");
     }
 
+    #[async_test]
+    async fn decryption_metadata() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let event_id = event_id!("$1234adfad:asdf");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        // Advance the ratchet so the message index isn't trivially zero.
+        outbound.encrypt(json!({}), "m.dummy").await;
+        let encrypted = outbound.encrypt(json!({}), "m.dummy").await;
+
+        let event = json!({
+            "sender": alice.user_id(),
+            "event_id": event_id,
+            "origin_server_ts": 0u64,
+            "room_id": room_id,
+            "type": "m.room.encrypted",
+            "content": encrypted,
+        });
+        let event: EncryptedEvent = json_convert(&event).unwrap();
+
+        let (decrypted, metadata) = inbound.decrypt_with_metadata(&event).await.unwrap();
+        let (expected, _) = inbound.decrypt(&event).await.unwrap();
+
+        assert_eq!(decrypted.json().get(), expected.json().get());
+        assert_eq!(
+            metadata,
+            DecryptionMetadata {
+                session_id: inbound.session_id().to_owned(),
+                sender_key: Some(alice.identity_keys().curve25519),
+                device_id: Some(alice.device_id().to_owned()),
+                message_index: 1,
+            }
+        );
+    }
+
+    #[async_test]
+    async fn unchecked_decryption() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let other_room_id = room_id!("!other:localhost");
+        let event_id = event_id!("$1234adfad:asdf");
+
+        let (outbound, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+
+        let encrypted = outbound.encrypt(json!({}), "m.dummy").await;
+        let event = json!({
+            "sender": alice.user_id(),
+            "event_id": event_id,
+            "origin_server_ts": 0u64,
+            "room_id": room_id,
+            "type": "m.room.encrypted",
+            "content": encrypted,
+        });
+        let event: EncryptedEvent = json_convert(&event).unwrap();
+
+        // Pretend that the session belongs to another room.
+        let mut other = InboundGroupSession::from_pickle(inbound.pickle().await).unwrap();
+        other.room_id = other_room_id.to_owned();
+
+        assert_matches!(
+            other.decrypt(&event).await,
+            Err(MegolmError::EventError(EventError::MismatchedRoom(..)))
+        );
+
+        let (_, index, claimed_room_id) = other
+            .decrypt_unchecked(&event)
+            .await
+            .expect("The unchecked decryption should ignore the room mismatch");
+
+        assert_eq!(index, 0);
+        assert_eq!(claimed_room_id.as_deref(), Some(room_id));
+    }
+
+    #[async_test]
+    async fn forwarding_chain_export() {
+        let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let forwarder_key =
+            Curve25519PublicKey::from_base64("Nn0L2hkcCMFKqynTjyGsJbth7QrVmX3lbrksMkrGOAw")
+                .unwrap();
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert!(inbound.forwarding_chain().is_empty());
+
+        let mut export = inbound.export().await;
+        export.forwarding_curve25519_key_chain = vec![forwarder_key];
+
+        let forwarded: ForwardedRoomKeyContent = export.try_into().unwrap();
+        let ForwardedRoomKeyContent::MegolmV1AesSha2(content) = &forwarded else {
+            panic!("The forwarded room key should use the megolm v1 algorithm")
+        };
+        let forwarded = InboundGroupSession::from(content.as_ref());
+
+        assert_eq!(forwarded.forwarding_chain(), [forwarder_key]);
+
+        let export = forwarded.export().await;
+        assert_eq!(export.forwarding_curve25519_key_chain, vec![forwarder_key]);
+
+        let imported = InboundGroupSession::from_pickle(
+            InboundGroupSession::from_export(&export).unwrap().pickle().await,
+        )
+        .unwrap();
+        assert_eq!(imported.forwarding_chain(), [forwarder_key]);
+    }
+
     #[async_test]
     async fn group_session_export() {
         let alice = ReadOnlyAccount::with_device_id(alice_id(), alice_device_id());
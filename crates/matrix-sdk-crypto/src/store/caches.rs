@@ -105,6 +105,11 @@ impl GroupSessionStore {
             .collect()
     }
 
+    /// Get all the group sessions the store knows about for the given room.
+    pub fn get_for_room(&self, room_id: &RoomId) -> Vec<InboundGroupSession> {
+        self.entries.get(room_id).map(|keys| keys.values().cloned().collect()).unwrap_or_default()
+    }
+
     /// Get the number of `InboundGroupSession`s we have.
     pub fn count(&self) -> usize {
         self.entries.iter().map(|d| d.value().len()).sum()
@@ -119,6 +124,13 @@ impl GroupSessionStore {
     pub fn get(&self, room_id: &RoomId, session_id: &str) -> Option<InboundGroupSession> {
         self.entries.get(room_id)?.get(session_id).cloned()
     }
+
+    /// Remove an inbound group session from the store.
+    ///
+    /// Returns the removed session, if the store contained it.
+    pub fn remove(&self, room_id: &RoomId, session_id: &str) -> Option<InboundGroupSession> {
+        self.entries.get_mut(room_id)?.remove(session_id)
+    }
 }
 
 /// In-memory store holding the devices of users.
@@ -19,7 +19,7 @@ macro_rules! cryptostore_integration_tests {
             use serde_json::value::to_raw_value;
             use $crate::{
                 olm::{
-                    Curve25519PublicKey, InboundGroupSession, OlmMessageHash,
+                    Curve25519PublicKey, InboundGroupSession, KeySourceKind, OlmMessageHash,
                     PrivateCrossSigningIdentity, ReadOnlyAccount, Session,
                 },
                 store::{
@@ -320,6 +320,157 @@ macro_rules! cryptostore_integration_tests {
                 assert_eq!(to_back_up, vec![session]);
             }
 
+            #[async_test]
+            async fn inbound_group_session_rooms_for_backup() {
+                let (account, store) =
+                    get_loaded_store("inbound_group_session_rooms_for_backup").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let other_room_id = &room_id!("!test2:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) =
+                    account.create_group_session_pair_with_defaults(other_room_id).await;
+                other.mark_as_backed_up();
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session.clone(), other],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                let rooms = store.inbound_group_session_rooms_for_backup().await.unwrap();
+                assert_eq!(rooms, vec![room_id.to_owned()]);
+
+                session.mark_as_backed_up();
+                let changes =
+                    Changes { inbound_group_sessions: vec![session], ..Default::default() };
+                store.save_changes(changes).await.expect("Can't save group session");
+
+                assert!(store.inbound_group_session_rooms_for_backup().await.unwrap().is_empty());
+            }
+
+            #[async_test]
+            async fn inbound_group_session_metadata() {
+                let (account, store) = get_loaded_store("inbound_group_session_metadata").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) = account.create_group_session_pair_with_defaults(room_id).await;
+                let imported = InboundGroupSession::from_export(&other.export().await).unwrap();
+                imported.mark_as_backed_up();
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session, imported],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                let mut metadata = store.get_inbound_group_session_metadata().await.unwrap();
+                metadata.sort_by_key(|m| m.backed_up);
+
+                assert_eq!(metadata.len(), 2);
+                assert!(metadata.iter().all(|m| m.room_id == *room_id));
+                assert!(!metadata[0].backed_up);
+                assert_eq!(metadata[0].key_source, KeySourceKind::Direct);
+                assert!(metadata[1].backed_up);
+                assert_eq!(metadata[1].key_source, KeySourceKind::Imported);
+            }
+
+            #[async_test]
+            async fn delete_inbound_group_session() {
+                let (account, store) = get_loaded_store("delete_inbound_group_session").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) = account.create_group_session_pair_with_defaults(room_id).await;
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session.clone(), other.clone()],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                let changes = Changes {
+                    deleted_inbound_group_sessions: vec![session.clone()],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't delete group session");
+
+                assert!(store
+                    .get_inbound_group_session(room_id, session.session_id())
+                    .await
+                    .unwrap()
+                    .is_none());
+                assert_eq!(store.get_inbound_group_sessions().await.unwrap(), vec![other]);
+                assert_eq!(store.inbound_group_session_counts().await.unwrap().total, 1);
+            }
+
+            #[async_test]
+            async fn load_inbound_group_sessions_for_room() {
+                let (account, store) =
+                    get_loaded_store("load_inbound_group_sessions_for_room").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let other_room_id = &room_id!("!test2:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) =
+                    account.create_group_session_pair_with_defaults(other_room_id).await;
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session.clone(), other.clone()],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                assert_eq!(
+                    store.get_inbound_group_sessions_for_room(room_id).await.unwrap(),
+                    vec![session]
+                );
+                assert_eq!(
+                    store.get_inbound_group_sessions_for_room(other_room_id).await.unwrap(),
+                    vec![other]
+                );
+                assert!(store
+                    .get_inbound_group_sessions_for_room(room_id!("!unknown:localhost"))
+                    .await
+                    .unwrap()
+                    .is_empty());
+
+                let pickles =
+                    store.get_pickled_inbound_group_sessions_for_room(room_id).await.unwrap();
+                assert_eq!(pickles.len(), 1);
+                assert_eq!(pickles[0].room_id.as_str(), room_id.as_str());
+            }
+
+            #[async_test]
+            async fn inbound_group_session_counts_for_room() {
+                let (account, store) =
+                    get_loaded_store("inbound_group_session_counts_for_room").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let other_room_id = &room_id!("!test2:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, backed_up) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) =
+                    account.create_group_session_pair_with_defaults(other_room_id).await;
+                backed_up.mark_as_backed_up();
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session, backed_up, other],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                let counts = store.inbound_group_session_counts_for_room(room_id).await.unwrap();
+                assert_eq!(counts.total, 2);
+                assert_eq!(counts.backed_up, 1);
+
+                let counts =
+                    store.inbound_group_session_counts_for_room(other_room_id).await.unwrap();
+                assert_eq!(counts.total, 1);
+                assert_eq!(counts.backed_up, 0);
+            }
+
             #[async_test]
             async fn load_inbound_group_session() {
                 let dir = "load_inbound_group_session";
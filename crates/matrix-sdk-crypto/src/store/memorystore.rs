@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::Infallible,
     sync::Arc,
     time::{Duration, Instant},
@@ -36,7 +36,10 @@ use super::{
 use crate::{
     gossiping::{GossipRequest, GossippedSecret, SecretInfo},
     identities::{ReadOnlyDevice, ReadOnlyUserIdentities},
-    olm::{OutboundGroupSession, PrivateCrossSigningIdentity},
+    olm::{
+        OutboundGroupSession, PickledInboundGroupSession, PrivateCrossSigningIdentity,
+        SessionMetadata,
+    },
     types::events::room_key_withheld::RoomKeyWithheldEvent,
     TrackedUser,
 };
@@ -146,6 +149,10 @@ impl CryptoStore for MemoryStore {
         self.save_sessions(changes.sessions).await;
         self.save_inbound_group_sessions(changes.inbound_group_sessions).await;
 
+        for session in changes.deleted_inbound_group_sessions {
+            self.inbound_group_sessions.remove(session.room_id(), session.session_id());
+        }
+
         self.save_devices(changes.devices.new).await;
         self.save_devices(changes.devices.changed).await;
         self.delete_devices(changes.devices.deleted).await;
@@ -223,6 +230,25 @@ impl CryptoStore for MemoryStore {
         Ok(self.inbound_group_sessions.get_all())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_for_room(room_id))
+    }
+
+    async fn get_pickled_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<PickledInboundGroupSession>> {
+        let sessions = self.inbound_group_sessions.get_for_room(room_id);
+        Ok(InboundGroupSession::pickle_many(&sessions).await)
+    }
+
+    async fn get_inbound_group_session_metadata(&self) -> Result<Vec<SessionMetadata>> {
+        Ok(self.inbound_group_sessions.get_all().iter().map(|s| s.metadata()).collect())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let backed_up =
             self.get_inbound_group_sessions().await?.into_iter().filter(|s| s.backed_up()).count();
@@ -230,6 +256,16 @@ impl CryptoStore for MemoryStore {
         Ok(RoomKeyCounts { total: self.inbound_group_sessions.count(), backed_up })
     }
 
+    async fn inbound_group_session_counts_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyCounts> {
+        let sessions = self.inbound_group_sessions.get_for_room(room_id);
+        let backed_up = sessions.iter().filter(|s| s.backed_up()).count();
+
+        Ok(RoomKeyCounts { total: sessions.len(), backed_up })
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         limit: usize,
@@ -243,6 +279,18 @@ impl CryptoStore for MemoryStore {
             .collect())
     }
 
+    async fn inbound_group_session_rooms_for_backup(&self) -> Result<Vec<OwnedRoomId>> {
+        let rooms: HashSet<OwnedRoomId> = self
+            .inbound_group_sessions
+            .get_all()
+            .into_iter()
+            .filter(|s| !s.backed_up())
+            .map(|s| s.room_id().to_owned())
+            .collect();
+
+        Ok(rooms.into_iter().collect())
+    }
+
     async fn reset_backup_state(&self) -> Result<()> {
         for session in self.get_inbound_group_sessions().await? {
             session.reset_backup_state();
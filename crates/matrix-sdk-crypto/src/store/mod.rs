@@ -52,12 +52,13 @@ use dashmap::DashSet;
 use futures_core::Stream;
 use futures_util::stream::StreamExt;
 use ruma::{
-    events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
+    events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
+    OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::{broadcast, Mutex};
-use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::wrappers::BroadcastStream;
 use tracing::{info, warn};
 use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey};
 use zeroize::Zeroize;
@@ -69,10 +70,14 @@ use crate::{
         Device, ReadOnlyDevice, ReadOnlyUserIdentities, UserDevices,
     },
     olm::{
-        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PrivateCrossSigningIdentity,
-        ReadOnlyAccount, Session,
+        ExportedRoomKey, InboundGroupSession, KeySourceKind, LazyInboundGroupSession,
+        OlmMessageHash, OutboundGroupSession, PrivateCrossSigningIdentity, ReadOnlyAccount,
+        Session,
+    },
+    types::{
+        events::room_key_withheld::{RoomKeyWithheldEvent, WithheldCode},
+        EventEncryptionAlgorithm,
     },
-    types::{events::room_key_withheld::RoomKeyWithheldEvent, EventEncryptionAlgorithm},
     utilities::encode,
     verification::VerificationMachine,
     CrossSigningStatus,
@@ -93,6 +98,7 @@ use caches::{SequenceNumber, UsersForKeyQuery};
 pub use error::{CryptoStoreError, Result};
 use matrix_sdk_common::timeout::timeout;
 pub use memorystore::MemoryStore;
+pub use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 pub use traits::{CryptoStore, DynCryptoStore, IntoCryptoStore};
 
 use self::locks::CryptoStoreLock;
@@ -130,10 +136,20 @@ struct StoreInner {
     tracked_user_loading_lock: Mutex<()>,
     tracked_users_loaded: AtomicBool,
 
+    /// Lock making sure that the compare and store steps of
+    /// [`Store::upsert_if_better`] and [`Store::save_changes_if_better`], and
+    /// the select and delete steps of [`Store::prune_sessions_created_before`]
+    /// can't interleave.
+    inbound_group_session_upsert_lock: Mutex<()>,
+
     /// The sender side of a broadcast stream that is notified whenever we get
     /// an update to an inbound group session.
     room_keys_received_sender: broadcast::Sender<Vec<RoomKeyInfo>>,
 
+    /// The sender side of a broadcast stream that is notified whenever the
+    /// backed up flag of inbound group sessions changes.
+    backup_state_sender: broadcast::Sender<Vec<BackupStateUpdate>>,
+
     /// The sender side of a broadcast channel which sends out secrets we
     /// received as a `m.secret.send` event.
     secrets_broadcaster: broadcast::Sender<GossippedSecret>,
@@ -151,6 +167,8 @@ pub struct Changes {
     pub sessions: Vec<Session>,
     pub message_hashes: Vec<OlmMessageHash>,
     pub inbound_group_sessions: Vec<InboundGroupSession>,
+    /// Inbound group sessions that should be removed from the store.
+    pub deleted_inbound_group_sessions: Vec<InboundGroupSession>,
     pub outbound_group_sessions: Vec<OutboundGroupSession>,
     pub key_requests: Vec<GossipRequest>,
     pub identities: IdentityChanges,
@@ -184,6 +202,7 @@ impl Changes {
             && self.sessions.is_empty()
             && self.message_hashes.is_empty()
             && self.inbound_group_sessions.is_empty()
+            && self.deleted_inbound_group_sessions.is_empty()
             && self.outbound_group_sessions.is_empty()
             && self.key_requests.is_empty()
             && self.identities.is_empty()
@@ -281,6 +300,42 @@ pub struct RoomKeyCounts {
     pub backed_up: usize,
 }
 
+/// Aggregate statistics about the room keys the store has, see
+/// [`Store::session_stats()`].
+#[derive(Debug, Clone, Default)]
+pub struct SessionStats {
+    /// The total number of room keys the store has.
+    pub total: usize,
+    /// The number of backed up room keys the store has.
+    pub backed_up: usize,
+    /// The number of room keys we received directly from their creator.
+    pub direct: usize,
+    /// The number of room keys that were forwarded to us by another device.
+    pub forwarded: usize,
+    /// The number of room keys that were imported from a file or a backup.
+    pub imported: usize,
+    /// The number of room keys, and backed up room keys, per room.
+    pub rooms: BTreeMap<OwnedRoomId, RoomKeyCounts>,
+}
+
+/// The outcome of a call to [`Store::upsert_if_better`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    /// We didn't have a session with this ID, the session was stored.
+    Inserted,
+    /// The session was better than the one we had, it replaced it.
+    Replaced,
+    /// The session we already had is at least as good, nothing was written.
+    Unchanged,
+}
+
+impl UpsertOutcome {
+    /// Was the session written to the store?
+    pub fn was_written(&self) -> bool {
+        !matches!(self, Self::Unchanged)
+    }
+}
+
 /// Stored versions of the backup keys.
 #[derive(Default, Clone, Debug)]
 pub struct BackupKeys {
@@ -363,6 +418,13 @@ impl Default for RoomSettings {
     }
 }
 
+/// A change of the backed up flag of an inbound group session, see
+/// [`Store::backup_state_stream()`].
+///
+/// Contains the room ID and the session ID of the session, and whether the
+/// session is now backed up.
+pub type BackupStateUpdate = (OwnedRoomId, String, bool);
+
 /// Information on a room key that has been received or imported.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct RoomKeyInfo {
@@ -402,6 +464,7 @@ impl Store {
         verification_machine: VerificationMachine,
     ) -> Self {
         let room_keys_received_sender = broadcast::Sender::new(10);
+        let backup_state_sender = broadcast::Sender::new(10);
         let secrets_broadcaster = broadcast::Sender::new(10);
 
         let inner = Arc::new(StoreInner {
@@ -414,7 +477,9 @@ impl Store {
             users_for_key_query_condvar: Condvar::new(),
             tracked_users_loaded: AtomicBool::new(false),
             tracked_user_loading_lock: Mutex::new(()),
+            inbound_group_session_upsert_lock: Mutex::new(()),
             room_keys_received_sender,
+            backup_state_sender,
             secrets_broadcaster,
         });
 
@@ -497,6 +562,344 @@ impl Store {
         })
     }
 
+    /// Store the given `InboundGroupSession` if it's better than the session
+    /// with the same ID we already have, or if we don't have such a session.
+    ///
+    /// The comparison and the write happen while holding a lock, so two
+    /// concurrent calls to this method, e.g. one importing a key from a backup
+    /// and one receiving a forwarded key, can't clobber a better session with
+    /// a worse one.
+    ///
+    /// The store holds at most one session per room ID and session ID, copies
+    /// of the same session received through backups or forwards replace each
+    /// other instead of accumulating. Writing them through this method makes
+    /// sure the copy that is kept is the one that can decrypt the most
+    /// messages.
+    pub async fn upsert_if_better(&self, session: InboundGroupSession) -> Result<UpsertOutcome> {
+        let changes = Changes { inbound_group_sessions: vec![session], ..Default::default() };
+        let outcomes = self.save_changes_if_better(changes).await?;
+
+        Ok(outcomes[0])
+    }
+
+    /// Save the given changes, keeping only the inbound group sessions that
+    /// are better than the copies we already have, see
+    /// [`Store::upsert_if_better()`].
+    ///
+    /// This is used by every code path that receives room keys from the
+    /// outside, i.e. `m.room_key` and `m.forwarded_room_key` events, key
+    /// exports and backups.
+    ///
+    /// Returns the outcome for each of the inbound group sessions of the
+    /// changes, in the same order.
+    pub(crate) async fn save_changes_if_better(
+        &self,
+        mut changes: Changes,
+    ) -> Result<Vec<UpsertOutcome>> {
+        let _guard = self.inner.inbound_group_session_upsert_lock.lock().await;
+
+        let sessions = std::mem::take(&mut changes.inbound_group_sessions);
+        let mut outcomes = Vec::with_capacity(sessions.len());
+        let mut backup_state_updates = Vec::new();
+
+        for session in sessions {
+            let old_session = self
+                .inner
+                .store
+                .get_inbound_group_session(session.room_id(), session.session_id())
+                .await?;
+
+            let was_backed_up = old_session.as_ref().is_some_and(|s| s.backed_up());
+
+            let outcome = match old_session {
+                None => UpsertOutcome::Inserted,
+                Some(old_session) => {
+                    if session.compare(&old_session).await == SessionOrdering::Better {
+                        UpsertOutcome::Replaced
+                    } else {
+                        UpsertOutcome::Unchanged
+                    }
+                }
+            };
+
+            if outcome.was_written() {
+                // Room keys restored from a backup are stored as backed up
+                // right away, and a better copy that wasn't backed up replaces
+                // a backed up one.
+                if session.backed_up() != was_backed_up {
+                    backup_state_updates.push((
+                        session.room_id().to_owned(),
+                        session.session_id().to_owned(),
+                        session.backed_up(),
+                    ));
+                }
+
+                changes.inbound_group_sessions.push(session);
+            }
+
+            outcomes.push(outcome);
+        }
+
+        self.save_changes(changes).await?;
+        self.send_backup_state_updates(backup_state_updates);
+
+        Ok(outcomes)
+    }
+
+    /// Get all the inbound group sessions that were created by the device
+    /// with the given Curve25519 key, across all rooms.
+    ///
+    /// This is useful to warn about messages that may have been compromised
+    /// if the device is found to be compromised.
+    ///
+    /// **Note**: The store doesn't index sessions by their sender key, so this
+    /// loads every inbound group session we have.
+    pub async fn sessions_from_sender(
+        &self,
+        sender_key: Curve25519PublicKey,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let sessions = self.inner.store.get_inbound_group_sessions().await?;
+
+        Ok(sessions.into_iter().filter(|s| s.sender_key() == sender_key).collect())
+    }
+
+    /// Get the inbound group sessions that haven't been backed up yet, and
+    /// that come strictly after the given cursor.
+    ///
+    /// The sessions are sorted by their
+    /// [`InboundGroupSession::backup_order_key()`], passing the key of the
+    /// last uploaded session as the cursor allows to resume a backup after an
+    /// interruption. If the cursor is `None`, all the sessions that need to be
+    /// backed up are returned.
+    ///
+    /// **Note**: The store doesn't index sessions by this key, so this loads
+    /// every inbound group session we have.
+    pub async fn sessions_needing_backup_after(
+        &self,
+        cursor: Option<&(OwnedRoomId, String)>,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let mut sessions: Vec<_> = self
+            .inner
+            .store
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .filter(|s| !s.backed_up())
+            .map(|s| (s.backup_order_key(), s))
+            .filter(|(key, _)| cursor.map_or(true, |cursor| key > cursor))
+            .collect();
+
+        sessions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(sessions.into_iter().map(|(_, s)| s).collect())
+    }
+
+    /// Get an inbound group session that keeps its ratchet in the store.
+    ///
+    /// The session is loaded once to collect its metadata, the returned
+    /// [`LazyInboundGroupSession`] only loads the ratchet again to decrypt an
+    /// event. Returns `None` if we don't have the session.
+    ///
+    /// The metadata of the returned session is a snapshot, see
+    /// [`LazyInboundGroupSession`].
+    pub async fn get_lazy_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<LazyInboundGroupSession>> {
+        let Some(session) = self.inner.store.get_inbound_group_session(room_id, session_id).await?
+        else {
+            return Ok(None);
+        };
+
+        let pickle = session.pickle().await;
+
+        Ok(Some(LazyInboundGroupSession::from_pickle(pickle, self.inner.store.clone())))
+    }
+
+    /// Get all the inbound group sessions of the given room, keeping their
+    /// ratchets in the store.
+    ///
+    /// This is the bulk variant of [`Store::get_lazy_inbound_group_session()`].
+    /// The sessions are built from the pickles the store returns, so the
+    /// ratchets of the room are never unpickled all at once.
+    pub async fn get_lazy_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<LazyInboundGroupSession>> {
+        Ok(self
+            .inner
+            .store
+            .get_pickled_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .map(|pickle| LazyInboundGroupSession::from_pickle(pickle, self.inner.store.clone()))
+            .collect())
+    }
+
+    /// Remove the inbound group sessions that were created before the given
+    /// cutoff, e.g. to enforce a retention policy for room keys.
+    ///
+    /// Only sessions with a known [creation
+    /// time](InboundGroupSession::creation_time()) are removed, sessions that
+    /// were imported or forwarded to us are never pruned. If
+    /// `keep_if_not_backed_up` is set, sessions that haven't been backed up
+    /// yet are kept as well, so pruning can't lose room keys that only exist
+    /// on this device.
+    ///
+    /// All the sessions are removed in a single transaction. The upsert lock is
+    /// held while the sessions are selected and removed, so a better copy of
+    /// a session stored concurrently by [`Store::upsert_if_better()`], e.g. an
+    /// imported one without a creation time, isn't removed based on the
+    /// metadata of the copy it replaced. Sessions that are in use while they
+    /// are pruned, e.g. to decrypt an event, stay usable until they are
+    /// dropped.
+    ///
+    /// Returns the number of removed sessions.
+    ///
+    /// **Note**: The store doesn't index sessions by their creation time, so
+    /// this loads every inbound group session we have.
+    pub async fn prune_sessions_created_before(
+        &self,
+        cutoff: MilliSecondsSinceUnixEpoch,
+        keep_if_not_backed_up: bool,
+    ) -> Result<usize> {
+        let _guard = self.inner.inbound_group_session_upsert_lock.lock().await;
+
+        let sessions: Vec<_> = self
+            .inner
+            .store
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .filter(|s| s.creation_time().is_some_and(|created| created < cutoff))
+            .filter(|s| !keep_if_not_backed_up || s.backed_up())
+            .collect();
+
+        let count = sessions.len();
+
+        if count > 0 {
+            info!(count, "Pruning inbound group sessions");
+
+            let changes =
+                Changes { deleted_inbound_group_sessions: sessions, ..Default::default() };
+            self.save_changes(changes).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Get the rooms that have at least one room key that hasn't been backed
+    /// up yet, sorted by room ID.
+    ///
+    /// The store only looks at the room keys that aren't backed up, and only
+    /// at their metadata, the ratchets of the room keys aren't unpickled.
+    pub async fn rooms_needing_backup(&self) -> Result<Vec<OwnedRoomId>> {
+        let mut rooms = self.inner.store.inbound_group_session_rooms_for_backup().await?;
+        rooms.sort();
+
+        Ok(rooms)
+    }
+
+    /// Count the room keys we have for the given room, and how many of them
+    /// have been backed up.
+    ///
+    /// This is the per-room counterpart of
+    /// [`CryptoStore::inbound_group_session_counts()`], e.g. to show the
+    /// progress of the backup of a room.
+    ///
+    /// **Note**: This reflects the local backed up flag of the room keys, which
+    /// is only set once an upload succeeded, so it may lag behind the state of
+    /// the backup on the server.
+    ///
+    /// [`CryptoStore::inbound_group_session_counts()`]: crate::store::CryptoStore::inbound_group_session_counts
+    pub async fn room_key_counts(&self, room_id: &RoomId) -> Result<RoomKeyCounts> {
+        self.inner.store.inbound_group_session_counts_for_room(room_id).await
+    }
+
+    /// Collect aggregate statistics about all the room keys we have, e.g. for a
+    /// diagnostics screen.
+    ///
+    /// See [`InboundGroupSession::trust_summary()`] for what the key source
+    /// counts mean.
+    ///
+    /// Only the metadata of the room keys is loaded from the store, their
+    /// ratchets aren't unpickled.
+    pub async fn session_stats(&self) -> Result<SessionStats> {
+        let sessions = self.inner.store.get_inbound_group_session_metadata().await?;
+
+        Ok(sessions.into_iter().fold(SessionStats::default(), |mut stats, session| {
+            let backed_up = usize::from(session.backed_up);
+
+            stats.total += 1;
+            stats.backed_up += backed_up;
+
+            match session.key_source {
+                KeySourceKind::Direct => stats.direct += 1,
+                KeySourceKind::Forwarded => stats.forwarded += 1,
+                KeySourceKind::Imported => stats.imported += 1,
+            }
+
+            let room = stats.rooms.entry(session.room_id).or_default();
+            room.total += 1;
+            room.backed_up += backed_up;
+
+            stats
+        }))
+    }
+
+    /// Get the reason why the room key with the given session ID was withheld
+    /// from us, if its owner told us so.
+    ///
+    /// This can be used to explain why an event encrypted with this session
+    /// can't be decrypted, e.g. because our device was blacklisted. Only
+    /// [`m.room_key.withheld`] events sent by the owner of the session are
+    /// recorded.
+    ///
+    /// [`m.room_key.withheld`]: RoomKeyWithheldEvent
+    pub async fn withheld_code(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<WithheldCode>> {
+        Ok(self
+            .inner
+            .store
+            .get_withheld_info(room_id, session_id)
+            .await?
+            .map(|e| e.content.withheld_code()))
+    }
+
+    /// Export a single room key.
+    ///
+    /// Returns `None` if we don't have a room key with the given room and
+    /// session ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room the room key is used in.
+    ///
+    /// * `session_id` - The ID of the room key.
+    ///
+    /// * `message_index` - The message index the room key should be exported
+    /// at, the first known index of the room key is used if this is `None` or
+    /// smaller than the first known index.
+    pub async fn export_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+        message_index: Option<u32>,
+    ) -> Result<Option<ExportedRoomKey>> {
+        let Some(session) = self.get_inbound_group_session(room_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(match message_index {
+            Some(message_index) => session.export_at_index(message_index).await,
+            None => session.export().await,
+        }))
+    }
+
     #[cfg(test)]
     /// Testing helper to allow to save only a set of devices
     pub(crate) async fn save_devices(&self, devices: &[ReadOnlyDevice]) -> Result<()> {
@@ -518,6 +921,113 @@ impl Store {
         self.save_changes(changes).await
     }
 
+    /// Mark the given [`InboundGroupSession`]s as backed up and persist them.
+    ///
+    /// The sessions that weren't marked as backed up before are reported to
+    /// the [`Store::backup_state_stream()`].
+    pub(crate) async fn mark_as_backed_up(&self, sessions: Vec<InboundGroupSession>) -> Result<()> {
+        let updates: Vec<_> = sessions
+            .iter()
+            .filter(|session| !session.backed_up())
+            .map(|session| (session.room_id().to_owned(), session.session_id().to_owned(), true))
+            .collect();
+
+        for session in &sessions {
+            session.mark_as_backed_up();
+        }
+
+        let changes = Changes { inbound_group_sessions: sessions, ..Default::default() };
+        self.save_changes(changes).await?;
+
+        self.send_backup_state_updates(updates);
+
+        Ok(())
+    }
+
+    /// Reset the backed up flag of all our [`InboundGroupSession`]s.
+    ///
+    /// The sessions that were marked as backed up before are reported to the
+    /// [`Store::backup_state_stream()`].
+    pub async fn reset_backup_state(&self) -> Result<()> {
+        // Finding out which sessions were backed up requires loading all of
+        // them, only do so if someone is listening.
+        let updates: Vec<_> = if self.inner.backup_state_sender.receiver_count() > 0 {
+            self.inner
+                .store
+                .get_inbound_group_sessions()
+                .await?
+                .into_iter()
+                .filter(|session| session.backed_up())
+                .map(|session| {
+                    (session.room_id().to_owned(), session.session_id().to_owned(), false)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        self.inner.store.reset_backup_state().await?;
+
+        self.send_backup_state_updates(updates);
+
+        Ok(())
+    }
+
+    /// Reset the backed up flag of all our [`InboundGroupSession`]s and store
+    /// the given backup version.
+    ///
+    /// The room keys and the new version are saved together, so we can't end
+    /// up with a new version whose room keys are still marked as backed up.
+    /// The sessions that were marked as backed up before are reported to the
+    /// [`Store::backup_state_stream()`].
+    ///
+    /// Returns the number of sessions whose backup state got reset.
+    pub(crate) async fn reset_backup_state_for_new_version(
+        &self,
+        backup_version: String,
+    ) -> Result<usize> {
+        // Hold the upsert lock, a better copy of a session that is stored
+        // concurrently would otherwise be overwritten by the one we loaded.
+        let _guard = self.inner.inbound_group_session_upsert_lock.lock().await;
+
+        let sessions: Vec<_> = self
+            .inner
+            .store
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .filter(|session| session.backed_up())
+            .collect();
+
+        let updates: Vec<_> = sessions
+            .iter()
+            .map(|session| (session.room_id().to_owned(), session.session_id().to_owned(), false))
+            .collect();
+
+        for session in &sessions {
+            session.reset_backup_state();
+        }
+
+        let changes = Changes {
+            backup_version: Some(backup_version),
+            inbound_group_sessions: sessions,
+            ..Default::default()
+        };
+        self.inner.store.save_changes(changes).await?;
+
+        let reset_count = updates.len();
+        self.send_backup_state_updates(updates);
+
+        Ok(reset_count)
+    }
+
+    fn send_backup_state_updates(&self, updates: Vec<BackupStateUpdate>) {
+        if !updates.is_empty() {
+            // Ignore the result. It can only fail if there are no listeners.
+            let _ = self.inner.backup_state_sender.send(updates);
+        }
+    }
+
     /// Get the display name of our own device.
     pub(crate) async fn device_display_name(&self) -> Result<Option<String>, CryptoStoreError> {
         Ok(self
@@ -1016,23 +1526,63 @@ impl Store {
     /// the stream. Updates that happen at the same time are batched into a
     /// [`Vec`].
     ///
+    /// Room keys that aren't stored because we already have the same or a
+    /// better copy, e.g. when using [`Store::upsert_if_better()`], aren't
+    /// reported. A better copy of a room key we already had is reported again,
+    /// since it may be able to decrypt events that couldn't be decrypted
+    /// before.
+    ///
+    /// If the reader of the stream lags too far behind, the updates it missed
+    /// are dropped and a [`BroadcastStreamRecvError::Lagged`] error with the
+    /// number of missed updates is yielded instead. Readers that can't afford
+    /// to miss room keys should treat it as if any room key had been received.
+    pub fn room_keys_received_stream(
+        &self,
+    ) -> impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>> {
+        BroadcastStream::new(self.inner.room_keys_received_sender.subscribe())
+    }
+
+    /// Receive notifications of the backed up flag of inbound group sessions
+    /// changing as a [`Stream`].
+    ///
+    /// Each item contains the room ID and the session ID of a session, and
+    /// whether it's now backed up. The flag changes when room keys are marked
+    /// as backed up after a backup request was sent, when room keys are
+    /// restored or imported from a backup, and when the backup state is
+    /// reset, see [`Store::reset_backup_state()`].
+    ///
+    /// Updates that are queued up while the stream isn't polled are coalesced,
+    /// only the latest state of each session is reported.
+    ///
     /// If the reader of the stream lags too far behind, a warning will be
     /// logged and items will be dropped.
-    pub fn room_keys_received_stream(&self) -> impl Stream<Item = Vec<RoomKeyInfo>> {
-        let stream = BroadcastStream::new(self.inner.room_keys_received_sender.subscribe());
+    pub fn backup_state_stream(&self) -> impl Stream<Item = BackupStateUpdate> {
+        let stream = BroadcastStream::new(self.inner.backup_state_sender.subscribe());
+
+        stream
+            .filter_map(|result| async move {
+                match result {
+                    Ok(r) => Some(r),
+                    Err(BroadcastStreamRecvError::Lagged(lag)) => {
+                        warn!("backup_state_stream missed {lag} updates");
+                        None
+                    }
+                }
+            })
+            .ready_chunks(10)
+            .flat_map(|batches| {
+                let mut latest = BTreeMap::new();
 
-        // the raw BroadcastStream gives us Results which can fail with
-        // BroadcastStreamRecvError if the reader falls behind. That's annoying to work
-        // with, so here we just drop the errors.
-        stream.filter_map(|result| async move {
-            match result {
-                Ok(r) => Some(r),
-                Err(BroadcastStreamRecvError::Lagged(lag)) => {
-                    warn!("room_keys_received_stream missed {} updates", lag);
-                    None
+                for (room_id, session_id, backed_up) in batches.into_iter().flatten() {
+                    latest.insert((room_id, session_id), backed_up);
                 }
-            }
-        })
+
+                futures_util::stream::iter(
+                    latest
+                        .into_iter()
+                        .map(|((room_id, session_id), backed_up)| (room_id, session_id, backed_up)),
+                )
+            })
     }
 
     /// Creates a `CryptoStoreLock` for this store, that will contain the given
@@ -17,15 +17,16 @@ use std::{collections::HashMap, fmt, sync::Arc};
 use async_trait::async_trait;
 use matrix_sdk_common::AsyncTraitDeps;
 use ruma::{
-    events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
+    events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, RoomId,
+    TransactionId, UserId,
 };
 use tokio::sync::Mutex;
 
 use super::{BackupKeys, Changes, CryptoStoreError, Result, RoomKeyCounts, RoomSettings};
 use crate::{
     olm::{
-        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PrivateCrossSigningIdentity,
-        Session,
+        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PickledInboundGroupSession,
+        PrivateCrossSigningIdentity, Session, SessionMetadata,
     },
     types::events::room_key_withheld::RoomKeyWithheldEvent,
     GossipRequest, GossippedSecret, ReadOnlyAccount, ReadOnlyDevice, ReadOnlyUserIdentities,
@@ -98,16 +99,62 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Get all the inbound group sessions we have stored.
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
+    /// Get all the inbound group sessions we have stored for the given room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error>;
+
+    /// Get the pickles of all the inbound group sessions we have stored for
+    /// the given room, without unpickling them.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    async fn get_pickled_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<PickledInboundGroupSession>, Self::Error>;
+
+    /// Get the metadata of all the inbound group sessions we have stored.
+    ///
+    /// Only the pickles of the sessions are deserialized, their ratchets
+    /// aren't unpickled.
+    async fn get_inbound_group_session_metadata(&self)
+        -> Result<Vec<SessionMetadata>, Self::Error>;
+
     /// Get the number inbound group sessions we have and how many of them are
     /// backed up.
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts, Self::Error>;
 
+    /// Get the number of inbound group sessions we have for the given room
+    /// and how many of them are backed up.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    async fn inbound_group_session_counts_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyCounts, Self::Error>;
+
     /// Get all the inbound group sessions we have not backed up yet.
     async fn inbound_group_sessions_for_backup(
         &self,
         limit: usize,
     ) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
+    /// Get the rooms that have at least one inbound group session we have not
+    /// backed up yet.
+    ///
+    /// The rooms are returned in no particular order, each room only once.
+    async fn inbound_group_session_rooms_for_backup(&self)
+        -> Result<Vec<OwnedRoomId>, Self::Error>;
+
     /// Reset the backup state of all the stored inbound group sessions.
     async fn reset_backup_state(&self) -> Result<(), Self::Error>;
 
@@ -308,10 +355,35 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_inbound_group_sessions().await.map_err(Into::into)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.0.get_inbound_group_sessions_for_room(room_id).await.map_err(Into::into)
+    }
+
+    async fn get_pickled_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<PickledInboundGroupSession>> {
+        self.0.get_pickled_inbound_group_sessions_for_room(room_id).await.map_err(Into::into)
+    }
+
+    async fn get_inbound_group_session_metadata(&self) -> Result<Vec<SessionMetadata>> {
+        self.0.get_inbound_group_session_metadata().await.map_err(Into::into)
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         self.0.inbound_group_session_counts().await.map_err(Into::into)
     }
 
+    async fn inbound_group_session_counts_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyCounts> {
+        self.0.inbound_group_session_counts_for_room(room_id).await.map_err(Into::into)
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         limit: usize,
@@ -319,6 +391,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.inbound_group_sessions_for_backup(limit).await.map_err(Into::into)
     }
 
+    async fn inbound_group_session_rooms_for_backup(&self) -> Result<Vec<OwnedRoomId>> {
+        self.0.inbound_group_session_rooms_for_backup().await.map_err(Into::into)
+    }
+
     async fn reset_backup_state(&self) -> Result<()> {
         self.0.reset_backup_state().await.map_err(Into::into)
     }
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, RwLock},
 };
 
@@ -23,7 +23,7 @@ use indexed_db_futures::prelude::*;
 use matrix_sdk_crypto::{
     olm::{
         IdentityKeys, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
-        PrivateCrossSigningIdentity, Session,
+        PickledInboundGroupSession, PrivateCrossSigningIdentity, Session, SessionMetadata,
     },
     store::{
         caches::SessionStore, BackupKeys, Changes, CryptoStore, CryptoStoreError, RoomKeyCounts,
@@ -36,10 +36,11 @@ use matrix_sdk_crypto::{
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
-    OwnedUserId, RoomId, TransactionId, UserId,
+    OwnedRoomId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::Mutex;
+use tracing::warn;
 use wasm_bindgen::JsValue;
 use web_sys::IdbKeyRange;
 
@@ -408,7 +409,11 @@ impl_crypto_store! {
                 keys::IDENTITIES,
             ),
 
-            (!changes.inbound_group_sessions.is_empty(), keys::INBOUND_GROUP_SESSIONS),
+            (
+                !changes.inbound_group_sessions.is_empty()
+                    || !changes.deleted_inbound_group_sessions.is_empty(),
+                keys::INBOUND_GROUP_SESSIONS,
+            ),
             (!changes.outbound_group_sessions.is_empty(), keys::OUTBOUND_GROUP_SESSIONS),
             (!changes.message_hashes.is_empty(), keys::OLM_HASHES),
             (!changes.withheld_session_info.is_empty(), keys::DIRECT_WITHHELD_INFO),
@@ -512,6 +517,18 @@ impl_crypto_store! {
             }
         }
 
+        if !changes.deleted_inbound_group_sessions.is_empty() {
+            let sessions = tx.object_store(keys::INBOUND_GROUP_SESSIONS)?;
+
+            for session in &changes.deleted_inbound_group_sessions {
+                let room_id = session.room_id();
+                let session_id = session.session_id();
+                let key = self.encode_key(keys::INBOUND_GROUP_SESSIONS, (room_id, session_id));
+
+                sessions.delete(&key)?;
+            }
+        }
+
         if !changes.outbound_group_sessions.is_empty() {
             let sessions = tx.object_store(keys::OUTBOUND_GROUP_SESSIONS)?;
 
@@ -842,11 +859,75 @@ impl_crypto_store! {
             .get_all()?
             .await?
             .iter()
-            .filter_map(|i| self.deserialize_value(i).ok())
+            .filter_map(|i| match self.deserialize_value(i) {
+                Ok(pickle) => Some(pickle),
+                Err(e) => {
+                    warn!("Skipping an inbound group session that couldn't be deserialized: {e:?}");
+                    None
+                }
+            })
+            .filter_map(|p| InboundGroupSession::from_pickle(p).ok())
+            .collect())
+    }
+
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self
+            .get_pickled_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
             .filter_map(|p| InboundGroupSession::from_pickle(p).ok())
             .collect())
     }
 
+    async fn get_pickled_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<PickledInboundGroupSession>> {
+        let range = self.encode_to_range(keys::INBOUND_GROUP_SESSIONS, room_id)?;
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(
+                keys::INBOUND_GROUP_SESSIONS,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(keys::INBOUND_GROUP_SESSIONS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .filter_map(|i| match self.deserialize_value(i) {
+                Ok(pickle) => Some(pickle),
+                Err(e) => {
+                    warn!("Skipping an inbound group session that couldn't be deserialized: {e:?}");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn get_inbound_group_session_metadata(&self) -> Result<Vec<SessionMetadata>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(
+                keys::INBOUND_GROUP_SESSIONS,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(keys::INBOUND_GROUP_SESSIONS)?
+            .get_all()?
+            .await?
+            .iter()
+            .filter_map(|i| match self.deserialize_value::<PickledInboundGroupSession>(i) {
+                Ok(pickle) => Some(pickle.metadata()),
+                Err(e) => {
+                    warn!("Skipping an inbound group session that couldn't be deserialized: {e:?}");
+                    None
+                }
+            })
+            .collect())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let all = self.get_inbound_group_sessions().await?;
         let backed_up = all.iter().filter(|s| s.backed_up()).count();
@@ -854,6 +935,16 @@ impl_crypto_store! {
         Ok(RoomKeyCounts { total: all.len(), backed_up })
     }
 
+    async fn inbound_group_session_counts_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyCounts> {
+        let sessions = self.get_inbound_group_sessions_for_room(room_id).await?;
+        let backed_up = sessions.iter().filter(|s| s.backed_up()).count();
+
+        Ok(RoomKeyCounts { total: sessions.len(), backed_up })
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         limit: usize,
@@ -867,6 +958,33 @@ impl_crypto_store! {
             .collect())
     }
 
+    async fn inbound_group_session_rooms_for_backup(&self) -> Result<Vec<OwnedRoomId>> {
+        // Only deserialize the pickles, the ratchets don't need to be unpickled
+        // to find out which room a session belongs to.
+        let rooms: HashSet<OwnedRoomId> = self
+            .inner
+            .transaction_on_one_with_mode(
+                keys::INBOUND_GROUP_SESSIONS,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(keys::INBOUND_GROUP_SESSIONS)?
+            .get_all()?
+            .await?
+            .iter()
+            .filter_map(|i| match self.deserialize_value::<PickledInboundGroupSession>(i) {
+                Ok(pickle) => Some(pickle.metadata()),
+                Err(e) => {
+                    warn!("Skipping an inbound group session that couldn't be deserialized: {e:?}");
+                    None
+                }
+            })
+            .filter(|m| !m.backed_up)
+            .map(|m| m.room_id)
+            .collect();
+
+        Ok(rooms.into_iter().collect())
+    }
+
     async fn reset_backup_state(&self) -> Result<()> {
         let inbound_group_sessions = self
             .get_inbound_group_sessions()
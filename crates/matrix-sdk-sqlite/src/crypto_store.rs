@@ -14,7 +14,7 @@
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt,
     path::{Path, PathBuf},
     sync::{Arc, RwLock},
@@ -25,7 +25,7 @@ use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
 use matrix_sdk_crypto::{
     olm::{
         IdentityKeys, InboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
-        PrivateCrossSigningIdentity, Session,
+        PrivateCrossSigningIdentity, Session, SessionMetadata,
     },
     store::{caches::SessionStore, BackupKeys, Changes, CryptoStore, RoomKeyCounts, RoomSettings},
     types::events::room_key_withheld::RoomKeyWithheldEvent,
@@ -35,7 +35,7 @@ use matrix_sdk_crypto::{
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
-    OwnedUserId, RoomId, TransactionId, UserId,
+    OwnedRoomId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 use rusqlite::OptionalExtension;
 use serde::{de::DeserializeOwned, Serialize};
@@ -292,6 +292,12 @@ trait SqliteConnectionExt {
         backed_up: bool,
     ) -> rusqlite::Result<()>;
 
+    fn delete_inbound_group_session(
+        &self,
+        room_id: &[u8],
+        session_id: &[u8],
+    ) -> rusqlite::Result<()>;
+
     fn set_outbound_group_session(&self, room_id: &[u8], data: &[u8]) -> rusqlite::Result<()>;
 
     fn set_device(&self, user_id: &[u8], device_id: &[u8], data: &[u8]) -> rusqlite::Result<()>;
@@ -352,6 +358,18 @@ impl SqliteConnectionExt for rusqlite::Connection {
         Ok(())
     }
 
+    fn delete_inbound_group_session(
+        &self,
+        room_id: &[u8],
+        session_id: &[u8],
+    ) -> rusqlite::Result<()> {
+        self.execute(
+            "DELETE FROM inbound_group_session WHERE room_id = ? AND session_id = ?",
+            (room_id, session_id),
+        )?;
+        Ok(())
+    }
+
     fn set_outbound_group_session(&self, room_id: &[u8], data: &[u8]) -> rusqlite::Result<()> {
         self.execute(
             "INSERT INTO outbound_group_session (room_id, data) \
@@ -478,6 +496,20 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
             .await?)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: Key,
+    ) -> Result<Vec<(Vec<u8>, bool)>> {
+        Ok(self
+            .prepare(
+                "SELECT data, backed_up FROM inbound_group_session WHERE room_id = ?",
+                move |mut stmt| {
+                    stmt.query((room_id,))?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+                },
+            )
+            .await?)
+    }
+
     async fn get_inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let total = self
             .query_row("SELECT count(*) FROM inbound_group_session", (), |row| row.get(0))
@@ -492,6 +524,27 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
         Ok(RoomKeyCounts { total, backed_up })
     }
 
+    async fn get_inbound_group_session_counts_for_room(
+        &self,
+        room_id: Key,
+    ) -> Result<RoomKeyCounts> {
+        let total = self
+            .query_row(
+                "SELECT count(*) FROM inbound_group_session WHERE room_id = ?",
+                (room_id.clone(),),
+                |row| row.get(0),
+            )
+            .await?;
+        let backed_up = self
+            .query_row(
+                "SELECT count(*) FROM inbound_group_session WHERE room_id = ? AND backed_up = TRUE",
+                (room_id,),
+                |row| row.get(0),
+            )
+            .await?;
+        Ok(RoomKeyCounts { total, backed_up })
+    }
+
     async fn get_inbound_group_sessions_for_backup(&self, limit: usize) -> Result<Vec<Vec<u8>>> {
         Ok(self
             .prepare(
@@ -501,6 +554,15 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
             .await?)
     }
 
+    async fn get_inbound_group_sessions_not_backed_up(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare(
+                "SELECT data FROM inbound_group_session WHERE backed_up = FALSE",
+                |mut stmt| stmt.query(())?.mapped(|row| row.get(0)).collect(),
+            )
+            .await?)
+    }
+
     async fn reset_inbound_group_session_backup_state(&self) -> Result<()> {
         self.execute("UPDATE inbound_group_session SET backed_up = FALSE", ()).await?;
         Ok(())
@@ -741,6 +803,17 @@ impl CryptoStore for SqliteCryptoStore {
             inbound_session_changes.push((room_id, session_id, pickle));
         }
 
+        let deleted_inbound_sessions: Vec<_> = changes
+            .deleted_inbound_group_sessions
+            .iter()
+            .map(|session| {
+                (
+                    self.encode_key("inbound_group_session", session.room_id().as_bytes()),
+                    self.encode_key("inbound_group_session", session.session_id()),
+                )
+            })
+            .collect();
+
         let mut outbound_session_changes = Vec::new();
         for session in changes.outbound_group_sessions {
             let room_id = self.encode_key("outbound_group_session", session.room_id().as_bytes());
@@ -812,6 +885,10 @@ impl CryptoStore for SqliteCryptoStore {
                     )?;
                 }
 
+                for (room_id, session_id) in &deleted_inbound_sessions {
+                    txn.delete_inbound_group_session(room_id, session_id)?;
+                }
+
                 for (room_id, pickle) in &outbound_session_changes {
                     let serialized_session = this.serialize_json(&pickle)?;
                     txn.set_outbound_group_session(room_id, &serialized_session)?;
@@ -920,10 +997,58 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.get_pickled_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .map(|pickle| Ok(InboundGroupSession::from_pickle(pickle)?))
+            .collect()
+    }
+
+    async fn get_pickled_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<PickledInboundGroupSession>> {
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                self.deserialize_pickled_inbound_group_session(&value, backed_up)
+            })
+            .collect()
+    }
+
+    async fn get_inbound_group_session_metadata(&self) -> Result<Vec<SessionMetadata>> {
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                let pickle = self.deserialize_pickled_inbound_group_session(&value, backed_up)?;
+                Ok(pickle.metadata())
+            })
+            .collect()
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         Ok(self.acquire().await?.get_inbound_group_session_counts().await?)
     }
 
+    async fn inbound_group_session_counts_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomKeyCounts> {
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        Ok(self.acquire().await?.get_inbound_group_session_counts_for_room(room_id).await?)
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         limit: usize,
@@ -940,6 +1065,24 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn inbound_group_session_rooms_for_backup(&self) -> Result<Vec<OwnedRoomId>> {
+        // The room ID column is hashed, so take the room ID from the pickle, this
+        // doesn't require the ratchet to be unpickled.
+        let rooms = self
+            .acquire()
+            .await?
+            .get_inbound_group_sessions_not_backed_up()
+            .await?
+            .into_iter()
+            .map(|value| {
+                let pickle = self.deserialize_pickled_inbound_group_session(&value, false)?;
+                Ok(pickle.metadata().room_id)
+            })
+            .collect::<Result<HashSet<_>>>()?;
+
+        Ok(rooms.into_iter().collect())
+    }
+
     async fn reset_backup_state(&self) -> Result<()> {
         Ok(self.acquire().await?.reset_inbound_group_session_backup_state().await?)
     }
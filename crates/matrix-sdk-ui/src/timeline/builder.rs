@@ -12,11 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "e2e-encryption")]
+use std::collections::BTreeSet;
 use std::sync::Arc;
 
 use async_std::sync::Mutex;
 use eyeball::SharedObservable;
+#[cfg(feature = "e2e-encryption")]
+use futures_core::Stream;
+#[cfg(feature = "e2e-encryption")]
+use futures_util::{pin_mut, StreamExt};
 use imbl::Vector;
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk::encryption::{BroadcastStreamRecvError, RoomKeyInfo};
 use matrix_sdk::{
     deserialized_responses::SyncTimelineEvent, executor::spawn, sync::RoomUpdate, Room,
 };
@@ -25,16 +33,24 @@ use ruma::events::{
     AnySyncTimelineEvent,
 };
 use tokio::sync::{broadcast, mpsc};
-use tracing::{error, info, info_span, trace, warn, Instrument};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
-#[cfg(feature = "e2e-encryption")]
-use super::to_device::{handle_forwarded_room_key_event, handle_room_key_event};
 use super::{
     inner::{TimelineInner, TimelineInnerSettings},
     queue::send_queued_messages,
     BackPaginationStatus, Timeline, TimelineDropHandle,
 };
 
+/// The membership of the current user from which a [`Timeline`] shows events,
+/// see [`TimelineBuilder::since_own_membership()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MembershipSince {
+    /// Only show the events since the latest time the current user joined the
+    /// room.
+    Join,
+}
+
 /// Builder that allows creating and configuring various parts of a
 /// [`Timeline`].
 #[must_use]
@@ -106,6 +122,108 @@ impl TimelineBuilder {
         self
     }
 
+    /// Whether to add edits to the timeline as separate items, instead of
+    /// replacing the content of the edited item.
+    ///
+    /// Each edit is then added as a message whose [`Message::replaces()`]
+    /// points to the edited event, and the edited item keeps its original
+    /// content, with [`Message::is_edited()`] still returning `true`.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`Message::replaces()`]: super::Message::replaces
+    /// [`Message::is_edited()`]: super::Message::is_edited
+    pub fn edits_as_separate_items(mut self, separate: bool) -> Self {
+        self.settings.edits_as_separate_items = separate;
+        self
+    }
+
+    /// Whether to add a [`VirtualTimelineItem::UnreadBoundary`] before the
+    /// first message that is unread when the timeline is created.
+    ///
+    /// The boundary is computed once from the fully-read marker and the read
+    /// receipts of the current user, and isn't moved afterwards. No item is
+    /// added if there are no unread messages, or if the latest read event
+    /// isn't part of the initial events. This requires the timeline to track
+    /// the read marker and receipts, which is the case for timelines created
+    /// with [`RoomExt::timeline()`].
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`VirtualTimelineItem::UnreadBoundary`]: super::VirtualTimelineItem::UnreadBoundary
+    /// [`RoomExt::timeline()`]: super::RoomExt::timeline
+    pub fn add_unread_boundary(mut self, add: bool) -> Self {
+        self.settings.add_unread_boundary = add;
+        self
+    }
+
+    /// Whether to add a [`VirtualTimelineItem::TimelineStart`] before the
+    /// first item once back-pagination reaches the start of the room.
+    ///
+    /// This lets clients show a "This is the beginning of the room" banner
+    /// above the `m.room.create` event. If the room is the result of an
+    /// upgrade, the item contains the ID of the room it replaced.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`VirtualTimelineItem::TimelineStart`]: super::VirtualTimelineItem::TimelineStart
+    pub fn add_timeline_start(mut self, add: bool) -> Self {
+        self.settings.add_timeline_start = add;
+        self
+    }
+
+    /// Whether to add a [`VirtualTimelineItem::DayDivider`] before the first
+    /// event of every day.
+    ///
+    /// Clients that group events by date themselves can disable this, the
+    /// timeline then only contains event items and the other virtual items
+    /// that were enabled.
+    ///
+    /// Defaults to `true`.
+    ///
+    /// [`VirtualTimelineItem::DayDivider`]: super::VirtualTimelineItem::DayDivider
+    pub fn day_dividers(mut self, add: bool) -> Self {
+        self.settings.add_day_dividers = add;
+        self
+    }
+
+    /// Only add the events that were sent since the given membership of the
+    /// current user to the timeline.
+    ///
+    /// With [`MembershipSince::Join`], the events that predate the latest
+    /// join of the current user are left out, even if they could be decrypted,
+    /// the membership event of the join itself is kept. If the user joins the
+    /// room again while the timeline is alive, the items from before the new
+    /// join are removed. Once back-pagination reaches the join, the events
+    /// that are paginated further back aren't added anymore.
+    ///
+    /// This doesn't affect which events are requested from the server, and
+    /// only looks at the membership events that are part of the timeline.
+    ///
+    /// Defaults to `None`, which shows all the events.
+    pub fn since_own_membership(mut self, since: MembershipSince) -> Self {
+        self.settings.since_own_membership = Some(since);
+        self
+    }
+
+    /// Whether to retry decrypting the events that couldn't be decrypted as
+    /// soon as the room keys they were encrypted with are received.
+    ///
+    /// This covers room keys received from other devices, imported from a file
+    /// or restored from a backup. Clients that retry the decryption themselves,
+    /// using [`Timeline::retry_decryption()`], can disable this.
+    ///
+    /// The timeline starts listening for room keys when it's built, so this
+    /// has no effect if the client isn't logged in at that point. The
+    /// timeline needs to be built again after logging in in that case.
+    ///
+    /// Defaults to `true`.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn retry_decryption_on_room_keys(mut self, retry: bool) -> Self {
+        self.settings.retry_decryption_on_room_keys = retry;
+        self
+    }
+
     /// Create a [`Timeline`] with the options set on this builder.
     #[tracing::instrument(
         skip(self),
@@ -120,6 +238,9 @@ impl TimelineBuilder {
         let Self { room, prev_token, events, settings } = self;
         let has_events = !events.is_empty();
         let track_read_marker_and_receipts = settings.track_read_receipts;
+        let add_unread_boundary = settings.add_unread_boundary;
+        #[cfg(feature = "e2e-encryption")]
+        let retry_decryption_on_room_keys = settings.retry_decryption_on_room_keys;
 
         let mut inner = TimelineInner::new(room).with_settings(settings);
 
@@ -168,9 +289,11 @@ impl TimelineBuilder {
         if track_read_marker_and_receipts {
             inner.load_fully_read_event().await;
         }
+        if add_unread_boundary {
+            inner.insert_unread_boundary().await;
+        }
 
         let room = inner.room();
-        let client = room.client();
 
         let start_token = Arc::new(Mutex::new(prev_token));
 
@@ -220,24 +343,31 @@ impl TimelineBuilder {
             .instrument(info_span!("room_update_handler", room_id = ?room.room_id()))
         });
 
-        // Not using room.add_event_handler here because RoomKey events are
-        // to-device events that are not received in the context of a room.
-
         #[cfg(feature = "e2e-encryption")]
-        let room_key_handle = client
-            .add_event_handler(handle_room_key_event(inner.clone(), room.room_id().to_owned()));
-        #[cfg(feature = "e2e-encryption")]
-        let forwarded_room_key_handle = client.add_event_handler(handle_forwarded_room_key_event(
-            inner.clone(),
-            room.room_id().to_owned(),
-        ));
-
-        let handles = vec![
-            #[cfg(feature = "e2e-encryption")]
-            room_key_handle,
-            #[cfg(feature = "e2e-encryption")]
-            forwarded_room_key_handle,
-        ];
+        let room_keys_join_handle = if retry_decryption_on_room_keys {
+            let room_keys_stream = room.client().encryption().room_keys_received_stream().await;
+
+            if room_keys_stream.is_none() {
+                warn!("The client isn't logged in, can't retry decryption when room keys arrive");
+            }
+
+            room_keys_stream.map(|room_keys_stream| {
+                spawn(
+                    retry_decryption_on_room_keys_task(
+                        inner.clone(),
+                        room.clone(),
+                        room_keys_stream,
+                    )
+                    .instrument(
+                        info_span!("room_keys_received_handler", room_id = ?room.room_id()),
+                    ),
+                )
+            })
+        } else {
+            None
+        };
+        #[cfg(not(feature = "e2e-encryption"))]
+        let room_keys_join_handle = None;
 
         let (msg_sender, msg_receiver) = mpsc::channel(1);
         info!("Starting message-sending loop");
@@ -251,9 +381,8 @@ impl TimelineBuilder {
             _end_token: Mutex::new(None),
             msg_sender,
             drop_handle: Arc::new(TimelineDropHandle {
-                client,
-                event_handler_handles: handles,
                 room_update_join_handle,
+                room_keys_join_handle,
             }),
         };
 
@@ -268,3 +397,62 @@ impl TimelineBuilder {
         timeline
     }
 }
+
+/// Retry decrypting the events of the timeline that couldn't be decrypted
+/// whenever room keys for its room are received.
+#[cfg(feature = "e2e-encryption")]
+async fn retry_decryption_on_room_keys_task(
+    inner: TimelineInner,
+    room: Room,
+    room_keys_stream: impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>>,
+) {
+    retry_decryption_on_room_keys(&inner, &room, room_keys_stream).await;
+
+    // The stream belongs to the `OlmMachine` of the client, and ends when the
+    // machine is regenerated. Subscribe to the stream of the new machine then.
+    while let Some(room_keys_stream) = room.client().encryption().room_keys_received_stream().await
+    {
+        // Room keys may have been received before we subscribed again.
+        debug!("The room keys stream ended, retrying decryption of all the events");
+        inner.retry_event_decryption(&room, None).await;
+
+        retry_decryption_on_room_keys(&inner, &room, room_keys_stream).await;
+    }
+
+    warn!("The client isn't logged in anymore, stopped retrying decryption on room keys");
+}
+
+#[cfg(feature = "e2e-encryption")]
+async fn retry_decryption_on_room_keys(
+    inner: &TimelineInner,
+    room: &Room,
+    room_keys_stream: impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>>,
+) {
+    pin_mut!(room_keys_stream);
+
+    while let Some(room_keys) = room_keys_stream.next().await {
+        let session_ids = match room_keys {
+            Ok(room_keys) => {
+                let session_ids: BTreeSet<_> = room_keys
+                    .into_iter()
+                    .filter(|info| info.room_id == room.room_id())
+                    .map(|info| info.session_id)
+                    .collect();
+
+                if session_ids.is_empty() {
+                    continue;
+                }
+
+                trace!(?session_ids, "Received room keys, retrying decryption");
+                Some(session_ids)
+            }
+            Err(BroadcastStreamRecvError::Lagged(lag)) => {
+                // We don't know which room keys we missed, try them all.
+                warn!(lag, "Missed room key updates, retrying decryption of all the events");
+                None
+            }
+        };
+
+        inner.retry_event_decryption(room, session_ids).await;
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
+
+use async_stream::stream;
+use eyeball_im::VectorDiff;
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
+use tokio::time::{timeout_at, Instant};
+
+/// Collect the diffs of the given stream during `window`, starting from the
+/// first diff that is received, and yield them as a single compacted batch.
+pub(super) fn debounce_diffs<T>(
+    stream: impl Stream<Item = VectorDiff<T>>,
+    window: Duration,
+) -> impl Stream<Item = Vec<VectorDiff<T>>> {
+    stream! {
+        pin_mut!(stream);
+
+        while let Some(diff) = stream.next().await {
+            let deadline = Instant::now() + window;
+            let mut diffs = vec![diff];
+
+            while let Ok(Some(diff)) = timeout_at(deadline, stream.next()).await {
+                diffs.push(diff);
+            }
+
+            yield compact_set_diffs(diffs);
+        }
+    }
+}
+
+/// Collapse the [`VectorDiff::Set`]s on the same index into the latest one.
+///
+/// Only the `Set`s that aren't separated by another kind of diff are
+/// collapsed, since other diffs can shift the items around. Applying the
+/// compacted diffs results in the same vector as applying the original ones.
+pub(super) fn compact_set_diffs<T>(diffs: Vec<VectorDiff<T>>) -> Vec<VectorDiff<T>> {
+    let mut compacted = Vec::with_capacity(diffs.len());
+    // Position in `compacted` of the latest `Set` for a given index.
+    let mut latest_sets = HashMap::new();
+
+    for diff in diffs {
+        match diff {
+            VectorDiff::Set { index, value } => match latest_sets.entry(index) {
+                Entry::Occupied(entry) => {
+                    compacted[*entry.get()] = VectorDiff::Set { index, value };
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(compacted.len());
+                    compacted.push(VectorDiff::Set { index, value });
+                }
+            },
+            diff => {
+                latest_sets.clear();
+                compacted.push(diff);
+            }
+        }
+    }
+
+    compacted
+}
@@ -52,12 +52,14 @@ use super::{
         EventTimelineItemKind, LocalEventTimelineItem, Profile, RemoteEventOrigin,
         RemoteEventTimelineItem,
     },
+    grouping::{is_grouped_at, update_grouping_at},
+    inner::TimelineInnerSettings,
     item::timeline_item,
     read_receipts::maybe_add_implicit_read_receipt,
     util::{find_read_marker, rfind_event_by_id, rfind_event_item, timestamp_to_date},
-    EventTimelineItem, InReplyToDetails, Message, OtherState, ReactionGroup, ReactionSenderData,
-    Sticker, TimelineDetails, TimelineInnerState, TimelineItem, TimelineItemContent,
-    VirtualTimelineItem, DEFAULT_SANITIZER_MODE,
+    CallEvent, CallEventKind, EventTimelineItem, InReplyToDetails, Message, OtherState,
+    ReactionGroup, ReactionSenderData, Sticker, TimelineDetails, TimelineInnerState, TimelineItem,
+    TimelineItemContent, VirtualTimelineItem, DEFAULT_SANITIZER_MODE,
 };
 use crate::{events::SyncTimelineEventWithoutContent, timeline::polls::PollState};
 
@@ -79,6 +81,7 @@ pub(super) enum Flow {
 pub(super) struct TimelineEventContext {
     pub(super) sender: OwnedUserId,
     pub(super) sender_profile: Option<Profile>,
+    pub(super) sender_power_level: Option<i64>,
     pub(super) timestamp: MilliSecondsSinceUnixEpoch,
     pub(super) is_own_event: bool,
     pub(super) encryption_info: Option<EncryptionInfo>,
@@ -221,6 +224,8 @@ pub(super) struct TimelineEventHandler<'a> {
     state: &'a mut TimelineInnerState,
     ctx: TimelineEventContext,
     track_read_receipts: bool,
+    edits_as_separate_items: bool,
+    add_day_dividers: bool,
     result: HandleEventResult,
 }
 
@@ -248,9 +253,16 @@ impl<'a> TimelineEventHandler<'a> {
     pub(super) fn new(
         state: &'a mut TimelineInnerState,
         ctx: TimelineEventContext,
-        track_read_receipts: bool,
+        settings: &TimelineInnerSettings,
     ) -> Self {
-        Self { state, ctx, track_read_receipts, result: HandleEventResult::default() }
+        Self {
+            state,
+            ctx,
+            track_read_receipts: settings.track_read_receipts,
+            edits_as_separate_items: settings.edits_as_separate_items,
+            add_day_dividers: settings.add_day_dividers,
+            result: HandleEventResult::default(),
+        }
     }
 
     /// Handle an event.
@@ -290,13 +302,28 @@ impl<'a> TimelineEventHandler<'a> {
                     relates_to: Some(message::Relation::Replacement(re)),
                     ..
                 }) => {
-                    self.handle_room_message_edit(re);
+                    if self.edits_as_separate_items {
+                        self.add_room_message_edit(re, should_add);
+                    } else {
+                        self.handle_room_message_edit(re);
+                    }
                 }
                 AnyMessageLikeEventContent::RoomMessage(c) => {
-                    self.add(
-                        should_add,
-                        TimelineItemContent::message(c, relations, &self.state.items),
-                    );
+                    let content = if self.edits_as_separate_items {
+                        // Keep the original content, but still mark the
+                        // message as edited if the server bundled an edit.
+                        let edited = relations.has_replacement();
+                        let mut relations = relations;
+                        relations.replace = None;
+
+                        let mut message = Message::from_event(c, relations, &self.state.items);
+                        message.edited = edited;
+                        TimelineItemContent::Message(message)
+                    } else {
+                        TimelineItemContent::message(c, relations, &self.state.items)
+                    };
+
+                    self.add(should_add, content);
                 }
                 AnyMessageLikeEventContent::RoomEncrypted(c) => self.handle_room_encrypted(c),
                 AnyMessageLikeEventContent::Sticker(content) => {
@@ -311,6 +338,20 @@ impl<'a> TimelineEventHandler<'a> {
                 }
                 AnyMessageLikeEventContent::UnstablePollResponse(c) => self.handle_poll_response(c),
                 AnyMessageLikeEventContent::UnstablePollEnd(c) => self.handle_poll_end(c),
+                AnyMessageLikeEventContent::CallInvite(c) => self.add(
+                    should_add,
+                    TimelineItemContent::Call(CallEvent {
+                        call_id: c.call_id,
+                        kind: CallEventKind::Invite,
+                    }),
+                ),
+                AnyMessageLikeEventContent::CallHangup(c) => self.add(
+                    should_add,
+                    TimelineItemContent::Call(CallEvent {
+                        call_id: c.call_id,
+                        kind: CallEventKind::Hangup,
+                    }),
+                ),
                 // TODO
                 _ => {
                     debug!(
@@ -369,6 +410,7 @@ impl<'a> TimelineEventHandler<'a> {
                 // wouldn't normally be visible. Remove it.
                 trace!("Removing UTD that was successfully retried");
                 self.state.items.remove(idx);
+                update_grouping_at(&mut self.state.items, idx);
                 self.result.item_removed = true;
             }
 
@@ -406,6 +448,10 @@ impl<'a> TimelineEventHandler<'a> {
                     info!("Edit event applies to a poll, discarding");
                     return None;
                 }
+                TimelineItemContent::Call(_) => {
+                    info!("Edit event applies to a call event, discarding");
+                    return None;
+                }
                 TimelineItemContent::UnableToDecrypt(_) => {
                     info!("Edit event applies to event that couldn't be decrypted, discarding");
                     return None;
@@ -431,6 +477,7 @@ impl<'a> TimelineEventHandler<'a> {
                 msgtype,
                 in_reply_to: msg.in_reply_to.clone(),
                 edited: true,
+                replaces: msg.replaces.clone(),
             });
 
             let edit_json = match &self.ctx.flow {
@@ -439,10 +486,72 @@ impl<'a> TimelineEventHandler<'a> {
             };
 
             trace!("Applying edit");
-            Some(event_item.with_content(new_content, edit_json))
+            Some(event_item.with_edited_content(
+                msg.clone(),
+                new_content,
+                self.ctx.timestamp,
+                edit_json,
+            ))
         });
     }
 
+    /// Add an edit as a separate timeline item, and mark the edited item as
+    /// edited without touching its content.
+    #[instrument(skip_all, fields(replacement_event_id = ?replacement.event_id))]
+    fn add_room_message_edit(
+        &mut self,
+        replacement: Replacement<RoomMessageEventContentWithoutRelation>,
+        should_add: bool,
+    ) {
+        let mut is_valid = true;
+
+        update_timeline_item!(self, &replacement.event_id, found: |event_item| {
+            if self.ctx.sender != event_item.sender() {
+                info!(
+                    original_sender = ?event_item.sender(), edit_sender = ?self.ctx.sender,
+                    "Edit event applies to another user's timeline item, discarding"
+                );
+                is_valid = false;
+                return None;
+            }
+
+            let TimelineItemContent::Message(msg) = event_item.content() else {
+                info!("Edit event doesn't apply to a message, discarding");
+                is_valid = false;
+                return None;
+            };
+
+            if msg.edited {
+                return None;
+            }
+
+            let new_content = TimelineItemContent::Message(Message { edited: true, ..msg.clone() });
+            Some(event_item.with_content(new_content, event_item.latest_edit_json().cloned()))
+        }, not_found: || {
+            // The edited event may not have been loaded yet, add the edit
+            // anyway. Its bundled relations will mark it as edited later.
+            debug!("Timeline item not found, adding edit without updating it");
+        });
+
+        if !is_valid {
+            return;
+        }
+
+        let mut msgtype = replacement.new_content.msgtype;
+        // Edit's content is never supposed to contain the reply fallback.
+        msgtype.sanitize(DEFAULT_SANITIZER_MODE, RemoveReplyFallback::No);
+
+        self.add(
+            should_add,
+            TimelineItemContent::Message(Message {
+                msgtype,
+                in_reply_to: None,
+                edited: false,
+                replaces: Some(replacement.event_id),
+            }),
+        );
+    }
+
     // Redacted reaction events are no-ops so don't need to be handled
     #[instrument(skip_all, fields(relates_to_event_id = ?c.relates_to.event_id))]
     fn handle_reaction(&mut self, c: ReactionEventContent) {
@@ -564,7 +673,12 @@ impl<'a> TimelineEventHandler<'a> {
             };
 
             trace!("Applying edit");
-            Some(event_item.with_content(new_content, edit_json))
+            Some(event_item.with_edited_content(
+                msg.clone(),
+                new_content,
+                self.ctx.timestamp,
+                edit_json,
+            ))
         });
     }
 
@@ -832,32 +946,45 @@ impl<'a> TimelineEventHandler<'a> {
             }
         };
 
-        let mut item = EventTimelineItem::new(sender, sender_profile, timestamp, content, kind);
+        let mut item = EventTimelineItem::new(
+            sender,
+            sender_profile,
+            self.ctx.sender_power_level,
+            timestamp,
+            content,
+            kind,
+        );
 
         match &self.ctx.flow {
             Flow::Local { .. } => {
                 trace!("Adding new local timeline item");
 
-                // Check if the latest event has the same date as this event.
-                if let Some(latest_event) =
-                    self.state.items.iter().rev().find_map(|item| item.as_event())
-                {
-                    let old_ts = latest_event.timestamp();
-
-                    if let Some(day_divider_item) =
-                        self.state.maybe_create_day_divider_from_timestamps(old_ts, timestamp)
+                if self.add_day_dividers {
+                    // Check if the latest event has the same date as this event.
+                    if let Some(latest_event) =
+                        self.state.items.iter().rev().find_map(|item| item.as_event())
                     {
-                        trace!("Adding day divider (local)");
-                        self.state.items.push_back(day_divider_item);
+                        let old_ts = latest_event.timestamp();
+
+                        if let Some(day_divider_item) =
+                            self.state.maybe_create_day_divider_from_timestamps(old_ts, timestamp)
+                        {
+                            trace!("Adding day divider (local)");
+                            self.state.items.push_back(day_divider_item);
+                        }
+                    } else {
+                        // If there is no event item, there is no day divider yet.
+                        trace!("Adding first day divider (local)");
+                        let day_divider = self
+                            .state
+                            .new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
+                        self.state.items.push_back(day_divider);
                     }
-                } else {
-                    // If there is no event item, there is no day divider yet.
-                    trace!("Adding first day divider (local)");
-                    let day_divider =
-                        self.state.new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
-                    self.state.items.push_back(day_divider);
                 }
 
+                item.is_grouped_with_previous =
+                    is_grouped_at(&self.state.items, self.state.items.len(), &item);
+
                 let item = self.state.new_timeline_item(item);
                 self.state.items.push_back(item);
             }
@@ -876,21 +1003,30 @@ impl<'a> TimelineEventHandler<'a> {
 
                 trace!("Adding new remote timeline item at the start");
 
-                // Check if the earliest day divider has the same date as this event.
-                if let Some(VirtualTimelineItem::DayDivider(divider_ts)) =
-                    self.state.items.front().and_then(|item| item.as_virtual())
-                {
-                    if let Some(day_divider_item) =
-                        self.state.maybe_create_day_divider_from_timestamps(*divider_ts, timestamp)
+                // The item is inserted after the day divider, if there is one.
+                let insert_idx = if self.add_day_dividers {
+                    // Check if the earliest day divider has the same date as this event.
+                    if let Some(VirtualTimelineItem::DayDivider(divider_ts)) =
+                        self.state.items.front().and_then(|item| item.as_virtual())
                     {
-                        self.state.items.push_front(day_divider_item);
+                        if let Some(day_divider_item) = self
+                            .state
+                            .maybe_create_day_divider_from_timestamps(*divider_ts, timestamp)
+                        {
+                            self.state.items.push_front(day_divider_item);
+                        }
+                    } else {
+                        // The list must always start with a day divider.
+                        let day_divider = self
+                            .state
+                            .new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
+                        self.state.items.push_front(day_divider);
                     }
+
+                    1
                 } else {
-                    // The list must always start with a day divider.
-                    let day_divider =
-                        self.state.new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
-                    self.state.items.push_front(day_divider);
-                }
+                    0
+                };
 
                 if self.track_read_receipts {
                     maybe_add_implicit_read_receipt(
@@ -902,8 +1038,11 @@ impl<'a> TimelineEventHandler<'a> {
                     );
                 }
 
+                item.is_grouped_with_previous = is_grouped_at(&self.state.items, insert_idx, &item);
+
                 let item = self.state.new_timeline_item(item);
-                self.state.items.insert(1, item);
+                self.state.items.insert(insert_idx, item);
+                update_grouping_at(&mut self.state.items, insert_idx + 1);
             }
 
             Flow::Remote {
@@ -916,6 +1055,8 @@ impl<'a> TimelineEventHandler<'a> {
 
                 let mut removed_event_item_id = None;
                 let mut removed_day_divider_id = None;
+                // The index of the item that followed the removed one.
+                let mut removed_idx = None;
                 if let Some((idx, old_item)) = result {
                     if old_item.as_remote().is_some() {
                         // Item was previously received from the server. This
@@ -960,6 +1101,9 @@ impl<'a> TimelineEventHandler<'a> {
                             );
                         }
 
+                        item.is_grouped_with_previous =
+                            is_grouped_at(&self.state.items, idx, &item);
+
                         trace!(idx, "Replacing existing event");
                         self.state.items.set(idx, timeline_item(item, old_item_id));
                         return;
@@ -969,25 +1113,30 @@ impl<'a> TimelineEventHandler<'a> {
                     // divider (if necessary) before re-adding the item.
                     trace!("Removing local echo or duplicate timeline item");
                     removed_event_item_id = Some(self.state.items.remove(idx).internal_id);
+                    removed_idx = Some(idx);
 
-                    assert_ne!(
-                        idx, 0,
-                        "there is never an event item at index 0 because \
-                         the first event item is preceded by a day divider"
-                    );
+                    if self.add_day_dividers {
+                        assert_ne!(
+                            idx, 0,
+                            "there is never an event item at index 0 because \
+                             the first event item is preceded by a day divider"
+                        );
 
-                    // Pre-requisites for removing the day divider:
-                    // 1. there is one preceding the old item at all
-                    if self.state.items[idx - 1].is_day_divider()
-                        // 2. the item after the old one that was removed is virtual (it should be
-                        //    impossible for this to be a read marker)
-                        && self.state
-                            .items
-                            .get(idx)
-                            .map_or(true, |item| item.is_virtual())
-                    {
-                        trace!("Removing day divider");
-                        removed_day_divider_id = Some(self.state.items.remove(idx - 1).internal_id);
+                        // Pre-requisites for removing the day divider:
+                        // 1. there is one preceding the old item at all
+                        if self.state.items[idx - 1].is_day_divider()
+                            // 2. the item after the old one that was removed is virtual (it should
+                            //    be impossible for this to be a read marker)
+                            && self.state
+                                .items
+                                .get(idx)
+                                .map_or(true, |item| item.is_virtual())
+                        {
+                            trace!("Removing day divider");
+                            removed_day_divider_id =
+                                Some(self.state.items.remove(idx - 1).internal_id);
+                            removed_idx = Some(idx - 1);
+                        }
                     }
 
                     // no return here, below code for adding a new event
@@ -1029,42 +1178,46 @@ impl<'a> TimelineEventHandler<'a> {
 
                 // Keep push semantics, if we're inserting at the end.
                 let should_push = insert_idx == self.state.items.len();
-
-                if let Some(latest_event) = latest_event {
-                    // Check if that event has the same date as the new one.
-                    let old_ts = latest_event.timestamp();
-
-                    if timestamp_to_date(old_ts) != timestamp_to_date(timestamp) {
-                        trace!("Adding day divider (remote)");
-
-                        let id = match removed_day_divider_id {
-                            // If a day divider was removed for an item about to be moved and we
-                            // now need to add a new one, reuse the previous one's ID.
-                            Some(day_divider_id) => day_divider_id,
-                            None => self.state.next_internal_id(),
-                        };
-
-                        let day_divider_item =
-                            timeline_item(VirtualTimelineItem::DayDivider(timestamp), id);
-
+                let first_insert_idx = insert_idx;
+
+                if self.add_day_dividers {
+                    if let Some(latest_event) = latest_event {
+                        // Check if that event has the same date as the new one.
+                        let old_ts = latest_event.timestamp();
+
+                        if timestamp_to_date(old_ts) != timestamp_to_date(timestamp) {
+                            trace!("Adding day divider (remote)");
+
+                            let id = match removed_day_divider_id {
+                                // If a day divider was removed for an item about to be moved and we
+                                // now need to add a new one, reuse the previous one's ID.
+                                Some(day_divider_id) => day_divider_id,
+                                None => self.state.next_internal_id(),
+                            };
+
+                            let day_divider_item =
+                                timeline_item(VirtualTimelineItem::DayDivider(timestamp), id);
+
+                            if should_push {
+                                self.state.items.push_back(day_divider_item);
+                            } else {
+                                self.state.items.insert(insert_idx, day_divider_item);
+                                insert_idx += 1;
+                            }
+                        }
+                    } else {
+                        // If there is no event item, there is no day divider yet.
+                        trace!("Adding first day divider (remote)");
+                        let new_day_divider = self
+                            .state
+                            .new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
                         if should_push {
-                            self.state.items.push_back(day_divider_item);
+                            self.state.items.push_back(new_day_divider);
                         } else {
-                            self.state.items.insert(insert_idx, day_divider_item);
+                            self.state.items.insert(insert_idx, new_day_divider);
                             insert_idx += 1;
                         }
                     }
-                } else {
-                    // If there is no event item, there is no day divider yet.
-                    trace!("Adding first day divider (remote)");
-                    let new_day_divider =
-                        self.state.new_timeline_item(VirtualTimelineItem::DayDivider(timestamp));
-                    if should_push {
-                        self.state.items.push_back(new_day_divider);
-                    } else {
-                        self.state.items.insert(insert_idx, new_day_divider);
-                        insert_idx += 1;
-                    }
                 }
 
                 if self.track_read_receipts {
@@ -1085,6 +1238,10 @@ impl<'a> TimelineEventHandler<'a> {
                     None => self.state.next_internal_id(),
                 };
 
+                // A day divider pushed above doesn't move `insert_idx`.
+                let item_idx = if should_push { self.state.items.len() } else { insert_idx };
+                item.is_grouped_with_previous = is_grouped_at(&self.state.items, item_idx, &item);
+
                 trace!("Adding new remote timeline item after all non-pending events");
                 let new_item = timeline_item(item, id);
                 if should_push {
@@ -1092,13 +1249,25 @@ impl<'a> TimelineEventHandler<'a> {
                 } else {
                     self.state.items.insert(insert_idx, new_item);
                 }
+
+                // The items that followed the removed item and the new one
+                // are now preceded by another item.
+                if let Some(idx) = removed_idx {
+                    let inserted = item_idx + 1 - first_insert_idx;
+                    let idx = if idx >= first_insert_idx { idx + inserted } else { idx };
+                    update_grouping_at(&mut self.state.items, idx);
+                }
+                update_grouping_at(&mut self.state.items, item_idx + 1);
             }
 
             #[cfg(feature = "e2e-encryption")]
             Flow::Remote { position: TimelineItemPosition::Update(idx), .. } => {
                 trace!("Updating timeline item at position {idx}");
+                item.is_grouped_with_previous = is_grouped_at(&self.state.items, *idx, &item);
+
                 let id = self.state.items[*idx].internal_id;
                 self.state.items.set(*idx, timeline_item(item, id));
+                update_grouping_at(&mut self.state.items, *idx + 1);
             }
         }
 
@@ -46,6 +46,7 @@ use ruma::{
             third_party_invite::RoomThirdPartyInviteEventContent,
             tombstone::RoomTombstoneEventContent,
             topic::RoomTopicEventContent,
+            ImageInfo,
         },
         space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
         sticker::StickerEventContent,
@@ -54,8 +55,8 @@ use ruma::{
         MessageLikeEventType, OriginalSyncMessageLikeEvent, StateEventType,
     },
     html::RemoveReplyFallback,
-    OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedTransactionId, OwnedUserId, RoomVersionId,
-    UserId,
+    EventId, MilliSecondsSinceUnixEpoch, MxcUri, OwnedDeviceId, OwnedEventId, OwnedMxcUri,
+    OwnedTransactionId, OwnedUserId, OwnedVoipId, RoomVersionId, UserId, VoipId,
 };
 use tracing::{error, warn};
 
@@ -112,6 +113,9 @@ pub enum TimelineItemContent {
 
     /// An `m.poll.start` event.
     Poll(PollState),
+
+    /// An `m.call.invite` or `m.call.hangup` event.
+    Call(CallEvent),
 }
 
 impl TimelineItemContent {
@@ -198,6 +202,17 @@ impl TimelineItemContent {
         matches!(self, Self::RedactedMessage)
     }
 
+    /// Whether this content comes from a state event.
+    pub(crate) fn is_state(&self) -> bool {
+        matches!(
+            self,
+            Self::MembershipChange(_)
+                | Self::ProfileChange(_)
+                | Self::OtherState(_)
+                | Self::FailedToParseState { .. }
+        )
+    }
+
     // These constructors could also be `From` implementations, but that would
     // allow users to call them directly, which should not be supported
     pub(crate) fn message(
@@ -285,6 +300,7 @@ impl TimelineItemContent {
             | Self::RedactedMessage
             | Self::Sticker(_)
             | Self::Poll(_)
+            | Self::Call(_)
             | Self::UnableToDecrypt(_) => Self::RedactedMessage,
             Self::MembershipChange(ev) => Self::MembershipChange(ev.redact(room_version)),
             Self::ProfileChange(ev) => Self::ProfileChange(ev.redact()),
@@ -300,6 +316,7 @@ pub struct Message {
     pub(in crate::timeline) msgtype: MessageType,
     pub(in crate::timeline) in_reply_to: Option<InReplyToDetails>,
     pub(in crate::timeline) edited: bool,
+    pub(in crate::timeline) replaces: Option<OwnedEventId>,
 }
 
 impl Message {
@@ -357,7 +374,7 @@ impl Message {
             }
         };
 
-        Self { msgtype, in_reply_to, edited }
+        Self { msgtype, in_reply_to, edited, replaces: None }
     }
 
     /// Get the `msgtype`-specific data of this message.
@@ -382,6 +399,16 @@ impl Message {
         self.edited
     }
 
+    /// Get the event this message is an edit of, if any.
+    ///
+    /// This is only set if the timeline was configured to add edits as
+    /// separate items, see [`TimelineBuilder::edits_as_separate_items()`].
+    ///
+    /// [`TimelineBuilder::edits_as_separate_items()`]: crate::timeline::TimelineBuilder::edits_as_separate_items
+    pub fn replaces(&self) -> Option<&EventId> {
+        self.replaces.as_deref()
+    }
+
     pub(in crate::timeline) fn with_in_reply_to(&self, in_reply_to: InReplyToDetails) -> Self {
         Self { in_reply_to: Some(in_reply_to), ..self.clone() }
     }
@@ -399,16 +426,40 @@ impl From<Message> for RoomMessageEventContent {
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { msgtype: _, in_reply_to, edited } = self;
+        let Self { msgtype: _, in_reply_to, edited, replaces } = self;
         // since timeline items are logged, don't include all fields here so
         // people don't leak personal data in bug reports
         f.debug_struct("Message")
             .field("in_reply_to", in_reply_to)
             .field("edited", edited)
+            .field("replaces", replaces)
             .finish_non_exhaustive()
     }
 }
 
+/// A previous version of an edited message.
+#[derive(Clone, Debug)]
+pub struct OriginalContent {
+    pub(in crate::timeline) content: Option<Message>,
+    pub(in crate::timeline) timestamp: MilliSecondsSinceUnixEpoch,
+}
+
+impl OriginalContent {
+    /// Get the content of this version of the message.
+    ///
+    /// Returns `None` if the message has been redacted since, in which case
+    /// only the fact that an edit happened is retained.
+    pub fn content(&self) -> Option<&Message> {
+        self.content.as_ref()
+    }
+
+    /// Get the timestamp of this version of the message, i.e. the timestamp
+    /// of the original event or of the edit that introduced it.
+    pub fn timestamp(&self) -> MilliSecondsSinceUnixEpoch {
+        self.timestamp
+    }
+}
+
 /// Details about an event being replied to.
 #[derive(Clone, Debug)]
 pub struct InReplyToDetails {
@@ -603,6 +654,49 @@ impl Sticker {
     pub fn content(&self) -> &StickerEventContent {
         &self.content
     }
+
+    /// Get the textual representation of this sticker.
+    pub fn body(&self) -> &str {
+        &self.content.body
+    }
+
+    /// Get the metadata of the image of this sticker.
+    pub fn info(&self) -> &ImageInfo {
+        &self.content.info
+    }
+
+    /// Get the URL of the image of this sticker.
+    pub fn url(&self) -> &MxcUri {
+        &self.content.url
+    }
+}
+
+/// An `m.call.invite` or `m.call.hangup` event.
+#[derive(Clone, Debug)]
+pub struct CallEvent {
+    pub(in crate::timeline) call_id: OwnedVoipId,
+    pub(in crate::timeline) kind: CallEventKind,
+}
+
+impl CallEvent {
+    /// Get the ID of the call this event belongs to.
+    pub fn call_id(&self) -> &VoipId {
+        &self.call_id
+    }
+
+    /// Get the kind of this call event.
+    pub fn kind(&self) -> CallEventKind {
+        self.kind
+    }
+}
+
+/// The kind of a [`CallEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallEventKind {
+    /// An `m.call.invite` event, a call was started.
+    Invite,
+    /// An `m.call.hangup` event, a call was ended.
+    Hangup,
 }
 
 /// An event changing a room membership.
@@ -624,6 +718,17 @@ impl RoomMembershipChange {
         &self.content
     }
 
+    /// The display name of the user, as set by this event.
+    ///
+    /// This is always `None` for redacted events, since the display name is
+    /// removed by the redaction.
+    pub fn display_name(&self) -> Option<&str> {
+        match &self.content {
+            FullStateEventContent::Original { content, .. } => content.displayname.as_deref(),
+            FullStateEventContent::Redacted(_) => None,
+        }
+    }
+
     /// The membership change induced by this event.
     ///
     /// If this returns `None`, it doesn't mean that there was no change, but
@@ -32,9 +32,10 @@ mod remote;
 
 pub use self::{
     content::{
-        AnyOtherFullStateEventContent, BundledReactions, EncryptedMessage, InReplyToDetails,
-        MemberProfileChange, MembershipChange, Message, OtherState, ReactionGroup, RepliedToEvent,
-        RoomMembershipChange, Sticker, TimelineItemContent,
+        AnyOtherFullStateEventContent, BundledReactions, CallEvent, CallEventKind,
+        EncryptedMessage, InReplyToDetails, MemberProfileChange, MembershipChange, Message,
+        OriginalContent, OtherState, ReactionGroup, RepliedToEvent, RoomMembershipChange, Sticker,
+        TimelineItemContent,
     },
     local::EventSendState,
 };
@@ -54,12 +55,22 @@ pub struct EventTimelineItem {
     pub(super) sender: OwnedUserId,
     /// The sender's profile of the event.
     pub(super) sender_profile: TimelineDetails<Profile>,
+    /// The power level of the sender when the event was added to the
+    /// timeline.
+    pub(super) sender_power_level: Option<i64>,
     /// The timestamp of the event.
     pub(super) timestamp: MilliSecondsSinceUnixEpoch,
     /// The content of the event.
     pub(super) content: TimelineItemContent,
     /// The kind of event timeline item, local or remote.
     pub(super) kind: EventTimelineItemKind,
+    /// The previous versions of the content, if it has been edited.
+    pub(super) edit_history: Vec<OriginalContent>,
+    /// The timestamp of the latest edit, if any.
+    pub(super) latest_edit_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+    /// Whether this item is part of the same group of messages as the
+    /// previous one.
+    pub(super) is_grouped_with_previous: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -81,11 +92,22 @@ impl EventTimelineItem {
     pub(super) fn new(
         sender: OwnedUserId,
         sender_profile: TimelineDetails<Profile>,
+        sender_power_level: Option<i64>,
         timestamp: MilliSecondsSinceUnixEpoch,
         content: TimelineItemContent,
         kind: EventTimelineItemKind,
     ) -> Self {
-        Self { sender, sender_profile, timestamp, content, kind }
+        Self {
+            sender,
+            sender_profile,
+            sender_power_level,
+            timestamp,
+            content,
+            kind,
+            edit_history: Vec::new(),
+            latest_edit_timestamp: None,
+            is_grouped_with_previous: false,
+        }
     }
 
     /// If the supplied low-level SyncTimelineEventy is suitable for use as the
@@ -145,16 +167,23 @@ impl EventTimelineItem {
         .into();
 
         let room = client.get_room(room_id);
-        let sender_profile = if let Some(room) = room {
-            room.profile(&sender)
-                .await
-                .map(TimelineDetails::Ready)
-                .unwrap_or(TimelineDetails::Unavailable)
+        let (sender_profile, sender_power_level) = if let Some(room) = room {
+            let (profile, power_level) = room.profile_and_power_level(&sender).await;
+            let profile =
+                profile.map(TimelineDetails::Ready).unwrap_or(TimelineDetails::Unavailable);
+            (profile, power_level)
         } else {
-            TimelineDetails::Unavailable
+            (TimelineDetails::Unavailable, None)
         };
 
-        Some(EventTimelineItem::new(sender, sender_profile, timestamp, item_content, event_kind))
+        Some(EventTimelineItem::new(
+            sender,
+            sender_profile,
+            sender_power_level,
+            timestamp,
+            item_content,
+            event_kind,
+        ))
     }
 
     /// Check whether this item is a local echo.
@@ -236,11 +265,32 @@ impl EventTimelineItem {
         &self.sender_profile
     }
 
+    /// Get the power level of the sender in the room.
+    ///
+    /// This is the power level the sender had when the event was added to the
+    /// timeline, or when its sender profile was last updated. Other changes of
+    /// the power levels of the room aren't reflected. It's `None` if the
+    /// sender isn't known to be a member of the room, and for local echoes
+    /// until their sender profile is updated.
+    pub fn sender_power_level(&self) -> Option<i64> {
+        self.sender_power_level
+    }
+
     /// Get the content of this item.
     pub fn content(&self) -> &TimelineItemContent {
         &self.content
     }
 
+    /// Get the previous versions of the content of this item, oldest first.
+    ///
+    /// Only edits that were received while the timeline was live are
+    /// recorded here, edits that were bundled with the original event are
+    /// not. If the item has been redacted, the entries are kept but their
+    /// content is removed.
+    pub fn edit_history(&self) -> &[OriginalContent] {
+        &self.edit_history
+    }
+
     /// Get the reactions of this item.
     pub fn reactions(&self) -> &BundledReactions {
         // There's not much of a point in allowing reactions to local echoes.
@@ -301,7 +351,32 @@ impl EventTimelineItem {
         }
     }
 
+    /// Whether this item should be displayed as part of the same group as the
+    /// previous item of the timeline.
+    ///
+    /// This is the case if the previous item is a message-like event from the
+    /// same sender that was sent shortly before this one. State events and day
+    /// dividers break groups.
+    pub fn is_grouped_with_previous(&self) -> bool {
+        self.is_grouped_with_previous
+    }
+
+    /// Whether the event was received encrypted, whether it could be decrypted
+    /// or not.
+    ///
+    /// Clients can use this to warn about events that were sent in the clear
+    /// in an encrypted room. Local echoes always return `false`, since we
+    /// don't know yet whether they'll be encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption_info().is_some()
+            || matches!(self.content, TimelineItemContent::UnableToDecrypt(_))
+    }
+
     /// Get the encryption information for the event, if any.
+    ///
+    /// This is only available for events that were decrypted, it contains the
+    /// sender device and the verification state of the room key that was used
+    /// to decrypt the event.
     pub fn encryption_info(&self) -> Option<&EncryptionInfo> {
         match &self.kind {
             EventTimelineItemKind::Local(_) => None,
@@ -312,6 +387,13 @@ impl EventTimelineItem {
     /// Get the raw JSON representation of the initial event (the one that
     /// caused this timeline item to be created).
     ///
+    /// This can be used to offer a "view source" action. For events that were
+    /// decrypted, this is the decrypted event, with the `sender`, `event_id`,
+    /// `origin_server_ts` and `unsigned` fields of the `m.room.encrypted`
+    /// event it was decrypted from. It never contains the ciphertext or the
+    /// room key. For events that couldn't be decrypted, this is the
+    /// `m.room.encrypted` event.
+    ///
     /// Returns `None` if this event hasn't been echoed back by the server
     /// yet.
     pub fn original_json(&self) -> Option<&Raw<AnySyncTimelineEvent>> {
@@ -352,10 +434,33 @@ impl EventTimelineItem {
         Self { kind: kind.into(), ..self.clone() }
     }
 
+    /// Clone the current event item, and replace its content with an edited
+    /// version.
+    ///
+    /// The current content is pushed to the edit history, and
+    /// `latest_edit_json` is updated if the edit was received from the server.
+    pub(super) fn with_edited_content(
+        &self,
+        previous_content: Message,
+        new_content: TimelineItemContent,
+        edit_timestamp: MilliSecondsSinceUnixEpoch,
+        edit_json: Option<Raw<AnySyncTimelineEvent>>,
+    ) -> Self {
+        let mut new = self.with_content(new_content, edit_json);
+        let timestamp = self.latest_edit_timestamp.unwrap_or(self.timestamp);
+        new.edit_history.push(OriginalContent { content: Some(previous_content), timestamp });
+        new.latest_edit_timestamp = Some(edit_timestamp);
+
+        new
+    }
+
     /// Clone the current event item, and update its content.
     ///
     /// Optionally update `latest_edit_json` if the update is an edit received
     /// from the server.
+    ///
+    /// Everything else is kept as is, notably the reactions and the read
+    /// receipts of the item, so they survive edits.
     pub(super) fn with_content(
         &self,
         new_content: TimelineItemContent,
@@ -370,11 +475,22 @@ impl EventTimelineItem {
         new
     }
 
+    /// Clone the current event item, and update whether it is grouped with
+    /// the previous item.
+    pub(super) fn with_grouped_with_previous(&self, is_grouped_with_previous: bool) -> Self {
+        Self { is_grouped_with_previous, ..self.clone() }
+    }
+
     /// Clone the current event item, and update its `sender_profile`.
     pub(super) fn with_sender_profile(&self, sender_profile: TimelineDetails<Profile>) -> Self {
         Self { sender_profile, ..self.clone() }
     }
 
+    /// Clone the current event item, and update its `sender_power_level`.
+    pub(super) fn with_sender_power_level(&self, sender_power_level: Option<i64>) -> Self {
+        Self { sender_power_level, ..self.clone() }
+    }
+
     pub(super) fn redact(&self, room_version: &RoomVersionId) -> Self {
         let content = self.content.redact(room_version);
         let kind = match &self.kind {
@@ -387,6 +503,13 @@ impl EventTimelineItem {
             timestamp: self.timestamp,
             content,
             kind,
+            edit_history: self
+                .edit_history
+                .iter()
+                .map(|o| OriginalContent { content: None, timestamp: o.timestamp })
+                .collect(),
+            latest_edit_timestamp: self.latest_edit_timestamp,
+            is_grouped_with_previous: self.is_grouped_with_previous,
         }
     }
 }
@@ -0,0 +1,122 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{sync::Arc, time::Duration};
+
+use eyeball_im::ObservableVector;
+
+use super::{EventTimelineItem, TimelineItem, TimelineItemKind, VirtualTimelineItem};
+
+/// The maximum time between two messages of the same sender for them to be
+/// part of the same group.
+pub(super) const GROUPING_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Whether `item` should be grouped with the `previous` event item.
+fn is_grouped(previous: &EventTimelineItem, item: &EventTimelineItem) -> bool {
+    if previous.content().is_state() || item.content().is_state() {
+        return false;
+    }
+
+    if previous.sender() != item.sender() {
+        return false;
+    }
+
+    let previous_ts = u64::from(previous.timestamp().0);
+    let ts = u64::from(item.timestamp().0);
+
+    // Events are not always ordered by timestamp, in that case the difference
+    // is treated as zero.
+    u128::from(ts.saturating_sub(previous_ts)) <= GROUPING_WINDOW.as_millis()
+}
+
+/// Whether the given event item would be grouped with the previous item if it
+/// was placed at the given index.
+///
+/// The item currently at `idx`, if any, is not taken into account.
+pub(super) fn is_grouped_at(
+    items: &ObservableVector<Arc<TimelineItem>>,
+    idx: usize,
+    item: &EventTimelineItem,
+) -> bool {
+    let previous = items.iter().take(idx).rev().find_map(|previous| match previous.kind() {
+        TimelineItemKind::Event(event_item) => Some(Some(event_item)),
+        TimelineItemKind::Virtual(
+            VirtualTimelineItem::DayDivider(_) | VirtualTimelineItem::TimelineStart { .. },
+        ) => Some(None),
+        TimelineItemKind::Virtual(
+            VirtualTimelineItem::ReadMarker | VirtualTimelineItem::UnreadBoundary,
+        ) => None,
+    });
+
+    previous.flatten().is_some_and(|previous| is_grouped(previous, item))
+}
+
+/// Recompute whether the first event item at or after `idx` is grouped with
+/// the previous one, and update it if its grouping changed.
+///
+/// Whether an item is grouped only depends on the event item before it, so
+/// after an item was inserted at or removed from `idx`, the first event item
+/// from `idx` on is the only one whose grouping can change.
+pub(super) fn update_grouping_at(items: &mut ObservableVector<Arc<TimelineItem>>, idx: usize) {
+    let update = (idx..items.len())
+        .find_map(|idx| Some((idx, &items[idx], items[idx].as_event()?)))
+        .and_then(|(idx, item, event_item)| {
+            let is_grouped = is_grouped_at(items, idx, event_item);
+            (is_grouped != event_item.is_grouped_with_previous())
+                .then(|| (idx, item.with_kind(event_item.with_grouped_with_previous(is_grouped))))
+        });
+
+    if let Some((idx, item)) = update {
+        items.set(idx, item);
+    }
+}
+
+/// Recompute whether each event item is grouped with the previous one, and
+/// update the items whose grouping changed.
+///
+/// This scans the whole timeline, prefer [`update_grouping_at()`] if only a
+/// single position changed.
+pub(super) fn update_grouping(items: &mut ObservableVector<Arc<TimelineItem>>) {
+    let mut updates = Vec::new();
+    let mut previous: Option<&EventTimelineItem> = None;
+
+    for (idx, item) in items.iter().enumerate() {
+        match item.kind() {
+            TimelineItemKind::Event(event_item) => {
+                let is_grouped = previous.is_some_and(|previous| is_grouped(previous, event_item));
+                if is_grouped != event_item.is_grouped_with_previous() {
+                    updates.push((
+                        idx,
+                        item.with_kind(event_item.with_grouped_with_previous(is_grouped)),
+                    ));
+                }
+
+                previous = Some(event_item);
+            }
+            TimelineItemKind::Virtual(
+                VirtualTimelineItem::DayDivider(_) | VirtualTimelineItem::TimelineStart { .. },
+            ) => {
+                previous = None;
+            }
+            // The read marker and the unread boundary don't break groups.
+            TimelineItemKind::Virtual(
+                VirtualTimelineItem::ReadMarker | VirtualTimelineItem::UnreadBoundary,
+            ) => {}
+        }
+    }
+
+    for (idx, item) in updates {
+        items.set(idx, item);
+    }
+}
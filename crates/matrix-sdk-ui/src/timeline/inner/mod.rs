@@ -14,12 +14,13 @@
 
 #[cfg(feature = "e2e-encryption")]
 use std::collections::BTreeSet;
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::Duration};
 
 use async_rx::StreamExt as _;
 use eyeball_im::{ObservableVectorEntry, VectorDiff, VectorSubscriber};
 use eyeball_im_util::{FilterMapVectorSubscriber, VectorExt};
 use futures_core::Stream;
+use futures_util::StreamExt as _;
 use imbl::Vector;
 use itertools::Itertools;
 #[cfg(all(test, feature = "e2e-encryption"))]
@@ -44,7 +45,7 @@ use ruma::{
         AnyMessageLikeEventContent, AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent,
         AnySyncTimelineEvent,
     },
-    EventId, OwnedEventId, OwnedTransactionId, TransactionId, UserId,
+    EventId, OwnedEventId, OwnedRoomId, OwnedTransactionId, TransactionId, UserId,
 };
 use tracing::{debug, error, field::debug, info, instrument, trace, warn};
 #[cfg(feature = "e2e-encryption")]
@@ -53,14 +54,17 @@ use tracing::{field, info_span, Instrument as _};
 #[cfg(feature = "e2e-encryption")]
 use super::traits::Decryptor;
 use super::{
+    content_for_retry,
+    debounce::debounce_diffs,
     event_handler::TimelineItemPosition,
     event_item::EventItemIdentifier,
+    grouping::update_grouping_at,
     item::timeline_item,
     reactions::ReactionToggleResult,
     traits::RoomDataProvider,
     util::{compare_events_positions, rfind_event_by_id, rfind_event_item, RelativePosition},
-    AnnotationKey, EventSendState, EventTimelineItem, InReplyToDetails, Message, Profile,
-    RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent, TimelineItemKind,
+    AnnotationKey, EventSendState, EventTimelineItem, InReplyToDetails, MembershipSince, Message,
+    Profile, RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent, TimelineItemKind,
 };
 
 mod state;
@@ -98,6 +102,13 @@ pub(super) struct TimelineInnerSettings {
     pub(super) track_read_receipts: bool,
     pub(super) event_filter: Arc<TimelineEventFilterFn>,
     pub(super) add_failed_to_parse: bool,
+    pub(super) edits_as_separate_items: bool,
+    pub(super) add_unread_boundary: bool,
+    pub(super) add_timeline_start: bool,
+    pub(super) add_day_dividers: bool,
+    pub(super) since_own_membership: Option<MembershipSince>,
+    #[cfg(feature = "e2e-encryption")]
+    pub(super) retry_decryption_on_room_keys: bool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -106,6 +117,11 @@ impl fmt::Debug for TimelineInnerSettings {
         f.debug_struct("TimelineInnerSettings")
             .field("track_read_receipts", &self.track_read_receipts)
             .field("add_failed_to_parse", &self.add_failed_to_parse)
+            .field("edits_as_separate_items", &self.edits_as_separate_items)
+            .field("add_unread_boundary", &self.add_unread_boundary)
+            .field("add_timeline_start", &self.add_timeline_start)
+            .field("add_day_dividers", &self.add_day_dividers)
+            .field("since_own_membership", &self.since_own_membership)
             .finish_non_exhaustive()
     }
 }
@@ -116,6 +132,13 @@ impl Default for TimelineInnerSettings {
             track_read_receipts: false,
             event_filter: Arc::new(|_| true),
             add_failed_to_parse: true,
+            edits_as_separate_items: false,
+            add_unread_boundary: false,
+            add_timeline_start: false,
+            add_day_dividers: true,
+            since_own_membership: None,
+            #[cfg(feature = "e2e-encryption")]
+            retry_decryption_on_room_keys: true,
         }
     }
 }
@@ -144,6 +167,32 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         self.state.lock().await.items.clone()
     }
 
+    /// Get the item right before the event with the given ID, optionally
+    /// skipping virtual items.
+    pub(super) async fn item_before(
+        &self,
+        event_id: &EventId,
+        skip_virtual: bool,
+    ) -> Option<Arc<TimelineItem>> {
+        let items = self.items().await;
+        let (idx, _) = rfind_event_by_id(&items, event_id)?;
+
+        items.iter().take(idx).rfind(|item| !skip_virtual || item.as_event().is_some()).cloned()
+    }
+
+    /// Get the item right after the event with the given ID, optionally
+    /// skipping virtual items.
+    pub(super) async fn item_after(
+        &self,
+        event_id: &EventId,
+        skip_virtual: bool,
+    ) -> Option<Arc<TimelineItem>> {
+        let items = self.items().await;
+        let (idx, _) = rfind_event_by_id(&items, event_id)?;
+
+        items.iter().skip(idx + 1).find(|item| !skip_virtual || item.as_event().is_some()).cloned()
+    }
+
     pub(super) async fn subscribe(
         &self,
     ) -> (Vector<Arc<TimelineItem>>, VectorSubscriber<Arc<TimelineItem>>) {
@@ -163,6 +212,14 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         (items, stream)
     }
 
+    pub(super) async fn subscribe_debounced(
+        &self,
+        window: Duration,
+    ) -> (Vector<Arc<TimelineItem>>, impl Stream<Item = Vec<VectorDiff<Arc<TimelineItem>>>>) {
+        let (items, stream) = self.subscribe().await;
+        (items, debounce_diffs(stream, window))
+    }
+
     pub(super) async fn subscribe_filter_map<U, F>(
         &self,
         f: F,
@@ -450,18 +507,12 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             // transaction ID (and thus duplicated the local echo).
             if let Some((idx, _)) = local_echo {
                 warn!("Message echo got duplicated, removing the local one");
-                state.items.remove(idx);
 
-                if idx == 0 {
+                if idx == 0 && self.settings.add_day_dividers {
                     error!("Inconsistent state: Local echo was not preceded by day divider");
-                    return;
                 }
 
-                if idx == state.items.len() && state.items[idx - 1].is_day_divider() {
-                    // The day divider may have been added for this local echo, remove it and let
-                    // the next message decide whether it's required or not.
-                    state.items.remove(idx - 1);
-                }
+                state.remove_local_echo(idx);
             }
 
             return;
@@ -589,22 +640,120 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         let content = item.content.clone();
         state.items.remove(idx);
         state.items.push_back(new_item);
+        update_grouping_at(&mut state.items, idx);
+        let last_idx = state.items.len() - 1;
+        update_grouping_at(&mut state.items, last_idx);
 
         Some(content)
     }
 
+    /// Mark all the local echoes that failed to send, or whose sending was
+    /// cancelled, as pending again.
+    ///
+    /// Local echoes whose content can't be sent again are left untouched.
+    ///
+    /// Returns the transaction ID and content to send of each of them, in
+    /// timeline order.
+    pub(super) async fn prepare_retry_all(
+        &self,
+    ) -> Vec<(OwnedTransactionId, AnyMessageLikeEventContent)> {
+        let mut state = self.state.lock().await;
+        let mut to_retry = Vec::new();
+
+        let num_items = state.items.len();
+        for idx in 0..num_items {
+            let item = state.items[idx].clone();
+            let Some(event_item) = item.as_event() else { continue };
+            let Some(local_item) = event_item.as_local() else { continue };
+            if !matches!(
+                &local_item.send_state,
+                EventSendState::SendingFailed { .. } | EventSendState::Cancelled
+            ) {
+                continue;
+            }
+
+            let Some(content) = content_for_retry(event_item.content.clone()) else { continue };
+            to_retry.push((local_item.transaction_id.clone(), content));
+
+            let new_event_item =
+                event_item.with_kind(local_item.with_send_state(EventSendState::NotSentYet));
+            state.items.set(idx, item.with_kind(new_event_item));
+        }
+
+        to_retry
+    }
+
     pub(super) async fn discard_local_echo(&self, txn_id: &TransactionId) -> bool {
         let mut state = self.state.lock().await;
         if let Some((idx, _)) =
             rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
         {
-            state.items.remove(idx);
+            state.remove_local_echo(idx);
             true
         } else {
             false
         }
     }
 
+    /// Discard a local echo that hasn't been sent yet.
+    ///
+    /// Returns `false` if the local echo couldn't be found or if it has
+    /// already been sent.
+    pub(super) async fn abort_local_echo(&self, txn_id: &TransactionId) -> bool {
+        let mut state = self.state.lock().await;
+        let Some((idx, item)) =
+            rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
+        else {
+            return false;
+        };
+
+        if let Some(EventSendState::Sent { .. }) = item.send_state() {
+            debug!("Can't abort a message that has already been sent");
+            return false;
+        }
+
+        state.remove_local_echo(idx);
+        true
+    }
+
+    /// Whether the local echo with the given transaction ID exists and is
+    /// still waiting to be sent.
+    pub(super) async fn is_pending_local_echo(&self, txn_id: &TransactionId) -> bool {
+        let state = self.state.lock().await;
+        rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
+            .is_some_and(|(_, item)| matches!(item.send_state(), Some(EventSendState::NotSentYet)))
+    }
+
+    /// Wait until the local echo with the given transaction ID has been sent,
+    /// and return the event ID the server assigned to it.
+    ///
+    /// Returns `None` if sending the message failed, or if the local echo was
+    /// removed from the timeline before it was sent.
+    pub(super) async fn wait_for_event_id(&self, txn_id: &TransactionId) -> Option<OwnedEventId> {
+        let (mut items, mut stream) = self.subscribe().await;
+        let (_, local_echo) = rfind_event_item(&items, |it| it.transaction_id() == Some(txn_id))?;
+        let internal_id = local_echo.internal_id;
+
+        loop {
+            // The remote echo takes over the internal ID of the local echo, so
+            // this also finds the message if its remote echo was received
+            // before the response to the send request.
+            let item = items.iter().rev().find(|it| it.internal_id == internal_id)?.as_event()?;
+            match item.send_state() {
+                // The local echo was replaced by the remote echo.
+                None => return item.event_id().map(ToOwned::to_owned),
+                Some(EventSendState::NotSentYet) => {}
+                Some(EventSendState::Sent { event_id }) => return Some(event_id.clone()),
+                Some(EventSendState::SendingFailed { .. } | EventSendState::Cancelled) => {
+                    return None
+                }
+            }
+
+            stream.next().await?;
+            items = self.items().await;
+        }
+    }
+
     /// Handle a list of back-paginated events.
     ///
     /// Returns the number of timeline updates that were made. Short-circuits
@@ -639,6 +788,21 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         self.state.lock().await.set_fully_read_event(fully_read_event_id)
     }
 
+    /// Insert the unread boundary, based on the current fully-read event and
+    /// the read receipts of the own user.
+    pub(super) async fn insert_unread_boundary(&self) {
+        let own_user_id = self.room_data_provider.own_user_id();
+        self.state.lock().await.insert_unread_boundary(own_user_id);
+    }
+
+    /// Insert the start of the timeline, if the timeline was configured to
+    /// show it.
+    pub(super) async fn insert_timeline_start(&self, predecessor: Option<OwnedRoomId>) {
+        if self.settings.add_timeline_start {
+            self.state.lock().await.insert_timeline_start(predecessor);
+        }
+    }
+
     #[cfg(feature = "e2e-encryption")]
     #[instrument(skip(self, room), fields(room_id = ?room.room_id()))]
     pub(super) async fn retry_event_decryption(
@@ -823,19 +987,24 @@ impl<P: RoomDataProvider> TimelineInner<P> {
                 continue;
             }
 
-            match self.room_data_provider.profile(event_item.sender()).await {
+            let (profile, power_level) =
+                self.room_data_provider.profile_and_power_level(event_item.sender()).await;
+
+            match profile {
                 Some(profile) => {
                     trace!(event_id, transaction_id, "Adding profile");
-                    let updated_item =
-                        event_item.with_sender_profile(TimelineDetails::Ready(profile));
+                    let updated_item = event_item
+                        .with_sender_profile(TimelineDetails::Ready(profile))
+                        .with_sender_power_level(power_level);
                     let new_item = entry.with_kind(updated_item);
                     ObservableVectorEntry::set(&mut entry, new_item);
                 }
                 None => {
                     if !event_item.sender_profile().is_unavailable() {
                         trace!(event_id, transaction_id, "Marking profile unavailable");
-                        let updated_item =
-                            event_item.with_sender_profile(TimelineDetails::Unavailable);
+                        let updated_item = event_item
+                            .with_sender_profile(TimelineDetails::Unavailable)
+                            .with_sender_power_level(power_level);
                         let new_item = entry.with_kind(updated_item);
                         ObservableVectorEntry::set(&mut entry, new_item);
                     } else {
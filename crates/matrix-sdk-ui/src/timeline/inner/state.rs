@@ -27,12 +27,12 @@ use ruma::{
     events::{
         receipt::{Receipt, ReceiptType},
         relation::Annotation,
-        room::redaction::RoomRedactionEventContent,
-        AnyMessageLikeEventContent,
+        room::{member::MembershipChange, redaction::RoomRedactionEventContent},
+        AnyMessageLikeEventContent, AnySyncStateEvent, AnySyncTimelineEvent, SyncStateEvent,
     },
     push::Action,
-    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, OwnedUserId, RoomVersionId,
-    UserId,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedTransactionId, OwnedUserId,
+    RoomVersionId, UserId,
 };
 use tokio::sync::{Mutex, MutexGuard, OwnedMutexGuard};
 use tracing::{debug, error, instrument, trace, warn};
@@ -46,13 +46,14 @@ use crate::{
             TimelineEventHandler, TimelineEventKind, TimelineItemPosition,
         },
         event_item::EventItemIdentifier,
+        grouping::{update_grouping, update_grouping_at},
         item::timeline_item,
         polls::PollPendingEvents,
         reactions::{ReactionToggleResult, Reactions},
         traits::RoomDataProvider,
-        util::{rfind_event_item, timestamp_to_date},
-        AnnotationKey, Error as TimelineError, Profile, ReactionSenderData, TimelineItem,
-        TimelineItemKind, VirtualTimelineItem,
+        util::{rfind_event_by_id, rfind_event_item, timestamp_to_date},
+        AnnotationKey, Error as TimelineError, MembershipSince, Profile, ReactionSenderData,
+        TimelineItem, TimelineItemKind, VirtualTimelineItem,
     },
 };
 
@@ -114,6 +115,11 @@ pub(in crate::timeline) struct TimelineInnerState {
     /// the in flight reaction request state that is ongoing
     pub in_flight_reaction: IndexMap<AnnotationKey, ReactionState>,
     pub room_version: RoomVersionId,
+    /// Whether the latest join of the current user was handled, see
+    /// [`TimelineBuilder::since_own_membership()`].
+    ///
+    /// [`TimelineBuilder::since_own_membership()`]: crate::timeline::TimelineBuilder::since_own_membership
+    pub own_join_handled: bool,
 }
 
 impl TimelineInnerState {
@@ -132,6 +138,7 @@ impl TimelineInnerState {
             reaction_state: Default::default(),
             in_flight_reaction: Default::default(),
             room_version,
+            own_join_handled: false,
         }
     }
 
@@ -204,11 +211,18 @@ impl TimelineInnerState {
         settings: &TimelineInnerSettings,
     ) -> HandleEventResult {
         let should_add_event = &*settings.event_filter;
+        let since_own_join = settings.since_own_membership == Some(MembershipSince::Join);
         let raw = event.event;
         let (event_id, sender, timestamp, txn_id, event_kind, should_add) = match raw.deserialize()
         {
             Ok(event) => {
-                let should_add = should_add_event(&event);
+                let mut should_add = should_add_event(&event);
+
+                if since_own_join {
+                    let is_own_join = is_own_join(&event, room_data_provider.own_user_id());
+                    should_add &= self.track_own_join(is_own_join, &position);
+                }
+
                 let room_version = room_data_provider.room_version();
                 (
                     event.event_id().to_owned(),
@@ -226,7 +240,7 @@ impl TimelineInnerState {
                     event.origin_server_ts(),
                     event.transaction_id().map(ToOwned::to_owned),
                     TimelineEventKind::failed_to_parse(event, e),
-                    true,
+                    !since_own_join || self.track_own_join(false, &position),
                 ),
                 Ok(event) => {
                     let event_type = event.event_type();
@@ -244,10 +258,12 @@ impl TimelineInnerState {
         };
 
         let is_own_event = sender == room_data_provider.own_user_id();
-        let sender_profile = room_data_provider.profile(&sender).await;
+        let (sender_profile, sender_power_level) =
+            room_data_provider.profile_and_power_level(&sender).await;
         let ctx = TimelineEventContext {
             sender,
             sender_profile,
+            sender_power_level,
             timestamp,
             is_own_event,
             encryption_info: event.encryption_info,
@@ -260,7 +276,34 @@ impl TimelineInnerState {
             flow: Flow::Remote { event_id, raw_event: raw, txn_id, position, should_add },
         };
 
-        TimelineEventHandler::new(self, ctx, settings.track_read_receipts).handle_event(event_kind)
+        TimelineEventHandler::new(self, ctx, settings).handle_event(event_kind)
+    }
+
+    /// Keep track of the latest join of the current user, for
+    /// [`MembershipSince::Join`].
+    ///
+    /// Returns `false` if the event predates the latest join and shouldn't be
+    /// added to the timeline.
+    fn track_own_join(&mut self, is_own_join: bool, position: &TimelineItemPosition) -> bool {
+        match position {
+            // Back-paginated events are older than all the events we've seen so
+            // far.
+            TimelineItemPosition::Start => {
+                if self.own_join_handled {
+                    return false;
+                }
+                self.own_join_handled = is_own_join;
+            }
+            // A new join makes all the items we have so far predate it.
+            TimelineItemPosition::End { .. } if is_own_join => {
+                debug!("Current user joined the room, removing the items before the join");
+                self.clear();
+                self.own_join_handled = true;
+            }
+            _ => {}
+        }
+
+        true
     }
 
     /// Handle the creation of a new local event.
@@ -275,6 +318,8 @@ impl TimelineInnerState {
         let ctx = TimelineEventContext {
             sender: own_user_id,
             sender_profile: own_profile,
+            // The power level is only looked up for remote events.
+            sender_power_level: None,
             timestamp: MilliSecondsSinceUnixEpoch::now(),
             is_own_event: true,
             // FIXME: Should we supply something here for encrypted rooms?
@@ -285,7 +330,7 @@ impl TimelineInnerState {
             flow: Flow::Local { txn_id },
         };
 
-        TimelineEventHandler::new(self, ctx, settings.track_read_receipts)
+        TimelineEventHandler::new(self, ctx, settings)
             .handle_event(TimelineEventKind::Message { content, relations: Default::default() });
     }
 
@@ -302,6 +347,8 @@ impl TimelineInnerState {
         let ctx = TimelineEventContext {
             sender: own_user_id,
             sender_profile: own_profile,
+            // The power level is only looked up for remote events.
+            sender_power_level: None,
             timestamp: MilliSecondsSinceUnixEpoch::now(),
             is_own_event: true,
             // FIXME: Should we supply something here for encrypted rooms?
@@ -311,8 +358,7 @@ impl TimelineInnerState {
             is_highlighted: false,
             flow: Flow::Local { txn_id: txn_id.clone() },
         };
-        let timeline_event_handler =
-            TimelineEventHandler::new(self, ctx, settings.track_read_receipts);
+        let timeline_event_handler = TimelineEventHandler::new(self, ctx, settings);
 
         match to_redact {
             EventItemIdentifier::TransactionId(txn_id) => {
@@ -335,9 +381,13 @@ impl TimelineInnerState {
         // `VectorDiff::Clear` should be much more efficient to process for
         // subscribers.
         if self.items.iter().any(|item| item.is_local_echo()) {
-            // Remove all remote events and the read marker
+            // Remove all remote events and the virtual items that are tied to them
             self.items.for_each(|entry| {
-                if entry.is_remote_event() || entry.is_read_marker() {
+                if entry.is_remote_event()
+                    || entry.is_read_marker()
+                    || entry.is_unread_boundary()
+                    || entry.is_timeline_start()
+                {
                     ObservableVectorEntry::remove(entry);
                 }
             });
@@ -354,6 +404,8 @@ impl TimelineInnerState {
                     idx += 1;
                 }
             }
+
+            update_grouping(&mut self.items);
         } else {
             self.items.clear();
         }
@@ -361,6 +413,25 @@ impl TimelineInnerState {
         self.reactions.clear();
         self.fully_read_event = None;
         self.event_should_update_fully_read_marker = false;
+        self.own_join_handled = false;
+    }
+
+    /// Remove the local echo at the given index, along with the day divider
+    /// that precedes it if no other item depends on it anymore.
+    pub(super) fn remove_local_echo(&mut self, idx: usize) {
+        self.items.remove(idx);
+
+        let idx = if idx > 0
+            && self.items[idx - 1].is_day_divider()
+            && self.items.get(idx).map_or(true, |item| item.is_day_divider())
+        {
+            self.items.remove(idx - 1);
+            idx - 1
+        } else {
+            idx
+        };
+
+        update_grouping_at(&mut self.items, idx);
     }
 
     #[instrument(skip_all)]
@@ -379,6 +450,50 @@ impl TimelineInnerState {
         );
     }
 
+    /// Insert the unread boundary after the latest event the user has read,
+    /// according to the fully-read marker and their own read receipts.
+    ///
+    /// Nothing is inserted if that event isn't in the timeline, or if there
+    /// is no remote event after it.
+    pub(super) fn insert_unread_boundary(&mut self, own_user_id: &UserId) {
+        let own_receipts = self.users_read_receipts.get(own_user_id);
+        let read_event_ids = self
+            .fully_read_event
+            .iter()
+            .chain(own_receipts.into_iter().flat_map(|r| r.values().map(|(id, _)| id)));
+
+        let Some(read_idx) = read_event_ids
+            .filter_map(|event_id| rfind_event_by_id(&self.items, event_id).map(|(idx, _)| idx))
+            .max()
+        else {
+            trace!("Latest read event not found, not inserting the unread boundary");
+            return;
+        };
+
+        if !self.items.iter().skip(read_idx + 1).any(|item| item.is_remote_event()) {
+            trace!("No unread messages, not inserting the unread boundary");
+            return;
+        }
+
+        // Keep the read marker right after the read event.
+        let mut idx = read_idx + 1;
+        if self.items[idx].is_read_marker() {
+            idx += 1;
+        }
+
+        self.items.insert(idx, TimelineItem::unread_boundary());
+    }
+
+    /// Insert the start of the timeline before the first item, unless it's
+    /// already there.
+    pub(super) fn insert_timeline_start(&mut self, predecessor: Option<OwnedRoomId>) {
+        if self.items.front().is_some_and(|item| item.is_timeline_start()) {
+            return;
+        }
+
+        self.items.push_front(TimelineItem::timeline_start(predecessor));
+    }
+
     pub(super) fn update_timeline_reaction(
         &mut self,
         own_user_id: &UserId,
@@ -526,3 +641,23 @@ impl Drop for TimelineInnerStateOwnedLockGuard {
         self.lock_release_ob.set(());
     }
 }
+
+/// Whether the given event is the current user joining the room, as opposed to
+/// a profile change of the current user.
+fn is_own_join(event: &AnySyncTimelineEvent, own_user_id: &UserId) -> bool {
+    let AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(SyncStateEvent::Original(ev))) =
+        event
+    else {
+        return false;
+    };
+
+    if ev.state_key != own_user_id {
+        return false;
+    }
+
+    let prev_details = ev.unsigned.prev_content.as_ref().map(|c| c.details());
+    matches!(
+        ev.content.membership_change(prev_details, &ev.sender, &ev.state_key),
+        MembershipChange::Joined | MembershipChange::InvitationAccepted
+    )
+}
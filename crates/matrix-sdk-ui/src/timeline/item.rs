@@ -14,6 +14,8 @@
 
 use std::{ops::Deref, sync::Arc};
 
+use ruma::OwnedRoomId;
+
 use super::{EventTimelineItem, VirtualTimelineItem};
 
 #[derive(Clone, Debug)]
@@ -80,6 +82,20 @@ impl TimelineItem {
         })
     }
 
+    pub(crate) fn unread_boundary() -> Arc<TimelineItem> {
+        Arc::new(Self {
+            kind: TimelineItemKind::Virtual(VirtualTimelineItem::UnreadBoundary),
+            internal_id: u64::MAX - 1,
+        })
+    }
+
+    pub(crate) fn timeline_start(predecessor: Option<OwnedRoomId>) -> Arc<TimelineItem> {
+        Arc::new(Self {
+            kind: TimelineItemKind::Virtual(VirtualTimelineItem::TimelineStart { predecessor }),
+            internal_id: u64::MAX - 2,
+        })
+    }
+
     pub(crate) fn is_local_echo(&self) -> bool {
         matches!(&self.kind, TimelineItemKind::Event(ev) if ev.is_local_echo())
     }
@@ -99,6 +115,14 @@ impl TimelineItem {
     pub(crate) fn is_read_marker(&self) -> bool {
         matches!(self.kind, TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker))
     }
+
+    pub(crate) fn is_unread_boundary(&self) -> bool {
+        matches!(self.kind, TimelineItemKind::Virtual(VirtualTimelineItem::UnreadBoundary))
+    }
+
+    pub(crate) fn is_timeline_start(&self) -> bool {
+        matches!(self.kind, TimelineItemKind::Virtual(VirtualTimelineItem::TimelineStart { .. }))
+    }
 }
 
 impl Deref for TimelineItem {
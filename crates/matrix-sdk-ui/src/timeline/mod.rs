@@ -25,10 +25,9 @@ use futures_core::Stream;
 use imbl::Vector;
 use matrix_sdk::{
     attachment::AttachmentConfig,
-    event_handler::EventHandlerHandle,
     executor::JoinHandle,
     room::{MessagesOptions, Receipts, Room},
-    Client, Result,
+    Result,
 };
 use matrix_sdk_base::RoomState;
 use mime::Mime;
@@ -51,9 +50,11 @@ use tokio::sync::mpsc::Sender;
 use tracing::{debug, error, info, instrument, warn};
 
 mod builder;
+mod debounce;
 mod event_handler;
 mod event_item;
 mod futures;
+mod grouping;
 mod inner;
 mod item;
 mod pagination;
@@ -61,28 +62,29 @@ mod polls;
 mod queue;
 mod reactions;
 mod read_receipts;
+mod send_handle;
 mod sliding_sync_ext;
 #[cfg(test)]
 mod tests;
-#[cfg(feature = "e2e-encryption")]
-mod to_device;
 mod traits;
 mod util;
 mod virtual_item;
 
 pub use self::{
-    builder::TimelineBuilder,
+    builder::{MembershipSince, TimelineBuilder},
     event_item::{
-        AnyOtherFullStateEventContent, BundledReactions, EncryptedMessage, EventItemOrigin,
-        EventSendState, EventTimelineItem, InReplyToDetails, MemberProfileChange, MembershipChange,
-        Message, OtherState, Profile, ReactionGroup, RepliedToEvent, RoomMembershipChange, Sticker,
-        TimelineDetails, TimelineItemContent,
+        AnyOtherFullStateEventContent, BundledReactions, CallEvent, CallEventKind,
+        EncryptedMessage, EventItemOrigin, EventSendState, EventTimelineItem, InReplyToDetails,
+        MemberProfileChange, MembershipChange, Message, OriginalContent, OtherState, Profile,
+        ReactionGroup, RepliedToEvent, RoomMembershipChange, Sticker, TimelineDetails,
+        TimelineItemContent,
     },
     futures::SendAttachment,
     item::{TimelineItem, TimelineItemKind},
     pagination::{PaginationOptions, PaginationOutcome},
     polls::PollResult,
     reactions::ReactionSenderData,
+    send_handle::SendHandle,
     sliding_sync_ext::SlidingSyncRoomExt,
     traits::RoomExt,
     virtual_item::VirtualTimelineItem,
@@ -155,6 +157,11 @@ impl Timeline {
     }
 
     /// Add more events to the start of the timeline.
+    ///
+    /// The pagination can be cancelled by dropping the returned future, e.g.
+    /// when the user leaves the room. In that case, the back-pagination status
+    /// goes back to [`BackPaginationStatus::Idle`] and the events of the
+    /// requests that were already processed stay in the timeline.
     #[instrument(skip_all, fields(room_id = ?self.room().room_id(), ?options))]
     pub async fn paginate_backwards(&self, mut options: PaginationOptions<'_>) -> Result<()> {
         let mut start_lock = self.start_token.lock().await;
@@ -166,6 +173,7 @@ impl Timeline {
         }
 
         self.back_pagination_status.set(BackPaginationStatus::Paginating);
+        let _status_guard = BackPaginationStatusGuard(&self.back_pagination_status);
 
         if start_lock.is_none() && options.wait_for_token {
             info!("No prev_batch token, waiting");
@@ -189,11 +197,7 @@ impl Timeline {
                     from,
                     limit: limit.into(),
                 }))
-                .await
-                .map_err(|e| {
-                    self.back_pagination_status.set(BackPaginationStatus::Idle);
-                    e
-                })?;
+                .await?;
 
             let process_events_result = async {
                 outcome.events_received = messages.chunk.len().try_into().ok()?;
@@ -219,6 +223,9 @@ impl Timeline {
                 break;
             }
 
+            // Remember the progress in case the pagination is cancelled.
+            *start_lock = from.clone();
+
             if process_events_result.is_none() {
                 error!("Received an excessive number of events, ending pagination (u16 overflow)");
                 break;
@@ -228,6 +235,8 @@ impl Timeline {
         let status = if from.is_some() {
             BackPaginationStatus::Idle
         } else {
+            let predecessor = self.room().create_content().and_then(|c| c.predecessor);
+            self.inner.insert_timeline_start(predecessor.map(|p| p.room_id)).await;
             BackPaginationStatus::TimelineStartReached
         };
         self.back_pagination_status.set(status);
@@ -239,6 +248,10 @@ impl Timeline {
     /// Retry decryption of previously un-decryptable events given a list of
     /// session IDs whose keys have been imported.
     ///
+    /// The timeline does this automatically when room keys are received,
+    /// unless it was disabled with
+    /// [`TimelineBuilder::retry_decryption_on_room_keys()`].
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -290,6 +303,35 @@ impl Timeline {
         Some(item.to_owned())
     }
 
+    /// Get the timeline item that comes right before the event with the given
+    /// ID, e.g. to navigate the timeline with the keyboard.
+    ///
+    /// If `skip_virtual` is set, virtual items like day dividers and the read
+    /// marker are skipped, and the closest event item is returned instead.
+    ///
+    /// Returns `None` if the event isn't in the timeline, or if there's no
+    /// such item before it. Both items are taken from the same snapshot of the
+    /// timeline, so this doesn't race against updates of the timeline.
+    pub async fn item_before(
+        &self,
+        event_id: &EventId,
+        skip_virtual: bool,
+    ) -> Option<Arc<TimelineItem>> {
+        self.inner.item_before(event_id, skip_virtual).await
+    }
+
+    /// Get the timeline item that comes right after the event with the given
+    /// ID.
+    ///
+    /// This is the counterpart of [`Timeline::item_before()`].
+    pub async fn item_after(
+        &self,
+        event_id: &EventId,
+        skip_virtual: bool,
+    ) -> Option<Arc<TimelineItem>> {
+        self.inner.item_after(event_id, skip_virtual).await
+    }
+
     /// Get the latest of the timeline's event items.
     pub async fn latest_event(&self) -> Option<EventTimelineItem> {
         self.inner.items().await.last()?.as_event().cloned()
@@ -299,6 +341,37 @@ impl Timeline {
     ///
     /// You can poll this stream to receive updates. See
     /// [`futures_util::StreamExt`] for a high-level API on top of [`Stream`].
+    ///
+    /// # Redactions and removals
+    ///
+    /// Redacting an event never removes its item from the timeline. Instead,
+    /// the item is replaced in place by a tombstone: a single
+    /// [`VectorDiff::Set`] at the same index, with the same
+    /// [`unique_id()`](TimelineItem::unique_id) and with its content replaced
+    /// by the redacted content. Items replying to the redacted event receive a
+    /// `Set` as well.
+    ///
+    /// [`VectorDiff::Remove`] is only emitted when an item really leaves the
+    /// timeline, which happens when:
+    ///
+    /// * a local echo is discarded or its sending is aborted,
+    /// * a local echo turns out to be a duplicate of its remote echo,
+    /// * an event that couldn't be decrypted is decrypted and turns out to be
+    ///   an event that isn't displayed,
+    /// * a day divider isn't needed anymore after one of the above,
+    /// * a limited sync response clears the timeline while local echoes are
+    ///   still pending, otherwise a single [`VectorDiff::Clear`] is emitted.
+    ///
+    /// # Edits
+    ///
+    /// Like redactions, an edit replaces the edited item in place with a
+    /// [`VectorDiff::Set`]. The reactions and the read receipts of the edited
+    /// item are kept on the new item.
+    ///
+    /// The edit event doesn't get an item of its own, so a read receipt
+    /// pointing to it can't be shown on any item. If the user already has a
+    /// read receipt on an item of the timeline, e.g. on the edited one, that
+    /// receipt wins and stays where it is.
     pub async fn subscribe(
         &self,
     ) -> (Vector<Arc<TimelineItem>>, impl Stream<Item = VectorDiff<Arc<TimelineItem>>>) {
@@ -320,6 +393,24 @@ impl Timeline {
         (items, stream)
     }
 
+    /// Get the current timeline items, and a debounced stream of changes.
+    ///
+    /// Like [`subscribe_batched`](Self::subscribe_batched), this stream yields
+    /// multiple diffs at once: starting from the first diff, the changes are
+    /// collected during `window` and yielded together. Within a batch,
+    /// consecutive [`VectorDiff::Set`]s on the same index are collapsed into
+    /// the latest one, which avoids redrawing an item for every one of a
+    /// series of quick edits. Applying the batches results in the same items
+    /// as applying the diffs of [`subscribe`](Self::subscribe).
+    pub async fn subscribe_debounced(
+        &self,
+        window: Duration,
+    ) -> (Vector<Arc<TimelineItem>>, impl Stream<Item = Vec<VectorDiff<Arc<TimelineItem>>>>) {
+        let (items, stream) = self.inner.subscribe_debounced(window).await;
+        let stream = TimelineStream::new(stream, self.drop_handle.clone());
+        (items, stream)
+    }
+
     /// Send a message to the room, and add it to the timeline as a local echo.
     ///
     /// For simplicity, this method doesn't currently allow custom message
@@ -331,6 +422,9 @@ impl Timeline {
     /// If sending the message fails, the local echo item will change its
     /// `send_state` to [`EventSendState::SendingFailed`].
     ///
+    /// The returned [`SendHandle`] can be used to get the transaction ID of
+    /// the local echo, to wait for the message to be sent, or to abort it.
+    ///
     /// # Arguments
     ///
     /// * `content` - The content of the message event.
@@ -350,12 +444,18 @@ impl Timeline {
     /// [`MessageLikeUnsigned`]: ruma::events::MessageLikeUnsigned
     /// [`SyncMessageLikeEvent`]: ruma::events::SyncMessageLikeEvent
     #[instrument(skip(self, content), fields(room_id = ?self.room().room_id()))]
-    pub async fn send(&self, content: AnyMessageLikeEventContent, txn_id: Option<&TransactionId>) {
+    pub async fn send(
+        &self,
+        content: AnyMessageLikeEventContent,
+        txn_id: Option<&TransactionId>,
+    ) -> SendHandle {
         let txn_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
         self.inner.handle_local_event(txn_id.clone(), content.clone()).await;
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
+        if self.msg_sender.send(LocalMessage { content, txn_id: txn_id.clone() }).await.is_err() {
             error!("Internal error: timeline message receiver is closed");
         }
+
+        SendHandle::new(self.inner.clone(), txn_id)
     }
 
     /// Toggle a reaction on an event
@@ -466,39 +566,9 @@ impl Timeline {
     /// * `txn_id` - The transaction ID of a local echo timeline item that has a
     ///   `send_state()` of `SendState::FailedToSend { .. }`
     pub async fn retry_send(&self, txn_id: &TransactionId) -> Result<(), Error> {
-        macro_rules! error_return {
-            ($msg:literal) => {{
-                error!($msg);
-                return Ok(());
-            }};
-        }
-
         let item = self.inner.prepare_retry(txn_id).await.ok_or(Error::RetryEventNotInTimeline)?;
-        let content = match item {
-            TimelineItemContent::Message(msg) => {
-                AnyMessageLikeEventContent::RoomMessage(msg.into())
-            }
-            TimelineItemContent::RedactedMessage => {
-                error_return!("Invalid state: attempting to retry a redacted message");
-            }
-            TimelineItemContent::Sticker(sticker) => {
-                AnyMessageLikeEventContent::Sticker(sticker.content)
-            }
-            TimelineItemContent::UnableToDecrypt(_) => {
-                error_return!("Invalid state: attempting to retry a UTD item");
-            }
-            TimelineItemContent::MembershipChange(_)
-            | TimelineItemContent::ProfileChange(_)
-            | TimelineItemContent::OtherState(_) => {
-                error_return!("Retrying state events is not currently supported");
-            }
-            TimelineItemContent::FailedToParseMessageLike { .. }
-            | TimelineItemContent::FailedToParseState { .. } => {
-                error_return!("Invalid state: attempting to retry a failed-to-parse item");
-            }
-            TimelineItemContent::Poll(poll_state) => {
-                AnyMessageLikeEventContent::UnstablePollStart(poll_state.into())
-            }
+        let Some(content) = content_for_retry(item) else {
+            return Ok(());
         };
 
         let txn_id = txn_id.to_owned();
@@ -509,6 +579,21 @@ impl Timeline {
         Ok(())
     }
 
+    /// Retry sending all the messages that previously failed to send, or
+    /// whose sending was cancelled because an earlier message failed to send.
+    ///
+    /// The messages are sent again in the order in which they appear in the
+    /// timeline, and their local echoes stay in place, with their
+    /// `send_state()` changing back to [`EventSendState::NotSentYet`].
+    pub async fn retry_failed_sends(&self) {
+        for (txn_id, content) in self.inner.prepare_retry_all().await {
+            if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
+                error!("Internal error: timeline message receiver is closed");
+                return;
+            }
+        }
+    }
+
     /// Discard a local echo for a message that failed to send.
     ///
     /// Returns whether the local echo with the given transaction ID was found.
@@ -668,19 +753,69 @@ impl Timeline {
     }
 }
 
+/// Get the content that should be sent again when retrying to send a local
+/// echo with the given content, if that's supported.
+fn content_for_retry(item: TimelineItemContent) -> Option<AnyMessageLikeEventContent> {
+    macro_rules! error_return {
+        ($msg:literal) => {{
+            error!($msg);
+            return None;
+        }};
+    }
+
+    Some(match item {
+        TimelineItemContent::Message(msg) => AnyMessageLikeEventContent::RoomMessage(msg.into()),
+        TimelineItemContent::RedactedMessage => {
+            error_return!("Invalid state: attempting to retry a redacted message");
+        }
+        TimelineItemContent::Sticker(sticker) => {
+            AnyMessageLikeEventContent::Sticker(sticker.content)
+        }
+        TimelineItemContent::UnableToDecrypt(_) => {
+            error_return!("Invalid state: attempting to retry a UTD item");
+        }
+        TimelineItemContent::MembershipChange(_)
+        | TimelineItemContent::ProfileChange(_)
+        | TimelineItemContent::OtherState(_) => {
+            error_return!("Retrying state events is not currently supported");
+        }
+        TimelineItemContent::FailedToParseMessageLike { .. }
+        | TimelineItemContent::FailedToParseState { .. } => {
+            error_return!("Invalid state: attempting to retry a failed-to-parse item");
+        }
+        TimelineItemContent::Poll(poll_state) => {
+            AnyMessageLikeEventContent::UnstablePollStart(poll_state.into())
+        }
+        TimelineItemContent::Call(_) => {
+            error_return!("Retrying call events is not currently supported");
+        }
+    })
+}
+
 #[derive(Debug)]
 struct TimelineDropHandle {
-    client: Client,
-    event_handler_handles: Vec<EventHandlerHandle>,
     room_update_join_handle: JoinHandle<()>,
+    room_keys_join_handle: Option<JoinHandle<()>>,
 }
 
 impl Drop for TimelineDropHandle {
     fn drop(&mut self) {
-        for handle in self.event_handler_handles.drain(..) {
-            self.client.remove_event_handler(handle);
-        }
         self.room_update_join_handle.abort();
+        if let Some(handle) = &self.room_keys_join_handle {
+            handle.abort();
+        }
+    }
+}
+
+/// Resets the back-pagination status to `Idle` if a back-pagination stops
+/// before reaching its end, because it failed or was cancelled.
+struct BackPaginationStatusGuard<'a>(&'a SharedObservable<BackPaginationStatus>);
+
+impl Drop for BackPaginationStatusGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.get() == BackPaginationStatus::Paginating {
+            self.0.set(BackPaginationStatus::Idle);
+        }
     }
 }
 
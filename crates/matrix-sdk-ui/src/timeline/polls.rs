@@ -82,6 +82,14 @@ impl PollState {
         }
     }
 
+    /// Whether the poll was ended by a poll end event.
+    ///
+    /// Responses sent after the poll was ended aren't counted in the
+    /// [results](Self::results).
+    pub fn has_ended(&self) -> bool {
+        self.end_event_timestamp.is_some()
+    }
+
     pub fn fallback_text(&self) -> Option<String> {
         self.start_event_content.text.clone()
     }
@@ -200,6 +200,11 @@ impl SendMessageTask {
         debug!("Spawning message-sending task");
         let txn_id = msg.txn_id.clone();
         let join_handle = spawn(async move {
+            if !timeline_inner.is_pending_local_echo(&msg.txn_id).await {
+                debug!("Local echo was aborted or discarded, not sending the message");
+                return Some(room);
+            }
+
             let result = room.send(msg.content, Some(&msg.txn_id)).await;
             let (room, send_state) = match result {
                 Ok(response) => (Some(room), EventSendState::Sent { event_id: response.event_id }),
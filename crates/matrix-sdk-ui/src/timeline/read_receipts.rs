@@ -72,9 +72,18 @@ impl TimelineInnerState {
                         continue;
                     }
 
+                    let is_own_user_id = user_id == own_user_id;
+
+                    // Private read receipts are only meant to be seen by their
+                    // sender, make sure that they never leak into the receipts
+                    // shown on the items if the server sends us one of them.
+                    if receipt_type == ReceiptType::ReadPrivate && !is_own_user_id {
+                        warn!(%user_id, "Ignoring private read receipt of another user");
+                        continue;
+                    }
+
                     let receipt_item_pos =
                         rfind_event_by_id(&self.items, &event_id).map(|(pos, _)| pos);
-                    let is_own_user_id = user_id == own_user_id;
                     let full_receipt = FullReceipt {
                         event_id: &event_id,
                         user_id: &user_id,
@@ -0,0 +1,62 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::{OwnedEventId, OwnedTransactionId, TransactionId};
+
+use super::inner::TimelineInner;
+
+/// A handle to a message that was sent through [`Timeline::send`].
+///
+/// It can be used to correlate the message with its local echo, to wait for
+/// it to be sent, or to abort sending it.
+///
+/// [`Timeline::send`]: super::Timeline::send
+#[derive(Debug)]
+pub struct SendHandle {
+    inner: TimelineInner,
+    txn_id: OwnedTransactionId,
+}
+
+impl SendHandle {
+    pub(super) fn new(inner: TimelineInner, txn_id: OwnedTransactionId) -> Self {
+        Self { inner, txn_id }
+    }
+
+    /// Get the transaction ID of the message.
+    ///
+    /// This is the same as the `transaction_id()` of its local echo.
+    pub fn txn_id(&self) -> &TransactionId {
+        &self.txn_id
+    }
+
+    /// Wait until the message has been sent, and get the event ID the server
+    /// assigned to it.
+    ///
+    /// Returns `None` if sending the message failed, or if the local echo was
+    /// removed from the timeline before the message was sent.
+    pub async fn event_id(&self) -> Option<OwnedEventId> {
+        self.inner.wait_for_event_id(&self.txn_id).await
+    }
+
+    /// Abort sending the message, and remove its local echo from the
+    /// timeline.
+    ///
+    /// Returns `false` if the message has already been sent, or if its local
+    /// echo isn't part of the timeline anymore. *Note:* if the request to
+    /// send the message is already in flight, there is no guarantee that it
+    /// doesn't reach the server.
+    pub async fn abort(&self) -> bool {
+        self.inner.abort_local_echo(&self.txn_id).await
+    }
+}
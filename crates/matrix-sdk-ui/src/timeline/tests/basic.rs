@@ -19,23 +19,24 @@ use matrix_sdk_test::async_test;
 use ruma::{
     assign,
     events::{
-        relation::{InReplyTo, Thread},
+        relation::{InReplyTo, Replacement, Thread},
         room::{
             member::{MembershipState, RedactedRoomMemberEventContent, RoomMemberEventContent},
             message::{MessageType, Relation, RoomMessageEventContent},
             name::RoomNameEventContent,
             topic::RedactedRoomTopicEventContent,
         },
-        FullStateEventContent,
+        AnyMessageLikeEventContent, FullStateEventContent,
     },
+    uint,
 };
 use serde_json::json;
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::{sync_timeline_event, TestTimeline, ALICE, BOB};
 use crate::timeline::{
-    event_item::AnyOtherFullStateEventContent, tests::CAROL, MembershipChange, TimelineDetails,
-    TimelineItemContent, TimelineItemKind, VirtualTimelineItem,
+    event_item::AnyOtherFullStateEventContent, tests::CAROL, CallEventKind, MembershipChange,
+    TimelineDetails, TimelineItemContent, TimelineItemKind, VirtualTimelineItem,
 };
 
 #[async_test]
@@ -59,6 +60,7 @@ async fn initial_events() {
     assert_matches!(&item.kind, TimelineItemKind::Virtual(VirtualTimelineItem::DayDivider(_)));
     let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
     assert_eq!(item.as_event().unwrap().sender(), *ALICE);
+    assert!(!item.as_event().unwrap().is_encrypted());
     let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
     assert_eq!(item.as_event().unwrap().sender(), *BOB);
 }
@@ -88,7 +90,22 @@ async fn sticker() {
         .await;
 
     let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
-    assert_matches!(item.content(), TimelineItemContent::Sticker(_));
+    let sticker = assert_matches!(item.content(), TimelineItemContent::Sticker(s) => s);
+    assert_eq!(sticker.body(), "Happy sticker");
+    assert_eq!(sticker.url(), "mxc://server.name/JWEIFJgwEIhweiWJE");
+    assert_eq!(sticker.info().width, Some(uint!(394)));
+    assert_eq!(sticker.info().height, Some(uint!(398)));
+    assert_eq!(sticker.info().mimetype.as_deref(), Some("image/jpeg"));
+
+    // Edits don't apply to stickers.
+    let edit = assign!(RoomMessageEventContent::text_plain("* Sad sticker"), {
+        relates_to: Some(Relation::Replacement(Replacement::new(
+            item.event_id().unwrap().to_owned(),
+            MessageType::text_plain("Sad sticker").into(),
+        ))),
+    });
+    timeline.handle_live_message_event(&ALICE, edit).await;
+    assert_pending!(stream);
 }
 
 #[async_test]
@@ -112,6 +129,7 @@ async fn room_member() {
         assert_matches!(item.content(), TimelineItemContent::MembershipChange(ev) => ev);
     assert_matches!(membership.content(), FullStateEventContent::Original { .. });
     assert_matches!(membership.change(), Some(MembershipChange::Invited));
+    assert_eq!(membership.display_name(), Some("Alice"));
 
     let mut second_room_member_content = RoomMemberEventContent::new(MembershipState::Join);
     second_room_member_content.displayname = Some("Alice".to_owned());
@@ -159,6 +177,7 @@ async fn room_member() {
         assert_matches!(item.content(), TimelineItemContent::MembershipChange(ev) => ev);
     assert_matches!(membership.content(), FullStateEventContent::Redacted(_));
     assert_matches!(membership.change(), None);
+    assert_eq!(membership.display_name(), None);
 }
 
 #[async_test]
@@ -391,3 +410,76 @@ async fn thread() {
     let replied_to_event = assert_matches!(&in_reply_to.event, TimelineDetails::Ready(msg) => msg);
     assert_eq!(replied_to_event.sender(), *ALICE);
 }
+
+#[async_test]
+async fn call_events() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline
+        .handle_live_custom_event(json!({
+            "content": {
+                "call_id": "1414213562373095",
+                "lifetime": 60000,
+                "offer": {
+                    "sdp": "v=0\r\no=- 6584580628695956864 2 IN IP4 127.0.0.1[...]",
+                    "type": "offer",
+                },
+                "version": 0,
+            },
+            "event_id": "$143273582443PhrSn:example.org",
+            "origin_server_ts": 143273582,
+            "sender": *ALICE,
+            "type": "m.call.invite",
+        }))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let call =
+        assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::Call(c) => c);
+    assert_eq!(call.call_id().as_str(), "1414213562373095");
+    assert_eq!(call.kind(), CallEventKind::Invite);
+
+    timeline
+        .handle_live_custom_event(json!({
+            "content": {
+                "call_id": "1414213562373095",
+                "version": 0,
+            },
+            "event_id": "$143273582443PhrSo:example.org",
+            "origin_server_ts": 143273583,
+            "sender": *ALICE,
+            "type": "m.call.hangup",
+        }))
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let call =
+        assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::Call(c) => c);
+    assert_eq!(call.call_id().as_str(), "1414213562373095");
+    assert_eq!(call.kind(), CallEventKind::Hangup);
+}
+
+#[async_test]
+async fn sender_power_level() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline.handle_live_message_event(*ALICE, RoomMessageEventContent::text_plain("A")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.sender_power_level(), Some(100));
+
+    timeline.handle_live_message_event(*BOB, RoomMessageEventContent::text_plain("B")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.sender_power_level(), None);
+
+    // The power level isn't looked up for local echoes.
+    timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("C"),
+        ))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.sender_power_level(), None);
+}
@@ -0,0 +1,81 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use eyeball_im::VectorDiff;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk_test::async_test;
+use ruma::{
+    assign,
+    events::{
+        relation::Replacement,
+        room::message::{self, MessageType, RoomMessageEventContent},
+    },
+};
+
+use super::{TestTimeline, ALICE};
+use crate::timeline::{debounce::compact_set_diffs, TimelineItemContent};
+
+#[test]
+fn compact_sets() {
+    let diffs = vec![
+        VectorDiff::Set { index: 0, value: 'a' },
+        VectorDiff::Set { index: 1, value: 'b' },
+        VectorDiff::Set { index: 0, value: 'c' },
+        VectorDiff::Insert { index: 0, value: 'd' },
+        VectorDiff::Set { index: 1, value: 'e' },
+        VectorDiff::Set { index: 1, value: 'f' },
+    ];
+
+    let compacted = compact_set_diffs(diffs);
+    assert_eq!(
+        compacted,
+        vec![
+            VectorDiff::Set { index: 0, value: 'c' },
+            VectorDiff::Set { index: 1, value: 'b' },
+            // The `Set`s after the `Insert` target another item.
+            VectorDiff::Insert { index: 0, value: 'd' },
+            VectorDiff::Set { index: 1, value: 'f' },
+        ]
+    );
+}
+
+#[async_test]
+async fn debounced_edits() {
+    let timeline = TestTimeline::new();
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("0")).await;
+    let (items, stream) = timeline.inner.subscribe_debounced(Duration::from_millis(100)).await;
+    pin_mut!(stream);
+
+    let event_id = items[1].as_event().unwrap().event_id().unwrap().to_owned();
+    for body in ["1", "2", "3"] {
+        let edit = assign!(RoomMessageEventContent::text_plain(format!("* {body}")), {
+            relates_to: Some(message::Relation::Replacement(Replacement::new(
+                event_id.clone(),
+                MessageType::text_plain(body).into(),
+            ))),
+        });
+        timeline.handle_live_message_event(&ALICE, edit).await;
+    }
+
+    let batch = stream.next().await.unwrap();
+    assert_eq!(batch.len(), 1);
+    let item = assert_matches!(&batch[0], VectorDiff::Set { index: 1, value } => value);
+    assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::Message(msg) => {
+        assert_eq!(msg.body(), "3");
+    });
+}
@@ -16,11 +16,20 @@ use std::{io, sync::Arc};
 
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
+use futures_util::join;
 use matrix_sdk::Error;
 use matrix_sdk_test::async_test;
 use ruma::{
     event_id,
-    events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    events::{
+        room::{
+            encrypted::{
+                EncryptedEventScheme, MegolmV1AesSha2ContentInit, RoomEncryptedEventContent,
+            },
+            message::RoomMessageEventContent,
+        },
+        AnyMessageLikeEventContent,
+    },
 };
 use serde_json::json;
 use stream_assert::assert_next_matches;
@@ -165,3 +174,204 @@ async fn remote_echo_new_position() {
     let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
     assert!(!item.as_event().unwrap().is_local_echo());
 }
+
+#[async_test]
+async fn wait_for_event_id() {
+    let timeline = TestTimeline::new();
+
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("echo"),
+        ))
+        .await;
+
+    let event_id = event_id!("$W6mZSLWMmfuQQ9jhZWeTxFIM");
+    let (received_event_id, _) = join!(timeline.inner.wait_for_event_id(&txn_id), async {
+        timeline
+            .inner
+            .update_event_send_state(
+                &txn_id,
+                EventSendState::Sent { event_id: event_id.to_owned() },
+            )
+            .await
+    });
+    assert_eq!(received_event_id.as_deref(), Some(event_id));
+
+    // A message that failed to send never gets an event ID.
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("failed echo"),
+        ))
+        .await;
+    let some_io_error = Error::Io(io::Error::new(io::ErrorKind::Other, "this is a test"));
+    timeline
+        .inner
+        .update_event_send_state(
+            &txn_id,
+            EventSendState::SendingFailed { error: Arc::new(some_io_error) },
+        )
+        .await;
+    assert_eq!(timeline.inner.wait_for_event_id(&txn_id).await, None);
+
+    // The remote echo can be received before the response to the send
+    // request, the event ID is taken from it then.
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("raced echo"),
+        ))
+        .await;
+    let (received_event_id, _) = join!(timeline.inner.wait_for_event_id(&txn_id), async {
+        timeline
+            .handle_live_custom_event(json!({
+                "content": {
+                    "body": "raced echo",
+                    "msgtype": "m.text",
+                },
+                "sender": &*ALICE,
+                "event_id": "$eeG0HA0FAZ37wP8kXlNkxx3I",
+                "origin_server_ts": 6,
+                "type": "m.room.message",
+                "unsigned": {
+                    "transaction_id": txn_id,
+                },
+            }))
+            .await
+    });
+    assert_eq!(received_event_id.as_deref(), Some(event_id!("$eeG0HA0FAZ37wP8kXlNkxx3I")));
+}
+
+#[async_test]
+async fn abort_local_echo() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("echo"),
+        ))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _local_echo = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(timeline.inner.is_pending_local_echo(&txn_id).await);
+
+    // An unsent message can be aborted, which removes its local echo and the
+    // day divider that was only there for it.
+    assert!(timeline.inner.abort_local_echo(&txn_id).await);
+    assert_next_matches!(stream, VectorDiff::Remove { index: 1 });
+    assert_next_matches!(stream, VectorDiff::Remove { index: 0 });
+    assert!(timeline.inner.items().await.is_empty());
+    assert!(!timeline.inner.is_pending_local_echo(&txn_id).await);
+    assert!(!timeline.inner.abort_local_echo(&txn_id).await);
+
+    // A message that has already been sent can't be aborted anymore.
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("sent echo"),
+        ))
+        .await;
+    timeline
+        .inner
+        .update_event_send_state(
+            &txn_id,
+            EventSendState::Sent { event_id: event_id!("$W6mZSLWMmfuQQ9jhZWeTxFIM").to_owned() },
+        )
+        .await;
+    assert!(!timeline.inner.abort_local_echo(&txn_id).await);
+}
+
+#[async_test]
+async fn retry_all_failed_sends() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let first_txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("first"),
+        ))
+        .await;
+    let second_txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("second"),
+        ))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _first = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _second = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    // The first message fails to send, which cancels the second one.
+    let some_io_error = Error::Io(io::Error::new(io::ErrorKind::Other, "this is a test"));
+    timeline
+        .inner
+        .update_event_send_state(
+            &first_txn_id,
+            EventSendState::SendingFailed { error: Arc::new(some_io_error) },
+        )
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert_matches!(
+        item.as_event().unwrap().send_state(),
+        Some(EventSendState::SendingFailed { .. })
+    );
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 2, value } => value);
+    assert_matches!(item.as_event().unwrap().send_state(), Some(EventSendState::Cancelled));
+
+    // Both messages are retried in order, and their local echoes stay where
+    // they are.
+    let to_retry = timeline.inner.prepare_retry_all().await;
+    let retried_txn_ids: Vec<_> = to_retry.iter().map(|(txn_id, _)| txn_id.clone()).collect();
+    assert_eq!(retried_txn_ids, vec![first_txn_id, second_txn_id]);
+
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert_matches!(item.as_event().unwrap().send_state(), Some(EventSendState::NotSentYet));
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 2, value } => value);
+    assert_matches!(item.as_event().unwrap().send_state(), Some(EventSendState::NotSentYet));
+
+    // There is nothing left to retry.
+    assert!(timeline.inner.prepare_retry_all().await.is_empty());
+}
+
+#[async_test]
+async fn retry_all_skips_unsupported_content() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let encrypted = RoomEncryptedEventContent::new(
+        EncryptedEventScheme::MegolmV1AesSha2(
+            MegolmV1AesSha2ContentInit {
+                ciphertext: "ciphertext".into(),
+                sender_key: "sender_key".into(),
+                device_id: "DEVICEID".into(),
+                session_id: "session_id".into(),
+            }
+            .into(),
+        ),
+        None,
+    );
+    let txn_id =
+        timeline.handle_local_event(AnyMessageLikeEventContent::RoomEncrypted(encrypted)).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    let some_io_error = Error::Io(io::Error::new(io::ErrorKind::Other, "this is a test"));
+    timeline
+        .inner
+        .update_event_send_state(
+            &txn_id,
+            EventSendState::SendingFailed { error: Arc::new(some_io_error) },
+        )
+        .await;
+    let _item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+
+    // The content of the local echo can't be sent again, so it isn't marked
+    // as pending.
+    assert!(timeline.inner.prepare_retry_all().await.is_empty());
+    let items = timeline.inner.items().await;
+    assert_matches!(
+        items[1].as_event().unwrap().send_state(),
+        Some(EventSendState::SendingFailed { .. })
+    );
+}
@@ -18,7 +18,9 @@ use matrix_sdk_test::async_test;
 use ruma::{
     assign,
     events::{
-        relation::Replacement,
+        reaction::ReactionEventContent,
+        receipt::{ReceiptThread, ReceiptType},
+        relation::{Annotation, Replacement},
         room::message::{
             self, MessageType, RedactedRoomMessageEventContent, RoomMessageEventContent,
         },
@@ -27,10 +29,10 @@ use ruma::{
     server_name, EventId,
 };
 use serde_json::json;
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
-use super::{TestTimeline, ALICE};
-use crate::timeline::TimelineItemContent;
+use super::{TestTimeline, ALICE, BOB, CAROL};
+use crate::timeline::{inner::TimelineInnerSettings, TimelineItemContent};
 
 #[async_test]
 async fn live_redacted() {
@@ -106,6 +108,99 @@ async fn live_sanitized() {
     assert_eq!(text.formatted.as_ref().unwrap().body, " <strong>better</strong> message");
 }
 
+#[async_test]
+async fn edit_history() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("first")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let original = item.as_event().unwrap().to_owned();
+    assert!(original.edit_history().is_empty());
+    let original_event_id = original.event_id().unwrap();
+
+    for body in ["second", "third"] {
+        let edit = assign!(RoomMessageEventContent::text_plain(format!("* {body}")), {
+            relates_to: Some(message::Relation::Replacement(Replacement::new(
+                original_event_id.to_owned(),
+                MessageType::text_plain(body).into(),
+            ))),
+        });
+        timeline.handle_live_message_event(&ALICE, edit).await;
+    }
+
+    let _first_edit = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let event = item.as_event().unwrap();
+    assert_matches!(event.content(), TimelineItemContent::Message(msg) => {
+        assert_eq!(msg.body(), "third");
+    });
+
+    let history = event.edit_history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].content().unwrap().body(), "first");
+    assert_eq!(history[0].timestamp(), original.timestamp());
+    assert_eq!(history[1].content().unwrap().body(), "second");
+    assert!(history[1].timestamp() > history[0].timestamp());
+
+    timeline.handle_live_redaction(&ALICE, original_event_id).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let event = item.as_event().unwrap();
+    assert_matches!(event.content(), TimelineItemContent::RedactedMessage);
+
+    // The edits are still recorded, but without their content.
+    let redacted_history = event.edit_history();
+    assert_eq!(redacted_history.len(), 2);
+    assert!(redacted_history.iter().all(|o| o.content().is_none()));
+    assert_eq!(redacted_history[1].timestamp(), history[1].timestamp());
+}
+
+#[async_test]
+async fn edits_as_separate_items() {
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        edits_as_separate_items: true,
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("first")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let original_event_id = item.as_event().unwrap().event_id().unwrap().to_owned();
+
+    let edit = assign!(RoomMessageEventContent::text_plain("* second"), {
+        relates_to: Some(message::Relation::Replacement(Replacement::new(
+            original_event_id.clone(),
+            MessageType::text_plain("second").into(),
+        ))),
+    });
+    timeline.handle_live_message_event(&ALICE, edit).await;
+
+    // The original item keeps its content, but is marked as edited.
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let event = item.as_event().unwrap();
+    assert_matches!(event.content(), TimelineItemContent::Message(msg) => {
+        assert_eq!(msg.body(), "first");
+        assert!(msg.is_edited());
+        assert!(msg.replaces().is_none());
+    });
+    assert!(event.edit_history().is_empty());
+
+    // The edit is added as a new item that points to the original event.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::Message(msg) => {
+        assert_eq!(msg.body(), "second");
+        assert!(!msg.is_edited());
+        assert_eq!(msg.replaces(), Some(&*original_event_id));
+    });
+
+    assert_pending!(stream);
+}
+
 #[async_test]
 async fn aggregated_sanitized() {
     let timeline = TestTimeline::new();
@@ -160,3 +255,59 @@ async fn aggregated_sanitized() {
     assert_eq!(text.body, "!!edited!! **better** message");
     assert_eq!(text.formatted.as_ref().unwrap().body, " <strong>better</strong> message");
 }
+
+#[async_test]
+async fn edit_keeps_read_receipts_and_reactions() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { track_read_receipts: true, ..Default::default() });
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("original")).await;
+    let items = timeline.inner.items().await;
+    let original_event_id = items[1].as_event().unwrap().event_id().unwrap().to_owned();
+
+    timeline
+        .handle_read_receipts([(
+            original_event_id.clone(),
+            ReceiptType::Read,
+            CAROL.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+    let annotation = Annotation::new(original_event_id.clone(), "👍".to_owned());
+    timeline.handle_live_message_event(&CAROL, ReactionEventContent::new(annotation)).await;
+
+    let edit_event_id = EventId::new(server_name!("dummy.server"));
+    let edit = assign!(RoomMessageEventContent::text_plain(" * edited"), {
+        relates_to: Some(message::Relation::Replacement(Replacement::new(
+            original_event_id.clone(),
+            MessageType::text_plain("edited").into(),
+        ))),
+    });
+    let edit = timeline.make_message_event_with_id(&BOB, edit, edit_event_id.clone());
+    timeline.handle_live_event(Raw::new(&edit).unwrap().cast()).await;
+
+    // A read receipt on the edit event doesn't move the one on the edited item.
+    timeline
+        .handle_read_receipts([(
+            edit_event_id,
+            ReceiptType::Read,
+            CAROL.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 2);
+    let event = items[1].as_event().unwrap();
+    assert_eq!(event.event_id(), Some(&*original_event_id));
+
+    let message = assert_matches!(event.content(), TimelineItemContent::Message(msg) => msg);
+    assert!(message.is_edited());
+    assert_eq!(message.body(), "edited");
+
+    assert_eq!(event.read_receipts().len(), 2);
+    assert!(event.read_receipts().get(*BOB).is_some());
+    assert!(event.read_receipts().get(*CAROL).is_some());
+    assert_eq!(event.reactions().len(), 1);
+    assert!(event.reactions().contains_key("👍"));
+}
@@ -90,6 +90,10 @@ async fn retry_message_decryption() {
         ) => session_id
     );
     assert_eq!(session_id, SESSION_ID);
+    assert!(event.is_encrypted());
+
+    let source = event.original_json().unwrap();
+    assert_eq!(source.get_field::<String>("type").unwrap().as_deref(), Some("m.room.encrypted"));
 
     let own_user_id = user_id!("@example:morheus.localhost");
     let exported_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
@@ -111,9 +115,14 @@ async fn retry_message_decryption() {
     let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
     let event = item.as_event().unwrap();
     assert_matches!(event.encryption_info(), Some(_));
+    assert!(event.is_encrypted());
     let text = assert_matches!(event.content(), TimelineItemContent::Message(msg) => msg.body());
     assert_eq!(text, "It's a secret to everybody");
     assert!(!event.is_highlighted());
+
+    let source = event.original_json().unwrap();
+    assert_eq!(source.get_field::<String>("type").unwrap().as_deref(), Some("m.room.message"));
+    assert!(!source.json().get().contains("ciphertext"));
 }
 
 #[async_test]
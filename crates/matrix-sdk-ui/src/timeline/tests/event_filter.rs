@@ -36,7 +36,9 @@ use serde_json::json;
 use stream_assert::assert_next_matches;
 
 use super::{TestTimeline, ALICE, BOB};
-use crate::timeline::{inner::TimelineInnerSettings, TimelineItemContent};
+use crate::timeline::{
+    inner::TimelineInnerSettings, MembershipChange, MembershipSince, TimelineItemContent,
+};
 
 #[async_test]
 async fn default_filter() {
@@ -208,3 +210,114 @@ async fn hide_failed_to_parse() {
 
     assert_eq!(timeline.inner.items().await.len(), 0);
 }
+
+#[async_test]
+async fn since_own_join() {
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        since_own_membership: Some(MembershipSince::Join),
+        ..Default::default()
+    });
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("before")).await;
+    assert_eq!(timeline.event_summaries().await, ["before"]);
+
+    let join = RoomMemberEventContent::new(MembershipState::Join);
+    timeline.handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), join, None).await;
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("after")).await;
+    assert_eq!(timeline.event_summaries().await, ["join", "after"]);
+
+    // A profile change isn't a new join.
+    let profile_change = assign!(RoomMemberEventContent::new(MembershipState::Join), {
+        displayname: Some("Alice".to_owned()),
+    });
+    let prev_content = RoomMemberEventContent::new(MembershipState::Join);
+    timeline
+        .handle_live_state_event_with_state_key(
+            &ALICE,
+            ALICE.to_owned(),
+            profile_change,
+            Some(prev_content),
+        )
+        .await;
+    assert_eq!(timeline.event_summaries().await.len(), 3);
+
+    // Events from before the join aren't added when paginating backwards.
+    timeline
+        .handle_back_paginated_custom_event(json!({
+            "content": { "msgtype": "m.text", "body": "way before" },
+            "event_id": "$way_before",
+            "origin_server_ts": 0,
+            "sender": *BOB,
+            "type": "m.room.message",
+        }))
+        .await;
+    assert_eq!(timeline.event_summaries().await.len(), 3);
+
+    // Only the latest join counts.
+    let leave = RoomMemberEventContent::new(MembershipState::Leave);
+    let prev_content = RoomMemberEventContent::new(MembershipState::Join);
+    timeline
+        .handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), leave, Some(prev_content))
+        .await;
+    let join = RoomMemberEventContent::new(MembershipState::Join);
+    let prev_content = RoomMemberEventContent::new(MembershipState::Leave);
+    timeline
+        .handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), join, Some(prev_content))
+        .await;
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("again")).await;
+    assert_eq!(timeline.event_summaries().await, ["join", "again"]);
+}
+
+#[async_test]
+async fn since_own_join_back_pagination() {
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        since_own_membership: Some(MembershipSince::Join),
+        ..Default::default()
+    });
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("after")).await;
+
+    timeline
+        .handle_back_paginated_custom_event(json!({
+            "content": { "membership": "join" },
+            "event_id": "$join",
+            "origin_server_ts": 1,
+            "sender": *ALICE,
+            "state_key": *ALICE,
+            "type": "m.room.member",
+        }))
+        .await;
+    timeline
+        .handle_back_paginated_custom_event(json!({
+            "content": { "msgtype": "m.text", "body": "before" },
+            "event_id": "$before",
+            "origin_server_ts": 0,
+            "sender": *BOB,
+            "type": "m.room.message",
+        }))
+        .await;
+
+    assert_eq!(timeline.event_summaries().await, ["join", "after"]);
+}
+
+impl TestTimeline {
+    /// The body of the messages in the timeline, with the joins as `join` and
+    /// other membership changes as `member`.
+    async fn event_summaries(&self) -> Vec<String> {
+        self.inner
+            .items()
+            .await
+            .iter()
+            .filter_map(|item| item.as_event())
+            .map(|event| match event.content() {
+                TimelineItemContent::Message(message) => message.body().to_owned(),
+                TimelineItemContent::MembershipChange(change) => match change.change() {
+                    Some(MembershipChange::Joined) => "join".to_owned(),
+                    _ => "member".to_owned(),
+                },
+                TimelineItemContent::ProfileChange(_) => "profile".to_owned(),
+                _ => "other".to_owned(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use eyeball_im::VectorDiff;
+use matrix_sdk_test::async_test;
+use ruma::{
+    events::{
+        room::{message::RoomMessageEventContent, name::RoomNameEventContent},
+        AnyMessageLikeEventContent,
+    },
+    MilliSecondsSinceUnixEpoch,
+};
+use stream_assert::{assert_next_matches, assert_pending};
+
+use super::{TestTimeline, ALICE, BOB};
+use crate::timeline::grouping::GROUPING_WINDOW;
+
+#[async_test]
+async fn consecutive_messages() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("B")).await;
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("C")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.as_event().unwrap().is_grouped_with_previous());
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous(), "Other sender");
+
+    // State events break groups.
+    timeline
+        .handle_live_state_event(&BOB, RoomNameEventContent::new(Some("Name".to_owned())), None)
+        .await;
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("D")).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous(), "State event");
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous(), "After state event");
+
+    // Messages that are too far apart are not grouped.
+    timeline.set_next_ts(10 + GROUPING_WINDOW.as_millis() as u64);
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("E")).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous(), "Outside of the window");
+
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn insert_in_the_middle() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("B")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+
+    // An earlier message from the same sender is added before the existing one,
+    // which now needs to be grouped with it.
+    let event = timeline.make_message_event(&ALICE, RoomMessageEventContent::text_plain("A"));
+    timeline.handle_back_paginated_custom_event(event).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::Insert { index: 1, value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 2, value } => value);
+    assert!(item.as_event().unwrap().is_grouped_with_previous());
+
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn only_neighbours_of_changed_items_are_regrouped() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    // Local echoes use the current time, make remote events fit in with them.
+    timeline.set_next_ts(MilliSecondsSinceUnixEpoch::now().get().into());
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("A")).await;
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("B"),
+        ))
+        .await;
+    timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("C"),
+        ))
+        .await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _a = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.as_event().unwrap().is_grouped_with_previous());
+
+    // Once the local echo in the middle is gone, the next one follows a
+    // message of another sender.
+    assert!(timeline.inner.discard_local_echo(&txn_id).await);
+
+    assert_next_matches!(stream, VectorDiff::Remove { index: 2 });
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 2, value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+
+    // A remote event is added before the pending local echo, which is now
+    // grouped with it.
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("D")).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::Insert { index: 2, value } => value);
+    assert!(!item.as_event().unwrap().is_grouped_with_previous());
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 3, value } => value);
+    assert!(item.as_event().unwrap().is_grouped_with_previous());
+
+    assert_pending!(stream);
+}
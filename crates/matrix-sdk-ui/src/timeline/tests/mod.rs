@@ -58,11 +58,13 @@ use super::{
 };
 
 mod basic;
+mod debounce;
 mod echo;
 mod edit;
 #[cfg(feature = "e2e-encryption")]
 mod encryption;
 mod event_filter;
+mod grouping;
 mod invalid;
 mod polls;
 mod reaction_group;
@@ -420,6 +422,10 @@ impl RoomDataProvider for TestRoomDataProvider {
         None
     }
 
+    async fn profile_and_power_level(&self, user_id: &UserId) -> (Option<Profile>, Option<i64>) {
+        (None, (user_id == *ALICE).then_some(100))
+    }
+
     async fn read_receipts_for_event(&self, _event_id: &EventId) -> IndexMap<OwnedUserId, Receipt> {
         IndexMap::new()
     }
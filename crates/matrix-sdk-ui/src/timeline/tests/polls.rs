@@ -1,3 +1,4 @@
+use eyeball_im::VectorDiff;
 use matrix_sdk_test::async_test;
 use ruma::{
     events::{
@@ -13,6 +14,7 @@ use ruma::{
     serde::Raw,
     server_name, EventId, OwnedEventId, UserId,
 };
+use stream_assert::{assert_next_matches, assert_pending};
 
 use crate::timeline::{
     polls::PollState,
@@ -106,6 +108,31 @@ async fn votes_after_end_are_discarded() {
     }
 }
 
+#[async_test]
+async fn responses_and_end_update_the_poll_item() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.send_poll_start(&ALICE, fakes::poll_a()).await;
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let poll = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let poll_id = poll.as_event().unwrap().event_id().unwrap().to_owned();
+    assert!(!poll.as_event().unwrap().clone().poll_state().has_ended());
+
+    // Responses don't create timeline items, they update the poll.
+    timeline.send_poll_response(&BOB, vec!["id_up"], &poll_id).await;
+    let poll = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let results = poll.as_event().unwrap().clone().poll_state().results();
+    assert_eq!(results.votes["id_up"], vec![BOB.to_string()]);
+
+    timeline.send_poll_end(&ALICE, "ENDED", &poll_id).await;
+    let poll = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert!(poll.as_event().unwrap().clone().poll_state().has_ended());
+
+    assert_pending!(stream);
+    assert_eq!(timeline.event_items().await.len(), 1);
+}
+
 #[async_test]
 async fn multiple_end_events_are_discarded() {
     let timeline = TestTimeline::new();
@@ -18,7 +18,7 @@ use ruma::events::{
     receipt::{ReceiptThread, ReceiptType},
     room::message::RoomMessageEventContent,
 };
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::{TestTimeline, ALICE, BOB};
 use crate::timeline::inner::TimelineInnerSettings;
@@ -82,3 +82,60 @@ async fn read_receipts_updates() {
     assert_eq!(event_d.read_receipts().len(), 1);
     assert!(event_d.read_receipts().get(*BOB).is_some());
 }
+
+#[async_test]
+async fn private_read_receipts() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { track_read_receipts: true, ..Default::default() });
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(*BOB, RoomMessageEventContent::text_plain("A")).await;
+    timeline.handle_live_message_event(*ALICE, RoomMessageEventContent::text_plain("B")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _item_a = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item_b = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event_b = item_b.as_event().unwrap();
+    assert!(event_b.read_receipts().is_empty());
+
+    // Our own private read receipt is not shown on the items.
+    timeline
+        .handle_read_receipts([(
+            event_b.event_id().unwrap().to_owned(),
+            ReceiptType::ReadPrivate,
+            ALICE.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+    assert_pending!(stream);
+
+    // The private read receipt of another user is ignored.
+    timeline
+        .handle_read_receipts([(
+            event_b.event_id().unwrap().to_owned(),
+            ReceiptType::ReadPrivate,
+            BOB.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+    assert_pending!(stream);
+
+    // The public read receipt of another user is shown.
+    timeline
+        .handle_read_receipts([(
+            event_b.event_id().unwrap().to_owned(),
+            ReceiptType::Read,
+            BOB.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+
+    let item_a = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let event_a = item_a.as_event().unwrap();
+    assert!(event_a.read_receipts().is_empty());
+
+    let item_b = assert_next_matches!(stream, VectorDiff::Set { index: 2, value } => value);
+    let event_b = item_b.as_event().unwrap();
+    assert_eq!(event_b.read_receipts().len(), 1);
+    assert!(event_b.read_receipts().get(*BOB).is_some());
+}
@@ -32,7 +32,7 @@ use ruma::{
     owned_room_id,
 };
 use serde_json::json;
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::{sync_timeline_event, TestTimeline, ALICE, BOB};
 use crate::timeline::{AnyOtherFullStateEventContent, TimelineDetails, TimelineItemContent};
@@ -239,3 +239,25 @@ async fn receive_unredacted() {
     assert!(items[1].as_event().unwrap().content.is_redacted());
     assert!(items[2].as_event().unwrap().content.is_redacted());
 }
+
+#[async_test]
+async fn redaction_replaces_item_in_place() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("B")).await;
+
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let first = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _second = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    let event_id = first.as_event().unwrap().event_id().unwrap();
+    timeline.handle_live_redaction(&ALICE, event_id).await;
+
+    // The redacted item is replaced by a tombstone, it isn't removed.
+    let redacted = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert_eq!(redacted.unique_id(), first.unique_id());
+    assert_matches!(redacted.as_event().unwrap().content(), TimelineItemContent::RedactedMessage);
+    assert_pending!(stream);
+}
@@ -19,11 +19,12 @@ use matrix_sdk_test::async_test;
 use ruma::{
     event_id,
     events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    owned_room_id,
 };
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::{TestTimeline, ALICE, BOB};
-use crate::timeline::{TimelineItemKind, VirtualTimelineItem};
+use crate::timeline::{inner::TimelineInnerSettings, TimelineItemKind, VirtualTimelineItem};
 
 #[async_test]
 async fn day_divider() {
@@ -155,3 +156,158 @@ async fn update_read_marker() {
     let marker = assert_next_matches!(stream, VectorDiff::Insert { index: 4, value } => value);
     assert_matches!(marker.kind, TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker));
 }
+
+#[async_test]
+async fn unread_boundary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let first_event_id = item.as_event().unwrap().event_id().unwrap().to_owned();
+
+    timeline.inner.set_fully_read_event(first_event_id).await;
+
+    // There are no unread messages, so the boundary isn't added.
+    timeline.inner.insert_unread_boundary().await;
+    assert_pending!(stream);
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("B")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let second_event_id = item.as_event().unwrap().event_id().unwrap().to_owned();
+    let marker = assert_next_matches!(stream, VectorDiff::Insert { index: 2, value } => value);
+    assert_matches!(marker.kind, TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker));
+
+    // The boundary is added after the read marker.
+    timeline.inner.insert_unread_boundary().await;
+    let boundary = assert_next_matches!(stream, VectorDiff::Insert { index: 3, value } => value);
+    assert_matches!(boundary.kind, TimelineItemKind::Virtual(VirtualTimelineItem::UnreadBoundary));
+
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("C")).await;
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    // The read marker moves, but the boundary stays where it was.
+    timeline.inner.set_fully_read_event(second_event_id).await;
+    assert_next_matches!(stream, VectorDiff::Remove { index: 2 });
+    let marker = assert_next_matches!(stream, VectorDiff::Insert { index: 4, value } => value);
+    assert_matches!(marker.kind, TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker));
+    assert_pending!(stream);
+
+    let items = timeline.inner.items().await;
+    assert!(items[2].is_unread_boundary());
+}
+
+#[async_test]
+async fn timeline_start() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { add_timeline_start: true, ..Default::default() });
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    let predecessor = owned_room_id!("!old:example.org");
+    timeline.inner.insert_timeline_start(Some(predecessor.clone())).await;
+    let start = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
+    assert_matches!(
+        &start.kind,
+        TimelineItemKind::Virtual(VirtualTimelineItem::TimelineStart { predecessor: Some(id) }) => {
+            assert_eq!(*id, predecessor);
+        }
+    );
+
+    // The start of the timeline is only added once.
+    timeline.inner.insert_timeline_start(None).await;
+    assert_pending!(stream);
+
+    // Items after the start of the timeline are grouped as usual.
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("B")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.as_event().unwrap().is_grouped_with_previous());
+}
+
+#[async_test]
+async fn timeline_start_disabled() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    let _day_divider = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let _item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    timeline.inner.insert_timeline_start(None).await;
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn no_day_dividers() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { add_day_dividers: false, ..Default::default() });
+    let mut stream = timeline.subscribe().await;
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("A")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+
+    // A message on another day doesn't get a day divider either.
+    timeline.set_next_ts(24 * 60 * 60 * 1000);
+    timeline.handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("B")).await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+
+    timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("C"),
+        ))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+
+    // Back-paginated events are added at the very start.
+    timeline.set_next_ts(0);
+    let event = timeline.make_message_event(&BOB, RoomMessageEventContent::text_plain("Z"));
+    timeline.handle_back_paginated_custom_event(event).await;
+    let item = assert_next_matches!(stream, VectorDiff::Insert { index: 0, value } => value);
+    item.as_event().unwrap();
+
+    assert_pending!(stream);
+    assert!(!timeline.inner.items().await.iter().any(|item| item.is_day_divider()));
+}
+
+#[async_test]
+async fn adjacent_items() {
+    let timeline = TestTimeline::new();
+
+    timeline
+        .handle_live_message_event(*ALICE, RoomMessageEventContent::text_plain("First day"))
+        .await;
+    timeline.set_next_ts(24 * 60 * 60 * 1000);
+    timeline.handle_live_message_event(*BOB, RoomMessageEventContent::text_plain("Next day")).await;
+
+    // day divider, first message, day divider, second message
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 4);
+    let first = items[1].as_event().unwrap().event_id().unwrap().to_owned();
+    let second = items[3].as_event().unwrap().event_id().unwrap().to_owned();
+
+    let item = timeline.inner.item_before(&second, false).await.unwrap();
+    assert_matches!(item.as_virtual(), Some(VirtualTimelineItem::DayDivider(_)));
+    let item = timeline.inner.item_before(&second, true).await.unwrap();
+    assert_eq!(item.as_event().unwrap().event_id(), Some(&*first));
+
+    let item = timeline.inner.item_after(&first, false).await.unwrap();
+    assert_matches!(item.as_virtual(), Some(VirtualTimelineItem::DayDivider(_)));
+    let item = timeline.inner.item_after(&first, true).await.unwrap();
+    assert_eq!(item.as_event().unwrap().event_id(), Some(&*second));
+
+    // Only the day divider comes before the first message.
+    assert!(timeline.inner.item_before(&first, false).await.is_some());
+    assert!(timeline.inner.item_before(&first, true).await.is_none());
+    assert!(timeline.inner.item_after(&second, false).await.is_none());
+
+    let unknown = event_id!("$unknown");
+    assert!(timeline.inner.item_before(unknown, false).await.is_none());
+    assert!(timeline.inner.item_after(unknown, false).await.is_none());
+}
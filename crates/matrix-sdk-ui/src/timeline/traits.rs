@@ -67,6 +67,9 @@ pub(super) trait RoomDataProvider: Clone + Send + Sync + 'static {
     fn own_user_id(&self) -> &UserId;
     fn room_version(&self) -> RoomVersionId;
     async fn profile(&self, user_id: &UserId) -> Option<Profile>;
+    /// Get both the profile and the power level of the given user, looking
+    /// up their membership only once.
+    async fn profile_and_power_level(&self, user_id: &UserId) -> (Option<Profile>, Option<i64>);
     async fn read_receipts_for_event(&self, event_id: &EventId) -> IndexMap<OwnedUserId, Receipt>;
     async fn push_rules_and_context(&self) -> Option<(Ruleset, PushConditionRoomCtx)>;
 }
@@ -85,21 +88,28 @@ impl RoomDataProvider for Room {
     }
 
     async fn profile(&self, user_id: &UserId) -> Option<Profile> {
+        self.profile_and_power_level(user_id).await.0
+    }
+
+    async fn profile_and_power_level(&self, user_id: &UserId) -> (Option<Profile>, Option<i64>) {
         match self.get_member_no_sync(user_id).await {
-            Ok(Some(member)) => Some(Profile {
-                display_name: member.display_name().map(ToOwned::to_owned),
-                display_name_ambiguous: member.name_ambiguous(),
-                avatar_url: member.avatar_url().map(ToOwned::to_owned),
-            }),
-            Ok(None) if self.are_members_synced() => Some(Profile {
-                display_name: None,
-                display_name_ambiguous: false,
-                avatar_url: None,
-            }),
-            Ok(None) => None,
+            Ok(Some(member)) => {
+                let profile = Profile {
+                    display_name: member.display_name().map(ToOwned::to_owned),
+                    display_name_ambiguous: member.name_ambiguous(),
+                    avatar_url: member.avatar_url().map(ToOwned::to_owned),
+                };
+                (Some(profile), Some(member.power_level()))
+            }
+            Ok(None) if self.are_members_synced() => {
+                let profile =
+                    Profile { display_name: None, display_name_ambiguous: false, avatar_url: None };
+                (Some(profile), None)
+            }
+            Ok(None) => (None, None),
             Err(e) => {
                 error!(%user_id, "Failed to fetch room member information: {e}");
-                None
+                (None, None)
             }
         }
     }
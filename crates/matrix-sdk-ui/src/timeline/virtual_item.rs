@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use ruma::MilliSecondsSinceUnixEpoch;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId};
 
 /// A [`TimelineItem`](super::TimelineItem) that doesn't correspond to an event.
 #[derive(Clone, Debug)]
@@ -25,4 +25,28 @@ pub enum VirtualTimelineItem {
 
     /// The user's own read marker.
     ReadMarker,
+
+    /// The boundary before the first message that was unread when the
+    /// timeline was created.
+    ///
+    /// Unlike the read marker, this item doesn't move when the user reads
+    /// more messages. It is only added if the timeline was built with
+    /// [`TimelineBuilder::add_unread_boundary()`].
+    ///
+    /// [`TimelineBuilder::add_unread_boundary()`]: super::TimelineBuilder::add_unread_boundary
+    UnreadBoundary,
+
+    /// The start of the timeline, i.e. the creation of the room.
+    ///
+    /// It is added before the first item once back-pagination reaches the
+    /// start of the room, if the timeline was built with
+    /// [`TimelineBuilder::add_timeline_start()`].
+    ///
+    /// [`TimelineBuilder::add_timeline_start()`]: super::TimelineBuilder::add_timeline_start
+    TimelineStart {
+        /// The ID of the room this room was upgraded from, if any.
+        ///
+        /// Clients can use it to link to the older history of the room.
+        predecessor: Option<OwnedRoomId>,
+    },
 }
@@ -0,0 +1,160 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use eyeball_im::VectorDiff;
+use futures_util::StreamExt;
+use matrix_sdk::{config::SyncSettings, Client};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder, TimelineTestEvent};
+use matrix_sdk_ui::timeline::{RoomExt, TimelineItemContent};
+use ruma::{room_id, RoomId};
+use serde_json::json;
+use stream_assert::assert_pending;
+use tokio::time::sleep;
+use wiremock::MockServer;
+
+use crate::{logged_in_client, mock_sync};
+
+const SESSION_ID: &str = "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU";
+const SESSION_KEY: &[u8] = b"\
+    -----BEGIN MEGOLM SESSION DATA-----\n\
+    ASKcWoiAVUM97482UAi83Avce62hSLce7i5JhsqoF6xeAAAACqt2Cg3nyJPRWTTMXxXH7TXnkfdlmBXbQtq5\
+    bpHo3LRijcq2Gc6TXilESCmJN14pIsfKRJrWjZ0squ/XsoTFytuVLWwkNaW3QF6obeg2IoVtJXLMPdw3b2vO\
+    vgwGY3OMP0XafH13j1vcb6YLzvgLkZQLnYvd47hv3yK/9GmKS9tokuaQ7dCVYckYcIOS09EDTs70YdxUd5WG\
+    rQynATCLFP1p/NAGv70r9MK7Cy/mNpjD0r4qC7UEDIoi1kOWzHgnLo19wtvwsb8Fg8ATxcs3Wmtj8hIUYpDx\
+    ia4sM10zbytUuaPUAfCDf42IyxdmOnGe1CueXhgI71y+RW0s0argNqUt7jB70JT0o9CyX6UBGRaqLk2MPY9T\
+    hUu5J8X3UgIa6rcbWigzohzWm9rdbEHFrSWqjpfQYMaAKQQgETrjSy4XTrp2RhC2oNqG/hylI4ab+F4X6fpH\
+    DYP1NqNMP5g36xNu7LhDnrUB5qsPjYOmWORxGLfudpF3oLYCSlr3DgHqEIB6HjQblLZ3KQuPBse3zxyROTnS\
+    AhdPH4a/z1wioFtKNVph3hecsiKEdqnz4Y2coSIdhz58mJ9JWNQoFAENE5CSsoEZAGvafYZVpW4C75YY2zq1\
+    wIeiFi1dT43/jLAUGkslsi1VvnyfUu8qO404RxYO3XHoGLMFoFLOO+lZ+VGci2Vz10AhxJhEBHxRKxw4k2uB\
+    HztoSJUr/2Y\n\
+    -----END MEGOLM SESSION DATA-----";
+
+fn room_id() -> &'static RoomId {
+    room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost")
+}
+
+async fn sync_room(client: &Client, server: &MockServer, ev_builder: &mut SyncResponseBuilder) {
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    mock_sync(server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings).await.unwrap();
+    server.reset().await;
+}
+
+/// Add an event encrypted with the room key of [`SESSION_KEY`] to the next
+/// sync response.
+fn add_encrypted_event(ev_builder: &mut SyncResponseBuilder) {
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id()).add_timeline_event(
+        TimelineTestEvent::Custom(json!({
+            "content": {
+                "algorithm": "m.megolm.v1.aes-sha2",
+                "ciphertext": "AwgAEtABPRMavuZMDJrPo6pGQP4qVmpcuapuXtzKXJyi3YpEsjSWdzuRKIgJzD4P\
+                               cSqJM1A8kzxecTQNJsC5q22+KSFEPxPnI4ltpm7GFowSoPSW9+bFdnlfUzEP1jPq\
+                               YevHAsMJp2fRKkzQQbPordrUk1gNqEpGl4BYFeRqKl9GPdKFwy45huvQCLNNueql\
+                               CFZVoYMuhxrfyMiJJAVNTofkr2um2mKjDTlajHtr39pTG8k0eOjSXkLOSdZvNOMz\
+                               hGhSaFNeERSA2G2YbeknOvU7MvjiO0AKuxaAe1CaVhAI14FCgzrJ8g0y5nly+n7x\
+                               QzL2G2Dn8EoXM5Iqj8W99iokQoVsSrUEnaQ1WnSIfewvDDt4LCaD/w7PGETMCQ",
+                "device_id": "NLAZCWIOCO",
+                "sender_key": "DeHIg4gwhClxzFYcmNntPNF9YtsdZbmMy8+3kzCMXHA",
+                "session_id": SESSION_ID,
+            },
+            "event_id": "$encrypted:morpheus.localhost",
+            "origin_server_ts": 152037280,
+            "sender": "@bob:morpheus.localhost",
+            "type": "m.room.encrypted",
+        })),
+    ));
+}
+
+async fn import_room_keys(client: &Client, file_name: &str) {
+    let path = std::env::temp_dir().join(file_name);
+    std::fs::write(&path, SESSION_KEY).unwrap();
+
+    let result = client.encryption().import_room_keys(path.clone(), "1234").await.unwrap();
+    assert_eq!(result.imported_count, 1);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[async_test]
+async fn retry_decryption_on_room_keys() {
+    let (client, server) = logged_in_client().await;
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id()));
+    sync_room(&client, &server, &mut ev_builder).await;
+
+    let room = client.get_room(room_id()).unwrap();
+    let timeline = room.timeline().await;
+    let (_, mut timeline_stream) = timeline.subscribe().await;
+
+    add_encrypted_event(&mut ev_builder);
+    sync_room(&client, &server, &mut ev_builder).await;
+
+    let _day_divider = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    let item = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::UnableToDecrypt(_));
+
+    // The room key arrives, which replaces the undecryptable item.
+    import_room_keys(&client, "retry_decryption_on_room_keys.txt").await;
+
+    let item = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::Set { index: 1, value }) => value
+    );
+    let event = item.as_event().unwrap();
+    assert!(event.encryption_info().is_some());
+    let text = assert_matches!(event.content(), TimelineItemContent::Message(msg) => msg.body());
+    assert_eq!(text, "It's a secret to everybody");
+}
+
+#[async_test]
+async fn no_retry_decryption_on_room_keys() {
+    let (client, server) = logged_in_client().await;
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id()));
+    sync_room(&client, &server, &mut ev_builder).await;
+
+    let room = client.get_room(room_id()).unwrap();
+    let timeline = room.timeline_builder().retry_decryption_on_room_keys(false).build().await;
+    let (_, mut timeline_stream) = timeline.subscribe().await;
+
+    add_encrypted_event(&mut ev_builder);
+    sync_room(&client, &server, &mut ev_builder).await;
+
+    let _day_divider = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    let item = assert_matches!(
+        timeline_stream.next().await,
+        Some(VectorDiff::PushBack { value }) => value
+    );
+    assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::UnableToDecrypt(_));
+
+    // The room key arrives, but the timeline doesn't retry decrypting the
+    // undecryptable item on its own.
+    import_room_keys(&client, "no_retry_decryption_on_room_keys.txt").await;
+
+    sleep(Duration::from_millis(100)).await;
+    assert_pending!(timeline_stream);
+}
@@ -33,6 +33,8 @@ use wiremock::{
 };
 
 mod echo;
+#[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
+mod encryption;
 mod pagination;
 mod queue;
 mod read_receipts;
@@ -16,7 +16,7 @@ use std::{sync::Arc, time::Duration};
 
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
-use futures_util::future::join;
+use futures_util::future::{join, select, Either};
 use matrix_sdk::config::SyncSettings;
 use matrix_sdk_test::{
     async_test, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
@@ -138,6 +138,50 @@ async fn back_pagination() {
     assert_next_eq!(back_pagination_status, BackPaginationStatus::TimelineStartReached);
 }
 
+#[async_test]
+async fn back_pagination_cancelled() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = Arc::new(room.timeline().await);
+    let mut back_pagination_status = timeline.back_pagination_status();
+
+    // The server takes a long time to respond.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(&*test_json::ROOM_MESSAGES_BATCH_1)
+                .set_delay(Duration::from_secs(60)),
+        )
+        .mount(&server)
+        .await;
+
+    let paginate = Box::pin(timeline.paginate_backwards(PaginationOptions::single_request(10)));
+    let observe_paginating = Box::pin(async {
+        assert_eq!(back_pagination_status.next().await, Some(BackPaginationStatus::Paginating));
+    });
+
+    // Cancel the pagination once it started.
+    let paginate = match select(paginate, observe_paginating).await {
+        Either::Left(_) => panic!("the pagination should still be running"),
+        Either::Right((_, paginate)) => paginate,
+    };
+    drop(paginate);
+
+    assert_next_eq!(back_pagination_status, BackPaginationStatus::Idle);
+}
+
 #[async_test]
 async fn back_pagination_highlighted() {
     let room_id = room_id!("!a98sd12bjh:example.org");
@@ -12,15 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
+use futures_core::Stream;
 use futures_util::StreamExt;
 use matrix_sdk::config::SyncSettings;
 use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder, TimelineTestEvent};
-use matrix_sdk_ui::timeline::{RoomExt, TimelineItemContent};
-use ruma::{event_id, events::room::message::MessageType, room_id};
+use matrix_sdk_ui::timeline::{
+    MembershipSince, RoomExt, Timeline, TimelineItem, TimelineItemContent,
+};
+use ruma::{event_id, events::room::message::MessageType, room_id, EventId, OwnedEventId};
 use serde_json::json;
 
 use crate::{logged_in_client, mock_sync};
@@ -61,6 +64,42 @@ async fn batched() {
     tokio::time::timeout(Duration::from_millis(500), hdl).await.unwrap().unwrap();
 }
 
+#[async_test]
+async fn batched_without_day_dividers() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline_builder().day_dividers(false).build().await;
+    let (_, mut timeline_stream) = timeline.subscribe_batched().await;
+
+    let hdl = tokio::spawn(async move {
+        let next_batch = timeline_stream.next().await.unwrap();
+        // Three event items, no day divider
+        assert_eq!(next_batch.len(), 3);
+    });
+
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(TimelineTestEvent::MessageText)
+            .add_timeline_event(TimelineTestEvent::Member)
+            .add_timeline_event(TimelineTestEvent::MessageNotice),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+
+    tokio::time::timeout(Duration::from_millis(500), hdl).await.unwrap().unwrap();
+}
+
 #[async_test]
 async fn event_filter() {
     let room_id = room_id!("!a98sd12bjh:example.org");
@@ -172,3 +211,119 @@ async fn event_filter() {
     assert_eq!(text.body, "hi");
     assert!(msg.is_edited());
 }
+
+#[async_test]
+async fn since_own_join() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline =
+        room.timeline_builder().since_own_membership(MembershipSince::Join).build().await;
+    let (_, mut timeline_stream) = timeline.subscribe().await;
+
+    let join_event_id = event_id!("$join");
+    let post_join_event_id = event_id!("$post_join");
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(message_event(event_id!("$pre_join"), "before", 152037280))
+            .add_timeline_event(member_event(join_event_id, "join", None, 152037281))
+            .add_timeline_event(message_event(post_join_event_id, "after", 152037282)),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    wait_for_event(&mut timeline_stream, post_join_event_id).await;
+    assert_eq!(event_ids(&timeline).await, [join_event_id, post_join_event_id]);
+
+    // Re-joining the room hides the events from before the new join.
+    let rejoin_event_id = event_id!("$rejoin");
+    let post_rejoin_event_id = event_id!("$post_rejoin");
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(member_event(event_id!("$leave"), "leave", Some("join"), 152037283))
+            .add_timeline_event(member_event(rejoin_event_id, "join", Some("leave"), 152037284))
+            .add_timeline_event(message_event(post_rejoin_event_id, "again", 152037285)),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    wait_for_event(&mut timeline_stream, post_rejoin_event_id).await;
+    assert_eq!(event_ids(&timeline).await, [rejoin_event_id, post_rejoin_event_id]);
+}
+
+fn message_event(event_id: &EventId, body: &str, ts: u64) -> TimelineTestEvent {
+    TimelineTestEvent::Custom(json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "sender": "@bob:example.org",
+        "type": "m.room.message",
+    }))
+}
+
+fn member_event(
+    event_id: &EventId,
+    membership: &str,
+    prev_membership: Option<&str>,
+    ts: u64,
+) -> TimelineTestEvent {
+    let unsigned = match prev_membership {
+        Some(prev_membership) => json!({ "prev_content": { "membership": prev_membership } }),
+        None => json!({}),
+    };
+
+    TimelineTestEvent::Custom(json!({
+        "content": {
+            "membership": membership,
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "sender": "@example:localhost",
+        "state_key": "@example:localhost",
+        "type": "m.room.member",
+        "unsigned": unsigned,
+    }))
+}
+
+/// Wait until the event with the given ID is added to the timeline.
+async fn wait_for_event(
+    stream: &mut (impl Stream<Item = VectorDiff<Arc<TimelineItem>>> + Unpin),
+    event_id: &EventId,
+) {
+    let wait = async {
+        while let Some(diff) = stream.next().await {
+            if let VectorDiff::PushBack { value } = diff {
+                if value.as_event().and_then(|event| event.event_id()) == Some(event_id) {
+                    return;
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(Duration::from_millis(500), wait).await.unwrap();
+}
+
+async fn event_ids(timeline: &Timeline) -> Vec<OwnedEventId> {
+    timeline
+        .items()
+        .await
+        .iter()
+        .filter_map(|item| item.as_event()?.event_id().map(ToOwned::to_owned))
+        .collect()
+}
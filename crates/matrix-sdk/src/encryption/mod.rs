@@ -24,6 +24,7 @@ use std::{
 };
 
 use eyeball::SharedObservable;
+use futures_core::Stream;
 use futures_util::{
     future::try_join,
     stream::{self, StreamExt},
@@ -76,6 +77,7 @@ pub use matrix_sdk_base::crypto::{
         SessionCreationError as MegolmSessionCreationError,
         SessionExportError as OlmSessionExportError,
     },
+    store::{BroadcastStreamRecvError, RoomKeyInfo},
     vodozemac, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError, KeyExportError,
     LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
     SessionCreationError, SignatureError, VERSION,
@@ -490,6 +492,26 @@ impl Encryption {
         Some(machine.cross_signing_status().await)
     }
 
+    /// Receive notifications of room keys being received as a [`Stream`].
+    ///
+    /// Each time a room key is received, imported or restored from a backup,
+    /// an update is sent to the stream. Updates that happen at the same time
+    /// are batched into a [`Vec`]. If the reader lags too far behind, the
+    /// missed updates are replaced by a [`BroadcastStreamRecvError`].
+    ///
+    /// The stream belongs to the current `OlmMachine` of the client. It ends
+    /// when the `OlmMachine` is regenerated, which can happen when the
+    /// cross-process crypto store lock is used, callers need to subscribe
+    /// again then.
+    ///
+    /// Returns `None` if the client isn't logged in yet.
+    pub async fn room_keys_received_stream(
+        &self,
+    ) -> Option<impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>>> {
+        let olm = self.client.olm_machine().await;
+        Some(olm.as_ref()?.room_keys_received_stream())
+    }
+
     /// Get all the tracked users we know about
     ///
     /// Tracked users are users for which we keep the device list of E2EE